@@ -0,0 +1,40 @@
+"""Run the nbwipers executable installed alongside this package.
+
+This lets git filters be configured as ``python -m nbwipers``, which keeps working when the
+virtual environment holding nbwipers is moved or recreated.
+"""
+
+import os
+import sys
+import sysconfig
+
+
+def find_nbwipers_bin() -> str:
+    """Return the path of the nbwipers executable installed with this package."""
+    exe_name = "nbwipers" + (sysconfig.get_config_var("EXE") or "")
+
+    candidates = [os.path.join(sysconfig.get_path("scripts"), exe_name)]
+    if sys.version_info >= (3, 10):
+        user_scheme = sysconfig.get_preferred_scheme("user")
+    elif os.name == "nt":
+        user_scheme = "nt_user"
+    elif sys.platform == "darwin" and sys._framework:
+        user_scheme = "osx_framework_user"
+    else:
+        user_scheme = "posix_user"
+    candidates.append(os.path.join(sysconfig.get_path("scripts", scheme=user_scheme), exe_name))
+
+    for candidate in candidates:
+        if os.path.isfile(candidate):
+            return candidate
+    raise FileNotFoundError(candidates[0])
+
+
+if __name__ == "__main__":
+    nbwipers = os.fsdecode(find_nbwipers_bin())
+    if sys.platform == "win32":
+        import subprocess
+
+        sys.exit(subprocess.run([nbwipers, *sys.argv[1:]]).returncode)
+    else:
+        os.execvp(nbwipers, [nbwipers, *sys.argv[1:]])
@@ -0,0 +1,296 @@
+use std::{
+    fmt::Display,
+    io::Write,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+};
+
+use anyhow::{Context, Error, bail};
+use colored::Colorize;
+use rayon::prelude::*;
+use rustc_hash::FxHashMap;
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::{
+    cli::{AuditCommand, OutputFormat},
+    files::{FoundNotebooks, find_notebooks, get_cwd, normalize_path_to, read_nb, relativize_path},
+    schema::RawNotebook,
+    settings::Settings,
+    strip::stripped_size,
+};
+
+/// Number of outputs listed for each notebook, largest first.
+const MAX_LISTED_OUTPUTS: usize = 5;
+
+#[derive(Clone, Debug, Serialize, PartialEq, Eq)]
+pub struct OutputSize {
+    pub cell_number: usize,
+    pub mimetype: String,
+    pub size: u64,
+}
+
+#[derive(Clone, Debug, Serialize, PartialEq, Eq, Default)]
+pub struct HistorySize {
+    pub versions: u64,
+    pub size: u64,
+}
+
+#[derive(Clone, Debug, Serialize, PartialEq, Eq)]
+pub struct NotebookAudit {
+    pub path: String,
+    pub size: u64,
+    pub stripped_size: u64,
+    pub savings: u64,
+    pub largest_outputs: Vec<OutputSize>,
+    pub metadata_keys: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub history: Option<HistorySize>,
+}
+
+impl Display for NotebookAudit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "{}: {} bytes on disk, {} bytes stripped ({} bytes saved)",
+            self.path.bold(),
+            self.size,
+            self.stripped_size,
+            self.savings
+        )?;
+        for output in &self.largest_outputs {
+            writeln!(
+                f,
+                "  cell {}: {} output of {} bytes",
+                output.cell_number, output.mimetype, output.size
+            )?;
+        }
+        if !self.metadata_keys.is_empty() {
+            writeln!(f, "  metadata keys: {}", self.metadata_keys.join(", "))?;
+        }
+        if let Some(history) = &self.history {
+            writeln!(
+                f,
+                "  history: {} versions, {} bytes",
+                history.versions, history.size
+            )?;
+        }
+        Ok(())
+    }
+}
+
+fn value_size(value: &Value) -> u64 {
+    serde_json::to_vec(value).map_or(0, |v| v.len() as u64)
+}
+
+/// Size of each output of each code cell, broken down by mimetype for rich outputs.
+pub fn output_sizes(nb: &RawNotebook) -> Vec<OutputSize> {
+    let mut out = vec![];
+    for (cell_number, cell) in nb.cells.iter().enumerate() {
+        let Some(codecell) = cell.as_codecell() else {
+            continue;
+        };
+        for output in &codecell.outputs {
+            let output_type = output
+                .get("output_type")
+                .and_then(Value::as_str)
+                .unwrap_or("unknown");
+            match output.get("data").and_then(Value::as_object) {
+                Some(data) => out.extend(data.iter().map(|(mimetype, value)| OutputSize {
+                    cell_number,
+                    mimetype: mimetype.clone(),
+                    size: value_size(value),
+                })),
+                None => out.push(OutputSize {
+                    cell_number,
+                    mimetype: output_type.to_owned(),
+                    size: value_size(output),
+                }),
+            }
+        }
+    }
+    out.sort_by_key(|o| std::cmp::Reverse(o.size));
+    out
+}
+
+/// Notebook and cell metadata keys present in the notebook, in `--extra-keys` notation.
+pub fn metadata_keys(nb: &RawNotebook) -> Vec<String> {
+    let mut keys: Vec<String> = nb
+        .metadata
+        .as_object()
+        .into_iter()
+        .flat_map(|m| m.keys().map(|k| format!("metadata.{k}")))
+        .collect();
+    for cell in &nb.cells {
+        for key in cell
+            .get_metadata()
+            .as_object()
+            .into_iter()
+            .flat_map(|m| m.keys())
+        {
+            let key = format!("cell.metadata.{key}");
+            if !keys.contains(&key) {
+                keys.push(key);
+            }
+        }
+    }
+    keys
+}
+
+fn audit_notebook(nb_path: &Path, settings: &Settings) -> Result<NotebookAudit, Error> {
+    let size = std::fs::metadata(nb_path)?.len();
+    let nb = read_nb(nb_path)?;
+    let mut largest_outputs = output_sizes(&nb);
+    largest_outputs.truncate(MAX_LISTED_OUTPUTS);
+    let metadata_keys = metadata_keys(&nb);
+    let stripped_size = stripped_size(nb, settings)?;
+    Ok(NotebookAudit {
+        path: relativize_path(nb_path),
+        size,
+        stripped_size,
+        savings: size.saturating_sub(stripped_size),
+        largest_outputs,
+        metadata_keys,
+        history: None,
+    })
+}
+
+/// Total size of every version of each notebook in the history of the repository, keyed by canonical path.
+fn history_sizes() -> Result<FxHashMap<PathBuf, HistorySize>, Error> {
+    let cur_dir = std::env::current_dir()?;
+    let (git_dir, _) = gix::discover::upwards(&cur_dir)?;
+    let work_tree = git_dir
+        .into_repository_and_work_tree_directories()
+        .1
+        .context("Cannot audit the history of a bare repository")?;
+
+    let rev_list = Command::new("git")
+        .current_dir(&work_tree)
+        .args(["rev-list", "--objects", "--all"])
+        .output()?;
+    if !rev_list.status.success() {
+        bail!("Git rev-list failed");
+    }
+    let mut batch_check = Command::new("git")
+        .current_dir(&work_tree)
+        .args([
+            "cat-file",
+            "--batch-check=%(objecttype) %(objectsize) %(rest)",
+        ])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()?;
+    {
+        let mut stdin = batch_check.stdin.take().context("Could not open stdin")?;
+        stdin.write_all(&rev_list.stdout)?;
+    }
+    let output = batch_check.wait_with_output()?;
+    if !output.status.success() {
+        bail!("Git cat-file failed");
+    }
+
+    let mut sizes: FxHashMap<PathBuf, HistorySize> = FxHashMap::default();
+    for line in String::from_utf8(output.stdout)?.lines() {
+        let mut parts = line.splitn(3, ' ');
+        let (Some("blob"), Some(size), Some(path)) = (parts.next(), parts.next(), parts.next())
+        else {
+            continue;
+        };
+        if Path::new(path).extension().and_then(|e| e.to_str()) != Some("ipynb") {
+            continue;
+        }
+        let abs_path = normalize_path_to(path, &work_tree);
+        let key = abs_path.canonicalize().unwrap_or(abs_path);
+        let entry = sizes.entry(key).or_default();
+        entry.versions += 1;
+        entry.size += size.parse::<u64>()?;
+    }
+    Ok(sizes)
+}
+
+pub fn audit(cmd: AuditCommand) -> Result<(), Error> {
+    let path = cmd.path.unwrap_or_else(get_cwd);
+    let (args, overrides) = cmd.common.partition();
+    let settings = Settings::construct(args.config.as_deref(), args.isolated, &overrides)?;
+
+    let files = match find_notebooks(&[&path], &settings)? {
+        FoundNotebooks::Files(files) => files,
+        FoundNotebooks::NoFiles if args.allow_no_notebooks => return Ok(()),
+        _ => bail!("Could not find any notebooks in path(s)"),
+    };
+    let mut audits: Vec<(PathBuf, NotebookAudit)> = files
+        .into_par_iter()
+        .filter_map(|nb_path| match audit_notebook(&nb_path, &settings) {
+            Ok(audit) => Some((nb_path, audit)),
+            Err(_) => {
+                eprintln!("Could not parse nb file {}", nb_path.to_string_lossy());
+                None
+            }
+        })
+        .collect();
+
+    if cmd.git_history {
+        let mut sizes = history_sizes()?;
+        for (nb_path, audit) in &mut audits {
+            let key = nb_path.canonicalize().unwrap_or_else(|_| nb_path.clone());
+            audit.history = Some(sizes.remove(&key).unwrap_or_default());
+        }
+    }
+    audits.sort_by(|(_, a), (_, b)| b.savings.cmp(&a.savings).then(a.path.cmp(&b.path)));
+    let audits: Vec<NotebookAudit> = audits.into_iter().map(|(_, audit)| audit).collect();
+
+    match cmd.output_format.unwrap_or_default() {
+        OutputFormat::Text => {
+            for audit in &audits {
+                print!("{audit}");
+            }
+        }
+        OutputFormat::Json => print!("{}", serde_json::to_string_pretty(&audits)?),
+    }
+    Ok(())
+}
+
+#[allow(clippy::unwrap_used)]
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_output_sizes_and_metadata_keys() {
+        let nb: RawNotebook = serde_json::from_value(json!({
+            "cells": [
+                {
+                    "cell_type": "code",
+                    "execution_count": 1,
+                    "metadata": {"scrolled": true},
+                    "outputs": [
+                        {"name": "stdout", "output_type": "stream", "text": ["hi\n"]},
+                        {
+                            "data": {"image/png": "aGVsbG8gd29ybGQ=", "text/plain": ["<Figure>"]},
+                            "metadata": {},
+                            "output_type": "display_data"
+                        }
+                    ],
+                    "source": []
+                }
+            ],
+            "metadata": {"kernelspec": {}},
+            "nbformat": 4,
+            "nbformat_minor": 5
+        }))
+        .unwrap();
+
+        let sizes = output_sizes(&nb);
+        assert_eq!(sizes.len(), 3);
+        assert_eq!(sizes[0].mimetype, "stream");
+        assert!(sizes.iter().any(|s| s.mimetype == "image/png"));
+        assert!(sizes.windows(2).all(|w| w[0].size >= w[1].size));
+
+        assert_eq!(
+            metadata_keys(&nb),
+            vec!["metadata.kernelspec", "cell.metadata.scrolled"]
+        );
+    }
+}
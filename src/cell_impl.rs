@@ -1,7 +1,24 @@
 use rustc_hash::FxHashSet;
 use serde_json::Value;
 
-use crate::schema::{Cell, CodeCell, SourceValue};
+use crate::{
+    schema::{Cell, CodeCell, SourceValue},
+    settings::Settings,
+};
+
+/// The tags in `metadata.tags` of a cell.
+fn tags(metadata: &Value) -> impl Iterator<Item = &str> {
+    metadata
+        .get("tags")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter_map(Value::as_str)
+}
+
+fn has_tag_in(metadata: &Value, tag_set: &FxHashSet<String>) -> bool {
+    !tag_set.is_empty() && tags(metadata).any(|tag| tag_set.contains(tag))
+}
 
 impl SourceValue {
     fn is_empty(&self) -> bool {
@@ -40,28 +57,29 @@ impl CodeCell {
                 x.insert("execution_count".into(), Value::Null);
             });
     }
-    pub fn should_clear_output(&self, drop_output: bool, strip_init_cell: bool) -> bool {
-        // drop_output
+    pub fn should_clear_output(&self, settings: &Settings) -> bool {
+        let drop_output = settings.drop_output;
         let Some(cell_metadata) = self.metadata.as_object() else {
             return drop_output;
         };
+        if has_tag_in(&self.metadata, &settings.drop_output_tags) {
+            return true;
+        }
         if let Some(init_cell) = cell_metadata.get("init_cell") {
-            return !init_cell.as_bool().unwrap_or(false) || strip_init_cell;
+            return !init_cell.as_bool().unwrap_or(false) || settings.strip_init_cell;
         };
 
         if drop_output {
             let keep_output_metadata = cell_metadata.contains_key("keep_output");
-            let keep_output_tags = self
-                .metadata
-                .as_object()
-                .and_then(|x| x.get("tags"))
-                .and_then(|x| x.as_array())
-                .is_some_and(|x| x.iter().any(|s| s.as_str() == Some("keep_output")));
+            let keep_output_tags = has_tag_in(&self.metadata, &settings.keep_output_tags);
             !(keep_output_metadata || keep_output_tags)
         } else {
             false
         }
     }
+    pub fn should_clear_count(&self, settings: &Settings) -> bool {
+        settings.drop_count && !has_tag_in(&self.metadata, &settings.keep_count_tags)
+    }
 }
 
 impl Cell {
@@ -92,6 +110,14 @@ impl Cell {
         }
     }
 
+    pub fn get_source_mut(&mut self) -> &mut SourceValue {
+        match self {
+            Self::Code(c) => &mut c.source,
+            Self::Markdown(c) => &mut c.source,
+            Self::Raw(c) => &mut c.source,
+        }
+    }
+
     pub const fn get_metadata(&self) -> &Value {
         match self {
             Self::Code(c) => &c.metadata,
@@ -127,28 +153,27 @@ impl Cell {
         prev_id
     }
 
-    pub fn should_drop(
-        &self,
-        drop_empty_cells: bool,
-        drop_tagged_cells: &FxHashSet<String>,
-    ) -> bool {
-        if drop_empty_cells && self.get_source().is_empty() {
+    /// The tags of the cell in `metadata.tags`.
+    pub fn get_tags(&self) -> impl Iterator<Item = &str> {
+        tags(self.get_metadata())
+    }
+
+    /// Whether the cell has a tag in `clear-source-tags` and source to clear.
+    pub fn should_clear_source(&self, settings: &Settings) -> bool {
+        has_tag_in(self.get_metadata(), &settings.clear_source_tags)
+            && !self.get_source().is_empty()
+    }
+    pub fn clear_source(&mut self) {
+        *self.get_source_mut() = SourceValue::StringArray(vec![]);
+    }
+
+    /// Whether cleaning drops the cell. A cell whose source cleaning clears, as given by
+    /// `clears_source`, counts as empty, so that a single pass both clears and drops it.
+    pub fn should_drop(&self, settings: &Settings, clears_source: bool) -> bool {
+        if settings.drop_empty_cells && (clears_source || self.get_source().is_empty()) {
             return true;
         }
-        if drop_tagged_cells.is_empty() {
-            return false;
-        }
-        let tags = self
-            .get_metadata()
-            .as_object()
-            .and_then(|x| x.get("tags"))
-            .and_then(|x| x.as_array());
-
-        tags.is_some_and(|tags| {
-            tags.iter()
-                .filter_map(|v| v.as_str())
-                .any(|s| drop_tagged_cells.contains(s))
-        })
+        has_tag_in(self.get_metadata(), &settings.drop_tagged_cells)
     }
 }
 
@@ -157,6 +182,7 @@ mod tests {
     use serde_json::json;
 
     use super::*;
+    use crate::config::Configuration;
 
     #[test]
     fn test_empty_single_string() {
@@ -173,7 +199,40 @@ mod tests {
             outputs: vec![],
             source: SourceValue::StringArray(vec![]),
         };
-        assert!(cell.should_clear_output(true, true));
-        assert!(!cell.should_clear_output(false, true));
+        let settings = Configuration::default().into_settings().unwrap();
+        assert!(cell.should_clear_output(&settings));
+        let settings = Configuration {
+            drop_output: Some(false),
+            ..Configuration::default()
+        }
+        .into_settings()
+        .unwrap();
+        assert!(!cell.should_clear_output(&settings));
+    }
+
+    #[test]
+    fn test_tag_vocabulary() {
+        let cell = CodeCell {
+            execution_count: Some(1),
+            id: None,
+            metadata: json!({"tags": ["solution", "keep"]}),
+            outputs: vec![],
+            source: SourceValue::String("x = 1".into()),
+        };
+        let settings = Configuration {
+            drop_output: Some(false),
+            drop_output_tags: Some(vec!["solution".into()]),
+            keep_count_tags: Some(vec!["keep".into()]),
+            clear_source_tags: Some(vec!["solution".into()]),
+            ..Configuration::default()
+        }
+        .into_settings()
+        .unwrap();
+        assert!(cell.should_clear_output(&settings));
+        assert!(!cell.should_clear_count(&settings));
+        let mut cell = Cell::Code(cell);
+        assert!(cell.should_clear_source(&settings));
+        cell.clear_source();
+        assert!(!cell.should_clear_source(&settings));
     }
 }
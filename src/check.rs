@@ -4,10 +4,15 @@ use crate::{
     config::IdAction,
     extra_keys::partition_extra_keys,
     files::{NBReadError, relativize_path},
+    record::extract_kernel_info,
+    rules::{Rule, Severity},
     schema::{ID_OPTIONAL_MAX_VERSION, RawNotebook},
     settings::Settings,
+    span::{Span, SpanIndex},
+    suppress::Suppressions,
     utils::get_value_child,
 };
+use semver::Version;
 use serde::{Serialize, Serializer};
 use serde_json::Value;
 
@@ -35,6 +40,9 @@ pub enum CheckResult {
     ClearId {
         cell_number: usize,
     },
+    ClearSource {
+        cell_number: usize,
+    },
     CellStripMeta {
         cell_number: usize,
         extra_key: String,
@@ -42,6 +50,18 @@ pub enum CheckResult {
     DowngradeNBFormat {
         nbformat_minor: i64,
     },
+    DisallowedKernel {
+        kernel: String,
+    },
+    WrongLanguage {
+        language: String,
+        required: String,
+    },
+    WrongLanguageVersion {
+        version: String,
+        required: String,
+    },
+    ReplaceKernelspec,
 }
 impl From<NBReadError> for CheckResult {
     fn from(value: NBReadError) -> Self {
@@ -69,6 +89,29 @@ pub struct PathCheckResult<'a> {
     pub path: &'a Path,
     #[serde(flatten)]
     pub result: &'a CheckResult,
+    pub severity: Severity,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub span: Option<Span>,
+}
+
+/// A check result with where it was found in the text of the notebook, if that is known.
+#[derive(Clone, Debug, Serialize, PartialEq, Eq)]
+pub struct LocatedCheckResult {
+    #[serde(flatten)]
+    pub result: CheckResult,
+    pub severity: Severity,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub span: Option<Span>,
+}
+
+impl From<CheckResult> for LocatedCheckResult {
+    fn from(result: CheckResult) -> Self {
+        Self {
+            result,
+            severity: Severity::default(),
+            span: None,
+        }
+    }
 }
 
 impl Display for CheckResult {
@@ -96,23 +139,206 @@ impl Display for CheckResult {
             Self::ClearOutput { cell_number } => {
                 write!(f, "cell {cell_number}: Found cell with output")
             }
+            Self::ClearSource { cell_number } => {
+                write!(
+                    f,
+                    "cell {cell_number}: Found tagged cell with source to clear"
+                )
+            }
             Self::DowngradeNBFormat { nbformat_minor } => {
                 write!(
                     f,
                     "nbformat_minor version {nbformat_minor} to be downgraded."
                 )
             }
+            Self::DisallowedKernel { kernel } => {
+                write!(f, "Kernel {kernel:?} is not an allowed kernel")
+            }
+            Self::WrongLanguage { language, required } => {
+                write!(f, "Language {language:?} is not the required {required:?}")
+            }
+            Self::WrongLanguageVersion { version, required } => {
+                write!(f, "Language version {version} does not match {required}")
+            }
+            Self::ReplaceKernelspec => {
+                write!(
+                    f,
+                    "Found kernelspec to be replaced by the canonical kernelspec"
+                )
+            }
+        }
+    }
+}
+
+impl CheckResult {
+    /// The rule that this result is a finding of.
+    pub const fn rule(&self) -> Rule {
+        match self {
+            Self::IOError { .. } => Rule::IOError,
+            Self::InvalidNotebook { .. } => Rule::InvalidNotebook,
+            Self::StripMeta { .. } => Rule::NotebookMetadata,
+            Self::DropCells { .. } => Rule::DroppedCell,
+            Self::ClearOutput { .. } => Rule::OutputPresent,
+            Self::ClearCount { .. } => Rule::ExecutionCount,
+            Self::ClearId { .. } => Rule::CellId,
+            Self::ClearSource { .. } => Rule::TaggedSource,
+            Self::CellStripMeta { .. } => Rule::CellMetadata,
+            Self::DowngradeNBFormat { .. } => Rule::NbformatDowngrade,
+            Self::DisallowedKernel { .. } => Rule::DisallowedKernel,
+            Self::WrongLanguage { .. } => Rule::WrongLanguage,
+            Self::WrongLanguageVersion { .. } => Rule::WrongLanguageVersion,
+            Self::ReplaceKernelspec => Rule::NonCanonicalKernelspec,
         }
     }
 }
 
+impl CheckResult {
+    /// The number of the cell that this result is about, if it is about a cell.
+    pub const fn cell_number(&self) -> Option<usize> {
+        match self {
+            Self::DropCells { cell_number }
+            | Self::ClearOutput { cell_number }
+            | Self::ClearCount { cell_number }
+            | Self::ClearId { cell_number }
+            | Self::ClearSource { cell_number }
+            | Self::CellStripMeta { cell_number, .. } => Some(*cell_number),
+            _ => None,
+        }
+    }
+
+    /// The path of the JSON value in the notebook that this result is about, if there is one.
+    fn json_path(&self) -> Option<Vec<String>> {
+        let cell_path = |cell_number: &usize, rest: &[&str]| {
+            ["cells", &cell_number.to_string()]
+                .iter()
+                .chain(rest)
+                .map(ToString::to_string)
+                .collect()
+        };
+        let path = match self {
+            Self::IOError { .. } | Self::InvalidNotebook { .. } => return None,
+            Self::StripMeta { extra_key } => extra_key.split('.').map(str::to_string).collect(),
+            Self::DropCells { cell_number } => cell_path(cell_number, &[]),
+            Self::ClearOutput { cell_number } => cell_path(cell_number, &["outputs"]),
+            Self::ClearCount { cell_number } => cell_path(cell_number, &["execution_count"]),
+            Self::ClearId { cell_number } => cell_path(cell_number, &["id"]),
+            Self::ClearSource { cell_number } => cell_path(cell_number, &["source"]),
+            Self::CellStripMeta {
+                cell_number,
+                extra_key,
+            } => cell_path(
+                cell_number,
+                &extra_key
+                    .strip_prefix("cell.")
+                    .unwrap_or(extra_key)
+                    .split('.')
+                    .collect::<Vec<_>>(),
+            ),
+            Self::DowngradeNBFormat { .. } => vec!["nbformat_minor".to_string()],
+            Self::DisallowedKernel { .. } => vec![
+                "metadata".to_string(),
+                "kernelspec".to_string(),
+                "name".to_string(),
+            ],
+            Self::WrongLanguage { .. } => vec![
+                "metadata".to_string(),
+                "language_info".to_string(),
+                "name".to_string(),
+            ],
+            Self::WrongLanguageVersion { .. } => vec![
+                "metadata".to_string(),
+                "language_info".to_string(),
+                "version".to_string(),
+            ],
+            Self::ReplaceKernelspec => vec!["metadata".to_string(), "kernelspec".to_string()],
+        };
+        Some(path)
+    }
+}
+
+/// Check `nb`, keeping the findings of the rules selected by `settings` with their severity,
+/// and locate each of them in `text`, the text of the notebook.
+pub fn check_located(nb: &RawNotebook, text: &str, settings: &Settings) -> Vec<LocatedCheckResult> {
+    let index = SpanIndex::new(text);
+    check_nb(nb, settings)
+        .into_iter()
+        .filter_map(|result| {
+            let severity = settings.severity(result.rule())?;
+            let span = result
+                .json_path()
+                .and_then(|path| index.nearest_span(&path));
+            Some(LocatedCheckResult {
+                result,
+                severity,
+                span,
+            })
+        })
+        .collect()
+}
+
+/// Parse a version such as `3.12.4`, `3.12` or `3.13.0rc1`, ignoring any pre-release suffix.
+fn parse_language_version(version: &str) -> Option<Version> {
+    let mut parts = version.split('.').map(|part| {
+        let digits = part
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(part.len());
+        part[..digits].parse::<u64>().ok()
+    });
+    let major = parts.next()??;
+    let minor = parts.next().unwrap_or(Some(0))?;
+    let patch = parts.next().unwrap_or(Some(0)).unwrap_or(0);
+    Some(Version::new(major, minor, patch))
+}
+
+/// Check the kernelspec and language of the notebook against the kernel policy in `settings`.
+fn check_kernel_policy(nb: &RawNotebook, settings: &Settings) -> Vec<CheckResult> {
+    let mut out = vec![];
+    let kernel_info = extract_kernel_info(nb);
+    let kernel = kernel_info
+        .as_ref()
+        .and_then(|k| k.kernelspec.get("name"))
+        .and_then(Value::as_str);
+    if let Some(canonical) = &settings.canonical_kernelspec
+        && nb.metadata.get("kernelspec") != Some(canonical)
+    {
+        out.push(CheckResult::ReplaceKernelspec);
+    }
+    if let Some(allowed) = &settings.allowed_kernels
+        && let Some(kernel) = kernel
+        && !allowed.iter().any(|k| k == kernel)
+    {
+        out.push(CheckResult::DisallowedKernel {
+            kernel: kernel.to_owned(),
+        });
+    }
+    let language = get_value_child(&nb.metadata, &["language_info", "name"])
+        .or_else(|| get_value_child(&nb.metadata, &["kernelspec", "language"]))
+        .and_then(Value::as_str);
+    if let Some(required) = &settings.required_language
+        && let Some(language) = language
+        && !language.eq_ignore_ascii_case(required)
+    {
+        out.push(CheckResult::WrongLanguage {
+            language: language.to_owned(),
+            required: required.clone(),
+        });
+    }
+    if let Some(required) = &settings.required_language_version_
+        && let Some(version) = kernel_info.and_then(|k| k.python_version)
+        && !parse_language_version(&version).is_some_and(|v| required.matches(&v))
+    {
+        out.push(CheckResult::WrongLanguageVersion {
+            version,
+            required: required.to_string(),
+        });
+    }
+    out
+}
+
 pub fn check_nb(nb: &RawNotebook, settings: &Settings) -> Vec<CheckResult> {
     let (cell_keys, meta_keys) = partition_extra_keys(&settings.extra_keys);
+    let suppressions = Suppressions::new(nb);
     let mut out = vec![];
-    let nb_keep_output = get_value_child(&nb.metadata, &["keep_output"])
-        .and_then(Value::as_bool)
-        .unwrap_or(false);
-    let drop_output = settings.drop_output && !nb_keep_output;
     let mut downgrade_nbformat = false;
 
     meta_keys
@@ -127,28 +353,30 @@ pub fn check_nb(nb: &RawNotebook, settings: &Settings) -> Vec<CheckResult> {
     nb.cells
         .iter()
         .enumerate()
-        .filter(|(_i, c)| c.should_drop(settings.drop_empty_cells, &settings.drop_tagged_cells))
+        .filter(|(_i, c)| {
+            let clears_source =
+                c.should_clear_source(settings) && !suppressions.cell(c, Rule::TaggedSource);
+            c.should_drop(settings, clears_source)
+        })
         .for_each(|(cell_number, _c)| out.push(CheckResult::DropCells { cell_number }));
 
-    if drop_output {
-        nb.cells
-            .iter()
-            .enumerate()
-            .filter_map(|(i, c)| c.as_codecell().map(|c| (i, c)))
-            .filter(|(_i, c)| {
-                !c.is_clear_outputs()
-                    && c.should_clear_output(drop_output, settings.strip_init_cell)
-            })
-            .for_each(|(cell_number, _)| out.push(CheckResult::ClearOutput { cell_number }));
-    }
-    if settings.drop_count {
-        nb.cells
-            .iter()
-            .enumerate()
-            .filter_map(|(i, c)| c.as_codecell().map(|c| (i, c)))
-            .filter(|(_i, c)| !c.is_clear_exec_count())
-            .for_each(|(cell_number, _)| out.push(CheckResult::ClearCount { cell_number }));
-    }
+    nb.cells
+        .iter()
+        .enumerate()
+        .filter_map(|(i, c)| c.as_codecell().map(|c| (i, c)))
+        .filter(|(_i, c)| !c.is_clear_outputs() && c.should_clear_output(settings))
+        .for_each(|(cell_number, _)| out.push(CheckResult::ClearOutput { cell_number }));
+    nb.cells
+        .iter()
+        .enumerate()
+        .filter_map(|(i, c)| c.as_codecell().map(|c| (i, c)))
+        .filter(|(_i, c)| !c.is_clear_exec_count() && c.should_clear_count(settings))
+        .for_each(|(cell_number, _)| out.push(CheckResult::ClearCount { cell_number }));
+    nb.cells
+        .iter()
+        .enumerate()
+        .filter(|(_i, c)| c.should_clear_source(settings))
+        .for_each(|(cell_number, _)| out.push(CheckResult::ClearSource { cell_number }));
     match settings.id_action {
         IdAction::Sequential => {
             nb.cells
@@ -158,16 +386,21 @@ pub fn check_nb(nb: &RawNotebook, settings: &Settings) -> Vec<CheckResult> {
                 .for_each(|(cell_number, _)| out.push(CheckResult::ClearId { cell_number }));
         }
         IdAction::Drop => {
-            for (cell_number, _) in nb
+            let mut ids_kept = false;
+            for (cell_number, cell) in nb
                 .cells
                 .iter()
                 .enumerate()
                 .filter(|(_, c)| c.get_id().is_some())
             {
-                downgrade_nbformat = true;
-                out.push(CheckResult::ClearId { cell_number });
+                if suppressions.cell(cell, Rule::CellId) {
+                    ids_kept = true;
+                } else {
+                    downgrade_nbformat = true;
+                    out.push(CheckResult::ClearId { cell_number });
+                }
             }
-            // .for_each(|(cell_number, _)| out.push(CheckResult::ClearId { cell_number }));
+            downgrade_nbformat &= !ids_kept;
         }
         IdAction::Keep => {}
     }
@@ -187,7 +420,9 @@ pub fn check_nb(nb: &RawNotebook, settings: &Settings) -> Vec<CheckResult> {
             nbformat_minor: nb.nbformat_minor,
         })
     }
+    out.extend(check_kernel_policy(nb, settings));
 
+    out.retain(|result| !suppressions.result(nb, result));
     out
 }
 
@@ -207,4 +442,17 @@ mod tests {
         let displayed = check_result.to_string();
         assert!(displayed.starts_with("IO Error"));
     }
+    #[test]
+    fn test_parse_language_version() {
+        assert_eq!(
+            parse_language_version("3.12.4"),
+            Some(Version::new(3, 12, 4))
+        );
+        assert_eq!(parse_language_version("3.12"), Some(Version::new(3, 12, 0)));
+        assert_eq!(
+            parse_language_version("3.13.0rc1"),
+            Some(Version::new(3, 13, 0))
+        );
+        assert_eq!(parse_language_version("latest"), None);
+    }
 }
@@ -4,10 +4,12 @@ use clap::{
     Parser, Subcommand, ValueEnum,
     builder::{Styles, styling::AnsiColor},
 };
+use serde::Serialize;
 
 use crate::{
-    config::{Configuration, FilePattern, IdAction},
+    config::{Configuration, FilePattern, IdAction, parse_tag},
     extra_keys::ExtraKey,
+    rules::RuleSelector,
 };
 const STYLES: Styles = Styles::styled()
     .header(AnsiColor::Yellow.on_default())
@@ -111,10 +113,30 @@ pub struct CommonArgs {
     #[arg(long, overrides_with("strip_kernel_info"), hide = true)]
     pub keep_kernel_info: bool,
 
+    /// name of the profile to use, from `[tool.nbwipers.profiles.<name>]` or the built-in `student` profile. Its settings replace those of the rest of the configuration file
+    #[arg(long)]
+    pub profile: Option<String>,
+
     /// comma-separated list of tags that will cause the cell to be dropped
-    #[arg(long, value_delimiter = ',')]
+    #[arg(long, value_delimiter = ',', value_parser = parse_tag)]
     pub drop_tagged_cells: Option<Vec<String>>,
 
+    /// comma-separated list of tags that keep the output of a cell. Defaults to `keep_output`
+    #[arg(long, value_delimiter = ',', value_parser = parse_tag)]
+    pub keep_output_tags: Option<Vec<String>>,
+
+    /// comma-separated list of tags that clear the output of a cell, even with `--keep-output`
+    #[arg(long, value_delimiter = ',', value_parser = parse_tag)]
+    pub drop_output_tags: Option<Vec<String>>,
+
+    /// comma-separated list of tags that keep the execution count of a cell
+    #[arg(long, value_delimiter = ',', value_parser = parse_tag)]
+    pub keep_count_tags: Option<Vec<String>>,
+
+    /// comma-separated list of tags that clear the source of a cell, such as the solutions in teaching notebooks
+    #[arg(long, value_delimiter = ',', value_parser = parse_tag)]
+    pub clear_source_tags: Option<Vec<String>>,
+
     /// List of metadata keys that should be kept, even if they would otherwise be removed by `--extra-keys` or the default set of stripped keys
     #[arg(long, value_delimiter = ',')]
     pub keep_keys: Option<Vec<ExtraKey>>,
@@ -124,6 +146,39 @@ pub struct CommonArgs {
     /// List of additional file patterns to ignore, on top of `exclude` and any `extend-exclude` patterns set in the configuration file
     #[arg(long, value_delimiter = ',')]
     pub extend_exclude: Option<Vec<FilePattern>>,
+    /// comma-separated list of allowed values of `metadata.kernelspec.name`. `check` reports notebooks using any other kernel
+    #[arg(long, value_delimiter = ',')]
+    pub allowed_kernels: Option<Vec<String>>,
+    /// Language that notebooks must use, according to `metadata.language_info.name`. `check` reports notebooks using any other language
+    #[arg(long)]
+    pub required_language: Option<String>,
+    /// Version requirement for `metadata.language_info.version`, such as `>=3.10, <3.13`. `check` reports notebooks whose version does not match
+    #[arg(long)]
+    pub required_language_version: Option<String>,
+    /// metadata keys that `record` stores locally so that `smudge` can restore them after checkout, separated by commas. Must start with `metadata` or `cell.metadata`
+    #[arg(long, value_delimiter = ',')]
+    pub record_keys: Option<Vec<ExtraKey>>,
+    /// store cell outputs locally when the git filter strips them, and restore them when notebooks are checked out. Disable with `--no-cache-outputs`
+    #[arg(long, overrides_with("no_cache_outputs"))]
+    pub cache_outputs: bool,
+
+    #[arg(long, overrides_with("cache_outputs"), hide = true)]
+    pub no_cache_outputs: bool,
+    /// when the git filter strips kernel info because of `--strip-kernel-info`, record it locally as `record` would, so that checkout restores it. Disable with `--no-record-on-clean`
+    #[arg(long, overrides_with("no_record_on_clean"))]
+    pub record_on_clean: bool,
+
+    #[arg(long, overrides_with("record_on_clean"), hide = true)]
+    pub no_record_on_clean: bool,
+    /// comma-separated list of rule codes or code prefixes, such as `NBW001` or `NBW0`, that `check` reports as errors. Use `ALL` for every rule, which is the default
+    #[arg(long, value_delimiter = ',')]
+    pub select: Option<Vec<RuleSelector>>,
+    /// comma-separated list of rule codes or code prefixes that `check` does not report. Takes precedence over `--select` and `--warn`
+    #[arg(long, value_delimiter = ',')]
+    pub ignore: Option<Vec<RuleSelector>>,
+    /// comma-separated list of rule codes or code prefixes that `check` reports as warnings, which do not make it fail
+    #[arg(long, value_delimiter = ',')]
+    pub warn: Option<Vec<RuleSelector>>,
 }
 
 #[derive(Subcommand, Debug, Clone)]
@@ -150,12 +205,65 @@ pub enum Commands {
     /// Commands for pre-commit hooks
     #[command(subcommand)]
     Hook(HookCommands),
+    /// Rewrite git history so that every committed notebook is cleaned
+    RewriteHistory(RewriteHistoryCommand),
+    /// Report the size of notebooks and how much cleaning them would save
+    Audit(AuditCommand),
+    /// Manage the local cache of cell outputs kept when `cache-outputs` is enabled
+    #[command(subcommand)]
+    Cache(CacheCommands),
+    /// Replace an nbstripout git filter with nbwipers, keeping nbstripout's settings
+    MigrateFromNbstripout(MigrateCommand),
+    /// Run a language server over stdio that shows the findings of `check` in editors
+    Server(ServerCommand),
+    /// Write derived versions of notebooks, such as student copies of solution notebooks, to a directory
+    Derive(DeriveCommand),
+    /// Explain which rule and setting make each finding of `check` in a notebook, and where the setting comes from
+    Explain(ExplainCommand),
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum CacheCommands {
+    /// Remove cached outputs that are old and no longer match any cell in the working tree
+    Gc(CacheGcCommand),
+    /// Remove all cached outputs
+    Clear(CacheClearCommand),
+}
+
+#[derive(Clone, Debug, Parser)]
+pub struct CacheGcCommand {
+    /// path to search for notebooks whose cached outputs should be kept. Defaults to the current directory
+    pub path: Option<PathBuf>,
+    /// keep entries that were stored or refreshed within this many days, even if no cell uses them
+    #[arg(long, default_value_t = 30)]
+    pub max_age_days: u64,
+    /// path to pyproject.toml/.nbwipers.toml/nbwipers.toml file containing nbwipers settings. If not given use the file in the current working directory or the first such file in its containing folders.
+    #[arg(long, short)]
+    pub config: Option<PathBuf>,
+
+    /// Ignore all configuration files.
+    #[arg(long, conflicts_with = "config")]
+    pub isolated: bool,
+}
+
+#[derive(Clone, Debug, Parser)]
+pub struct CacheClearCommand {
+    /// path inside the repository whose cache should be cleared. Defaults to the current directory
+    pub path: Option<PathBuf>,
 }
 
 #[derive(Subcommand, Debug, Clone)]
 pub enum HookCommands {
     /// Check for large files, but measure ipynb sizes after cleaning
     CheckLargeFiles(CheckLargeFilesCommand),
+    /// Check notebooks for merge conflict markers in the JSON or in cell sources
+    CheckMergeConflict(CheckMergeConflictCommand),
+    /// Check that notebooks are valid JSON that follows the notebook schema
+    CheckJson(CheckJsonCommand),
+    /// Check that notebooks only use allowed kernels
+    CheckKernel(CheckKernelCommand),
+    /// Check that nbwipers or nbstripout is set up as a git filter, as `check-install` does
+    CheckFilterInstalled(CheckFilterInstalledCommand),
 }
 
 #[derive(Clone, Debug, Parser)]
@@ -168,6 +276,9 @@ pub struct CheckLargeFilesCommand {
     /// Max size in KB to consider a file large
     #[arg(long("maxkb"))]
     pub maxkb: Option<u64>,
+    /// Additional git filters that store files outside the repository like git-lfs. Files using these filters are skipped. `lfs` and filters whose command runs git-lfs are always skipped
+    #[arg(long, value_delimiter = ',')]
+    pub lfs_filters: Vec<String>,
     /// path to pyproject.toml/.nbwipers.toml/nbwipers.toml file containing nbwipers settings. If not given use the file in the current working directory or the first such file in its containing folders.
     #[arg(long, short)]
     pub config: Option<PathBuf>,
@@ -177,6 +288,33 @@ pub struct CheckLargeFilesCommand {
     pub isolated: bool,
 }
 
+#[derive(Clone, Debug, Parser)]
+pub struct CheckMergeConflictCommand {
+    /// Notebook files to check for merge conflict markers.
+    pub filenames: Vec<PathBuf>,
+}
+
+#[derive(Clone, Debug, Parser)]
+pub struct CheckJsonCommand {
+    /// Notebook files to validate.
+    pub filenames: Vec<PathBuf>,
+}
+
+#[derive(Clone, Debug, Parser)]
+pub struct CheckKernelCommand {
+    /// Notebook files whose kernel should be checked.
+    pub filenames: Vec<PathBuf>,
+    /// comma-separated list of allowed values of `metadata.kernelspec.name`
+    #[arg(long, value_delimiter = ',', required = true)]
+    pub allowed_kernels: Vec<String>,
+}
+
+#[derive(Clone, Debug, Parser)]
+pub struct CheckFilterInstalledCommand {
+    /// Files being committed. If files are given but none of them are notebooks, the check passes.
+    pub filenames: Vec<PathBuf>,
+}
+
 #[derive(Clone, Debug, Parser)]
 pub struct ShowConfigCommand {
     /// Show all config including defaults. Disable with `--no-show-defaults`
@@ -185,6 +323,10 @@ pub struct ShowConfigCommand {
 
     #[arg(long, overrides_with("show_all"), hide = true)]
     pub no_show_defaults: bool,
+
+    /// Show every setting in effect with where it comes from: a default, a configuration file, a profile or the command line
+    #[arg(long)]
+    pub explain: bool,
     #[clap(flatten)]
     pub common: CommonArgs,
 }
@@ -195,7 +337,7 @@ pub struct CheckCommand {
 
     /// desired output format for diagnostics
     #[arg(long, short)]
-    pub output_format: Option<OutputFormat>,
+    pub output_format: Option<CheckOutputFormat>,
 
     /// Name of file if stdin is used
     #[arg(long)]
@@ -249,6 +391,24 @@ pub enum OutputFormat {
     /// machine-readable JSON diagnostics
     Json,
 }
+
+/// The output formats of `check`, which can also report to code scanning and CI tools.
+#[derive(Clone, Debug, ValueEnum, Copy, Default)]
+pub enum CheckOutputFormat {
+    /// human-readable plain text diagnostics
+    #[default]
+    Text,
+    /// machine-readable JSON diagnostics
+    Json,
+    /// SARIF 2.1.0 log for code scanning tools
+    Sarif,
+    /// GitHub Actions workflow commands that annotate each notebook
+    Github,
+    /// GitLab Code Quality report
+    Gitlab,
+    /// JUnit XML report with a test case for each notebook
+    Junit,
+}
 #[derive(Clone, Debug, Parser)]
 pub struct InstallCommand {
     /// Git config type that determines which file to modify
@@ -262,7 +422,53 @@ pub struct InstallCommand {
     /// optional attribute file. If not specified, will write to .git/info/attributes
     #[arg(long, short)]
     pub attribute_file: Option<PathBuf>,
+
+    /// how the git filter should run nbwipers. Defaults to `path` with `--project` and `absolute` otherwise
+    #[arg(long, value_enum)]
+    pub command: Option<FilterCommand>,
+
+    /// write the attributes to the `.gitattributes` file at the root of the repository, so they can be committed and shared
+    #[arg(long, conflicts_with = "attribute_file")]
+    pub project: bool,
+}
+
+#[derive(Clone, Debug, ValueEnum, Copy, PartialEq, Eq)]
+pub enum FilterCommand {
+    /// the absolute path of the nbwipers executable running the install
+    Absolute,
+    /// `nbwipers`, looked up on PATH whenever git runs the filter
+    Path,
+    /// `python -m nbwipers`, using the python on PATH whenever git runs the filter
+    Python,
 }
+
+#[derive(Clone, Debug, Parser)]
+pub struct MigrateCommand {
+    /// Git config type where nbstripout is installed
+    #[clap(value_enum)]
+    pub config_type: GitConfigType,
+
+    /// Optional path to git config file
+    #[arg(long, short)]
+    pub git_config_file: Option<PathBuf>,
+
+    /// optional attribute file to migrate. If not specified, migrates the attribute file for the config type, and for `local` also the `.gitattributes` file at the root of the repository
+    #[arg(long, short)]
+    pub attribute_file: Option<PathBuf>,
+
+    /// nbwipers configuration file to write nbstripout's settings to. If not given, use the configuration file found from the current directory, or `.nbwipers.toml` at the root of the repository
+    #[arg(long, short)]
+    pub config: Option<PathBuf>,
+
+    /// how the git filter should run nbwipers
+    #[arg(long, value_enum, default_value_t = FilterCommand::Absolute)]
+    pub command: FilterCommand,
+
+    /// show the changes to each file without writing them
+    #[arg(long, short)]
+    pub dry_run: bool,
+}
+
 #[derive(Clone, Debug, Parser)]
 pub struct UninstallCommand {
     /// Git config type that determines which file to modify
@@ -293,7 +499,80 @@ pub struct RecordCommand {
     /// discard all recorded kernel metadata and rebuild it from the notebooks currently found under `path`, dropping entries for notebooks that are no longer found
     #[arg(long)]
     pub sync: bool,
+    /// drop entries for notebooks that no longer exist, moving the entries of notebooks that were renamed or moved to their new path
+    #[arg(long, conflicts_with_all = ["clear", "sync"])]
+    pub prune: bool,
+
+    /// list the recorded entries without recording anything
+    #[arg(long, conflicts_with_all = ["remove", "clear", "sync", "prune", "show"])]
+    pub list: bool,
+    /// show the entry recorded for this notebook path without recording anything
+    #[arg(long, conflicts_with_all = ["remove", "clear", "sync", "prune"])]
+    pub show: Option<PathBuf>,
+    /// desired output format for `--list` and `--show`
+    #[arg(long, short)]
+    pub output_format: Option<OutputFormat>,
+
+    #[clap(flatten)]
+    pub common: CommonArgs,
+}
+#[derive(Clone, Debug, Parser)]
+pub struct RewriteHistoryCommand {
+    /// branches or other refs whose history should be rewritten. Defaults to the current branch
+    pub refs: Vec<String>,
+
+    /// report how many bytes would be saved for each notebook without rewriting anything
+    #[arg(long, short)]
+    pub dry_run: bool,
+
+    #[clap(flatten)]
+    pub common: CommonArgs,
+}
+#[derive(Clone, Debug, Parser)]
+pub struct AuditCommand {
+    /// path to search for notebooks to audit. Defaults to the current directory
+    pub path: Option<PathBuf>,
+
+    /// desired output format for the report
+    #[arg(long, short)]
+    pub output_format: Option<OutputFormat>,
+
+    /// also report the total size of every version of each notebook in the git history
+    #[arg(long)]
+    pub git_history: bool,
+
+    #[clap(flatten)]
+    pub common: CommonArgs,
+}
+#[derive(Clone, Debug, Parser)]
+pub struct DeriveCommand {
+    /// notebooks, or directories containing them, to derive versions of
+    #[arg(required = true)]
+    pub files: Vec<PathBuf>,
+
+    /// directory to write the derived notebooks to, at their paths relative to the current directory
+    #[arg(long)]
+    pub output_dir: PathBuf,
+
+    #[clap(flatten)]
+    pub common: CommonArgs,
+}
 
+#[derive(Clone, Debug, Parser)]
+pub struct ExplainCommand {
+    /// notebook to explain the findings of
+    pub notebook: PathBuf,
+
+    /// only explain the findings about this cell, numbered from 0
+    #[arg(long)]
+    pub cell: Option<usize>,
+
+    #[clap(flatten)]
+    pub common: CommonArgs,
+}
+
+#[derive(Clone, Debug, Parser)]
+pub struct ServerCommand {
     #[clap(flatten)]
     pub common: CommonArgs,
 }
@@ -305,6 +584,12 @@ pub struct CheckInstallCommand {
     /// Git config type to check
     #[clap(value_enum)]
     pub config_type: Option<GitConfigType>,
+    /// print a detailed report of which config and attribute files set up nbwipers or nbstripout, the commands they run and any conflicts
+    #[arg(long)]
+    pub report: bool,
+    /// desired output format for `--report`, which it implies
+    #[arg(long, short)]
+    pub output_format: Option<OutputFormat>,
 }
 
 #[derive(Clone, Debug, Parser)]
@@ -312,7 +597,7 @@ pub struct SmudgeCommand {
     pub path: String,
 }
 
-#[derive(Clone, Debug, ValueEnum, Copy)]
+#[derive(Clone, Debug, ValueEnum, Copy, PartialEq, Eq)]
 pub enum GitConfigType {
     /// System-wide git config
     System,
@@ -322,19 +607,38 @@ pub enum GitConfigType {
     Local,
 }
 
-#[derive(Clone, Debug, Default)]
+#[derive(Clone, Debug, Default, Serialize)]
+#[serde(rename_all = "kebab-case")]
 pub struct ConfigOverrides {
+    /// the profile that is selected before the other overrides are applied
+    pub profile: Option<String>,
     pub strip_kernel_info: Option<bool>,
     pub extra_keys: Option<Vec<ExtraKey>>,
     pub drop_empty_cells: Option<bool>,
     pub drop_output: Option<bool>,
     pub drop_count: Option<bool>,
     pub id_action: Option<IdAction>,
+    /// the flag that set `id_action`, which is one of its aliases or `--id-action`
+    #[serde(skip)]
+    pub id_action_flag: Option<&'static str>,
     pub strip_init_cell: Option<bool>,
     pub drop_tagged_cells: Option<Vec<String>>,
+    pub keep_output_tags: Option<Vec<String>>,
+    pub drop_output_tags: Option<Vec<String>>,
+    pub keep_count_tags: Option<Vec<String>>,
+    pub clear_source_tags: Option<Vec<String>>,
     pub keep_keys: Option<Vec<ExtraKey>>,
     pub exclude: Option<Vec<FilePattern>>,
     pub extend_exclude: Option<Vec<FilePattern>>,
+    pub allowed_kernels: Option<Vec<String>>,
+    pub required_language: Option<String>,
+    pub required_language_version: Option<String>,
+    pub record_keys: Option<Vec<ExtraKey>>,
+    pub cache_outputs: Option<bool>,
+    pub record_on_clean: Option<bool>,
+    pub select: Option<Vec<RuleSelector>>,
+    pub ignore: Option<Vec<RuleSelector>>,
+    pub warn: Option<Vec<RuleSelector>>,
 }
 
 pub struct Args {
@@ -367,7 +671,21 @@ fn resolve_id_action(
     }
 }
 impl CommonArgs {
+    const fn id_action_flag(&self) -> Option<&'static str> {
+        if self.keep_id {
+            Some("--keep-id")
+        } else if self.sequential_id {
+            Some("--sequential-id")
+        } else if self.drop_id {
+            Some("--drop-id")
+        } else if self.id_action.is_some() {
+            Some("--id-action")
+        } else {
+            None
+        }
+    }
     pub fn partition(self) -> (Args, ConfigOverrides) {
+        let id_action_flag = self.id_action_flag();
         (
             Args {
                 config: self.config,
@@ -375,6 +693,7 @@ impl CommonArgs {
                 isolated: self.isolated,
             },
             ConfigOverrides {
+                profile: self.profile,
                 extra_keys: self.extra_keys,
                 drop_empty_cells: resolve_bool_arg(self.drop_empty_cells, self.keep_empty_cells),
                 drop_output: resolve_bool_arg(self.drop_output, self.keep_output),
@@ -385,12 +704,26 @@ impl CommonArgs {
                     self.sequential_id,
                     self.drop_id,
                 ),
+                id_action_flag,
                 drop_tagged_cells: self.drop_tagged_cells,
+                keep_output_tags: self.keep_output_tags,
+                drop_output_tags: self.drop_output_tags,
+                keep_count_tags: self.keep_count_tags,
+                clear_source_tags: self.clear_source_tags,
                 strip_init_cell: resolve_bool_arg(self.strip_init_cell, self.keep_init_cell),
                 keep_keys: self.keep_keys,
                 extend_exclude: self.extend_exclude,
                 exclude: self.exclude,
                 strip_kernel_info: resolve_bool_arg(self.strip_kernel_info, self.keep_kernel_info),
+                allowed_kernels: self.allowed_kernels,
+                required_language: self.required_language,
+                required_language_version: self.required_language_version,
+                record_keys: self.record_keys,
+                cache_outputs: resolve_bool_arg(self.cache_outputs, self.no_cache_outputs),
+                record_on_clean: resolve_bool_arg(self.record_on_clean, self.no_record_on_clean),
+                select: self.select,
+                ignore: self.ignore,
+                warn: self.warn,
             },
         )
     }
@@ -416,6 +749,18 @@ impl ConfigOverrides {
         if let Some(drop_tagged_cells) = &self.drop_tagged_cells {
             config.drop_tagged_cells = Some(drop_tagged_cells.clone());
         }
+        if let Some(keep_output_tags) = &self.keep_output_tags {
+            config.keep_output_tags = Some(keep_output_tags.clone());
+        }
+        if let Some(drop_output_tags) = &self.drop_output_tags {
+            config.drop_output_tags = Some(drop_output_tags.clone());
+        }
+        if let Some(keep_count_tags) = &self.keep_count_tags {
+            config.keep_count_tags = Some(keep_count_tags.clone());
+        }
+        if let Some(clear_source_tags) = &self.clear_source_tags {
+            config.clear_source_tags = Some(clear_source_tags.clone());
+        }
         if let Some(strip_init_cell) = &self.strip_init_cell {
             config.strip_init_cell = Some(*strip_init_cell);
         }
@@ -431,6 +776,33 @@ impl ConfigOverrides {
         if let Some(strip_kernel_info) = &self.strip_kernel_info {
             config.strip_kernel_info = Some(*strip_kernel_info);
         }
+        if let Some(allowed_kernels) = &self.allowed_kernels {
+            config.allowed_kernels = Some(allowed_kernels.clone());
+        }
+        if let Some(required_language) = &self.required_language {
+            config.required_language = Some(required_language.clone());
+        }
+        if let Some(required_language_version) = &self.required_language_version {
+            config.required_language_version = Some(required_language_version.clone());
+        }
+        if let Some(record_keys) = &self.record_keys {
+            config.record_keys = Some(record_keys.clone());
+        }
+        if let Some(cache_outputs) = &self.cache_outputs {
+            config.cache_outputs = Some(*cache_outputs);
+        }
+        if let Some(record_on_clean) = &self.record_on_clean {
+            config.record_on_clean = Some(*record_on_clean);
+        }
+        if let Some(select) = &self.select {
+            config.select = Some(select.clone());
+        }
+        if let Some(ignore) = &self.ignore {
+            config.ignore = Some(ignore.clone());
+        }
+        if let Some(warn) = &self.warn {
+            config.warn = Some(warn.clone());
+        }
         config
     }
 }
@@ -1,8 +1,13 @@
 use crate::files::{get_cwd, normalize_path_to};
-use crate::{extra_keys::ExtraKey, settings::Settings};
+use crate::{cli::ConfigOverrides, extra_keys::ExtraKey, rules::RuleSelector, settings::Settings};
+use anyhow::bail;
 use globset::{Glob, GlobSet, GlobSetBuilder};
+use itertools::Itertools;
 use rustc_hash::FxHashSet;
+use semver::VersionReq;
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
 use strum::EnumString;
 // use std::fmt::{Display, Formatter};
 use std::io;
@@ -22,7 +27,7 @@ pub enum IdAction {
     Drop,
 }
 
-#[derive(Debug, Clone, Default, Deserialize, PartialEq, Eq)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
 #[serde(rename_all = "kebab-case")]
 pub struct ConfigurationSection {
     pub extra_keys: Option<Vec<ExtraKey>>,
@@ -30,15 +35,162 @@ pub struct ConfigurationSection {
     pub drop_output: Option<bool>,
     pub drop_count: Option<bool>,
     pub id_action: Option<IdAction>,
+    #[serde(default, deserialize_with = "deserialize_tags")]
     pub drop_tagged_cells: Option<Vec<String>>,
+    #[serde(default, deserialize_with = "deserialize_tags")]
+    pub keep_output_tags: Option<Vec<String>>,
+    #[serde(default, deserialize_with = "deserialize_tags")]
+    pub drop_output_tags: Option<Vec<String>>,
+    #[serde(default, deserialize_with = "deserialize_tags")]
+    pub keep_count_tags: Option<Vec<String>>,
+    #[serde(default, deserialize_with = "deserialize_tags")]
+    pub clear_source_tags: Option<Vec<String>>,
     pub strip_init_cell: Option<bool>,
     pub keep_keys: Option<Vec<ExtraKey>>,
     pub exclude: Option<Vec<String>>,
     pub extend_exclude: Option<Vec<String>>,
     pub strip_kernel_info: Option<bool>,
+    pub allowed_kernels: Option<Vec<String>>,
+    pub required_language: Option<String>,
+    pub required_language_version: Option<String>,
+    pub canonical_kernelspec: Option<Value>,
+    pub record_keys: Option<Vec<ExtraKey>>,
+    pub cache_outputs: Option<bool>,
+    pub record_on_clean: Option<bool>,
+    pub select: Option<Vec<RuleSelector>>,
+    pub ignore: Option<Vec<RuleSelector>>,
+    pub warn: Option<Vec<RuleSelector>>,
+    pub smudge: Option<SmudgeSection>,
+    pub profiles: Option<BTreeMap<String, ConfigurationSection>>,
+}
+
+/// The profiles that are available without being configured. A configured profile of the same
+/// name is applied on top of the built-in one.
+pub fn builtin_profile(name: &str) -> Option<ConfigurationSection> {
+    match name {
+        // student versions of solution notebooks, for `derive`
+        "student" => Some(ConfigurationSection {
+            drop_output: Some(true),
+            drop_count: Some(true),
+            clear_source_tags: Some(vec!["solution".to_owned()]),
+            drop_tagged_cells: Some(vec!["hidden-tests".to_owned()]),
+            ..ConfigurationSection::default()
+        }),
+        _ => None,
+    }
+}
+const BUILTIN_PROFILES: &[&str] = &["student"];
+
+#[derive(Debug, Error)]
+pub enum ProfileError {
+    #[error("Unknown profile `{name}`. The available profiles are {available}")]
+    Unknown { name: String, available: String },
+    #[error("Profile `{0}` cannot have profiles of its own")]
+    Nested(String),
+}
+
+/// The items of `base` followed by the items of `extra` that are not in `base`.
+fn concat<T: PartialEq>(base: Option<Vec<T>>, extra: Option<Vec<T>>) -> Option<Vec<T>> {
+    match (base, extra) {
+        (Some(mut base), Some(extra)) => {
+            for item in extra {
+                if !base.contains(&item) {
+                    base.push(item);
+                }
+            }
+            Some(base)
+        }
+        (base, extra) => extra.or(base),
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub struct SmudgeSection {
+    pub defaults: Option<Vec<SmudgeDefaultSection>>,
+}
+
+/// Kernel metadata for the smudge filter to add to notebooks matching `path` when nothing is
+/// recorded for them locally.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub struct SmudgeDefaultSection {
+    pub path: String,
+    pub kernelspec: Option<Value>,
+    pub language_info: Option<Value>,
 }
 
 impl ConfigurationSection {
+    /// This section with the settings of the profile `name` on top, which are the settings of the
+    /// built-in profile of that name, if there is one, and of `[profiles.<name>]`. The tags of a
+    /// built-in profile are left out, as they are added to the tags in effect.
+    pub fn select_profile(mut self, name: &str) -> Result<Self, ProfileError> {
+        let mut profiles = self.profiles.take().unwrap_or_default();
+        let configured = profiles.remove(name);
+        if configured.as_ref().is_some_and(|p| p.profiles.is_some()) {
+            return Err(ProfileError::Nested(name.to_owned()));
+        }
+        let mut builtin = builtin_profile(name);
+        if configured.is_none() && builtin.is_none() {
+            let mut available: Vec<&str> = profiles
+                .keys()
+                .map(String::as_str)
+                .chain(BUILTIN_PROFILES.iter().copied())
+                .collect();
+            available.sort_unstable();
+            available.dedup();
+            return Err(ProfileError::Unknown {
+                name: name.to_owned(),
+                available: available.iter().map(|p| format!("`{p}`")).join(", "),
+            });
+        }
+        // the tags of a built-in profile are added by `Configuration::load` instead
+        if let Some(builtin) = &mut builtin {
+            builtin.drop_tagged_cells = None;
+            builtin.clear_source_tags = None;
+        }
+        Ok(builtin
+            .into_iter()
+            .chain(configured)
+            .fold(self, Self::overlay))
+    }
+
+    /// This section with the settings that `profile` sets replacing its own, except for
+    /// `extend-exclude`, whose patterns are added.
+    fn overlay(self, profile: Self) -> Self {
+        Self {
+            extra_keys: profile.extra_keys.or(self.extra_keys),
+            drop_empty_cells: profile.drop_empty_cells.or(self.drop_empty_cells),
+            drop_output: profile.drop_output.or(self.drop_output),
+            drop_count: profile.drop_count.or(self.drop_count),
+            id_action: profile.id_action.or(self.id_action),
+            drop_tagged_cells: profile.drop_tagged_cells.or(self.drop_tagged_cells),
+            keep_output_tags: profile.keep_output_tags.or(self.keep_output_tags),
+            drop_output_tags: profile.drop_output_tags.or(self.drop_output_tags),
+            keep_count_tags: profile.keep_count_tags.or(self.keep_count_tags),
+            clear_source_tags: profile.clear_source_tags.or(self.clear_source_tags),
+            strip_init_cell: profile.strip_init_cell.or(self.strip_init_cell),
+            keep_keys: profile.keep_keys.or(self.keep_keys),
+            exclude: profile.exclude.or(self.exclude),
+            extend_exclude: concat(self.extend_exclude, profile.extend_exclude),
+            strip_kernel_info: profile.strip_kernel_info.or(self.strip_kernel_info),
+            allowed_kernels: profile.allowed_kernels.or(self.allowed_kernels),
+            required_language: profile.required_language.or(self.required_language),
+            required_language_version: profile
+                .required_language_version
+                .or(self.required_language_version),
+            canonical_kernelspec: profile.canonical_kernelspec.or(self.canonical_kernelspec),
+            record_keys: profile.record_keys.or(self.record_keys),
+            cache_outputs: profile.cache_outputs.or(self.cache_outputs),
+            record_on_clean: profile.record_on_clean.or(self.record_on_clean),
+            select: profile.select.or(self.select),
+            ignore: profile.ignore.or(self.ignore),
+            warn: profile.warn.or(self.warn),
+            smudge: profile.smudge.or(self.smudge),
+            profiles: None,
+        }
+    }
+
     pub fn make_configuration(self, own_path: Option<&Path>) -> Configuration {
         let parent = own_path.map_or_else(get_cwd, |own_path| {
             own_path
@@ -58,36 +210,88 @@ impl ConfigurationSection {
             .into_iter()
             .map(|p| FilePattern::new_with_path(&p, &parent))
             .collect();
+        let smudge_defaults = self
+            .smudge
+            .and_then(|smudge| smudge.defaults)
+            .map(|defaults| {
+                defaults
+                    .into_iter()
+                    .map(|default| SmudgeDefault {
+                        path: FilePattern::new_with_path(&default.path, &parent),
+                        kernelspec: default.kernelspec,
+                        language_info: default.language_info,
+                    })
+                    .collect()
+            });
 
         Configuration {
+            profile: None,
             extra_keys: self.extra_keys,
             drop_empty_cells: self.drop_empty_cells,
             drop_output: self.drop_output,
             drop_count: self.drop_count,
             id_action: self.id_action,
             drop_tagged_cells: self.drop_tagged_cells,
+            keep_output_tags: self.keep_output_tags,
+            drop_output_tags: self.drop_output_tags,
+            keep_count_tags: self.keep_count_tags,
+            clear_source_tags: self.clear_source_tags,
             strip_init_cell: self.strip_init_cell,
             keep_keys: self.keep_keys,
             exclude,
             extend_exclude,
             strip_kernel_info: self.strip_kernel_info,
+            allowed_kernels: self.allowed_kernels,
+            required_language: self.required_language,
+            required_language_version: self.required_language_version,
+            canonical_kernelspec: self.canonical_kernelspec,
+            record_keys: self.record_keys,
+            cache_outputs: self.cache_outputs,
+            record_on_clean: self.record_on_clean,
+            select: self.select,
+            ignore: self.ignore,
+            warn: self.warn,
+            smudge_defaults,
         }
     }
 }
 
 #[derive(Debug, Clone, Serialize, Default)]
 pub struct Configuration {
+    pub profile: Option<String>,
     pub extra_keys: Option<Vec<ExtraKey>>,
     pub drop_empty_cells: Option<bool>,
     pub drop_output: Option<bool>,
     pub drop_count: Option<bool>,
     pub id_action: Option<IdAction>,
     pub drop_tagged_cells: Option<Vec<String>>,
+    pub keep_output_tags: Option<Vec<String>>,
+    pub drop_output_tags: Option<Vec<String>>,
+    pub keep_count_tags: Option<Vec<String>>,
+    pub clear_source_tags: Option<Vec<String>>,
     pub strip_init_cell: Option<bool>,
     pub keep_keys: Option<Vec<ExtraKey>>,
     pub exclude: Option<Vec<FilePattern>>,
     pub extend_exclude: Vec<FilePattern>,
     pub strip_kernel_info: Option<bool>,
+    pub allowed_kernels: Option<Vec<String>>,
+    pub required_language: Option<String>,
+    pub required_language_version: Option<String>,
+    pub canonical_kernelspec: Option<Value>,
+    pub record_keys: Option<Vec<ExtraKey>>,
+    pub cache_outputs: Option<bool>,
+    pub record_on_clean: Option<bool>,
+    pub select: Option<Vec<RuleSelector>>,
+    pub ignore: Option<Vec<RuleSelector>>,
+    pub warn: Option<Vec<RuleSelector>>,
+    pub smudge_defaults: Option<Vec<SmudgeDefault>>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SmudgeDefault {
+    pub path: FilePattern,
+    pub kernelspec: Option<Value>,
+    pub language_info: Option<Value>,
 }
 
 pub const EXTRA_KEYS: &[&str] = &[
@@ -101,6 +305,36 @@ pub const EXTRA_KEYS: &[&str] = &[
     "cell.metadata.scrolled",
 ];
 
+/// The metadata keys that `strip-kernel-info` adds to the extra keys.
+pub const KERNEL_INFO_KEYS: &[&str] = &["metadata.kernelspec", "metadata.language_info.version"];
+
+/// The cell tags that keep the outputs of a cell by default, as in nbstripout.
+pub const KEEP_OUTPUT_TAGS: &[&str] = &["keep_output"];
+
+/// Check that `tag` can be a cell tag, which cannot be empty or contain whitespace.
+pub fn parse_tag(tag: &str) -> Result<String, String> {
+    if tag.is_empty() || tag.contains(char::is_whitespace) {
+        Err(format!(
+            "{tag:?} is not a valid cell tag: tags cannot be empty or contain whitespace"
+        ))
+    } else {
+        Ok(tag.to_owned())
+    }
+}
+
+fn deserialize_tags<'de, D>(deserializer: D) -> Result<Option<Vec<String>>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    Option::<Vec<String>>::deserialize(deserializer)?
+        .map(|tags| {
+            tags.iter()
+                .map(|tag| parse_tag(tag).map_err(serde::de::Error::custom))
+                .collect()
+        })
+        .transpose()
+}
+
 fn default_extra_keys() -> FxHashSet<ExtraKey> {
     #[allow(clippy::unwrap_used)]
     EXTRA_KEYS
@@ -175,17 +409,76 @@ fn make_globset<I: IntoIterator<Item = FilePattern>>(patterns: I) -> anyhow::Res
 }
 
 impl Configuration {
+    /// The configuration in `config_file`, or in the configuration file for the current directory
+    /// unless `isolated`, with the profile selected in `overrides` and then `overrides` applied.
+    pub fn load(
+        config_file: Option<&Path>,
+        isolated: bool,
+        overrides: &ConfigOverrides,
+    ) -> Result<Self, anyhow::Error> {
+        let (mut config_sec, config_path) = if isolated {
+            (ConfigurationSection::default(), None)
+        } else {
+            resolve(config_file)?
+        };
+        if let Some(profile) = &overrides.profile {
+            config_sec = config_sec.select_profile(profile)?;
+        }
+        let mut config = config_sec.make_configuration(config_path.as_deref());
+        config.profile.clone_from(&overrides.profile);
+        let mut config = overrides.override_config(config);
+        // the tags of a built-in profile are added to the configured ones rather than replacing
+        // them, so that `--profile student` still drops the cells that are dropped otherwise
+        if let Some(builtin) = overrides.profile.as_deref().and_then(builtin_profile) {
+            config.drop_tagged_cells = concat(config.drop_tagged_cells, builtin.drop_tagged_cells);
+            config.clear_source_tags = concat(config.clear_source_tags, builtin.clear_source_tags);
+        }
+        Ok(config)
+    }
+
     pub fn into_settings(self) -> Result<Settings, anyhow::Error> {
         let mut extra_keys = default_extra_keys();
         let strip_kernel_info = self.strip_kernel_info.unwrap_or(false);
         if strip_kernel_info {
-            extra_keys.insert(ExtraKey::from_str("metadata.kernelspec").unwrap());
-            extra_keys.insert(ExtraKey::from_str("metadata.language_info.version").unwrap());
+            extra_keys.extend(
+                KERNEL_INFO_KEYS
+                    .iter()
+                    .map(|key| ExtraKey::from_str(key).unwrap()),
+            );
         }
         extra_keys.extend(self.extra_keys.unwrap_or_default());
         for key in &self.keep_keys.unwrap_or_default() {
             extra_keys.remove(key);
         }
+        if let Some(canonical) = &self.canonical_kernelspec {
+            // the kernelspec is replaced rather than removed
+            extra_keys.remove(&ExtraKey::from_str("metadata.kernelspec").unwrap());
+            if let Some(allowed) = &self.allowed_kernels {
+                let name = canonical.get("name").and_then(Value::as_str);
+                if !name.is_some_and(|name| allowed.iter().any(|k| k == name)) {
+                    bail!(
+                        "The name of `canonical-kernelspec` must be one of the `allowed-kernels`, or a cleaned notebook would still fail the check"
+                    );
+                }
+            }
+        }
+        let required_language_version_ = self
+            .required_language_version
+            .as_deref()
+            .map(VersionReq::parse)
+            .transpose()?;
+
+        let keep_output_tags: FxHashSet<String> = self.keep_output_tags.map_or_else(
+            || KEEP_OUTPUT_TAGS.iter().map(ToString::to_string).collect(),
+            FxHashSet::from_iter,
+        );
+        let drop_output_tags: FxHashSet<String> = self
+            .drop_output_tags
+            .map(FxHashSet::from_iter)
+            .unwrap_or_default();
+        if let Some(tag) = keep_output_tags.intersection(&drop_output_tags).min() {
+            bail!("The tag `{tag}` is in both `keep-output-tags` and `drop-output-tags`");
+        }
 
         let exclude = self
             .exclude
@@ -201,8 +494,14 @@ impl Configuration {
             .collect();
         let exclude_ = make_globset(self.exclude.unwrap_or_default())?;
         let extend_exclude_ = make_globset(self.extend_exclude)?;
+        let smudge_defaults = self.smudge_defaults.unwrap_or_default();
+        let smudge_defaults_ = smudge_defaults
+            .iter()
+            .map(|default| make_globset([default.path.clone()]))
+            .collect::<Result<_, _>>()?;
 
         Ok(Settings {
+            profile: self.profile,
             extra_keys,
             drop_empty_cells: self.drop_empty_cells.unwrap_or(false),
             drop_output: self.drop_output.unwrap_or(true),
@@ -212,12 +511,35 @@ impl Configuration {
                 .drop_tagged_cells
                 .map(FxHashSet::from_iter)
                 .unwrap_or_default(),
+            keep_output_tags,
+            drop_output_tags,
+            keep_count_tags: self
+                .keep_count_tags
+                .map(FxHashSet::from_iter)
+                .unwrap_or_default(),
+            clear_source_tags: self
+                .clear_source_tags
+                .map(FxHashSet::from_iter)
+                .unwrap_or_default(),
             strip_init_cell: self.strip_init_cell.unwrap_or(false),
             exclude,
             exclude_,
             extend_exclude,
             extend_exclude_,
             strip_kernel_info,
+            allowed_kernels: self.allowed_kernels,
+            required_language: self.required_language,
+            required_language_version: self.required_language_version,
+            required_language_version_,
+            canonical_kernelspec: self.canonical_kernelspec,
+            record_keys: self.record_keys.unwrap_or_default(),
+            cache_outputs: self.cache_outputs.unwrap_or(false),
+            record_on_clean: self.record_on_clean.unwrap_or(false),
+            select: self.select.unwrap_or_else(|| vec![RuleSelector::all()]),
+            ignore: self.ignore.unwrap_or_default(),
+            warn: self.warn.unwrap_or_default(),
+            smudge_defaults,
+            smudge_defaults_,
         })
     }
 }
@@ -297,13 +619,18 @@ fn read_settings<P: AsRef<Path>>(path: P) -> Result<Option<ConfigurationSection>
     }
 }
 
+/// The configuration file to read, which is `config_file` if given and otherwise the one found
+/// for the current directory, if any.
+pub fn resolve_path(config_file: Option<&Path>) -> Result<Option<PathBuf>, PyprojectError> {
+    config_file.map_or_else(find_settings, |config_file| {
+        Ok(Some(config_file.to_owned()))
+    })
+}
+
 pub fn resolve(
     config_file: Option<&Path>,
 ) -> Result<(ConfigurationSection, Option<PathBuf>), PyprojectError> {
-    if let Some(config_file) = config_file {
-        let config = read_settings(config_file)?;
-        Ok((config.unwrap_or_default(), Some(config_file.to_owned())))
-    } else if let Some(settings_file) = find_settings()? {
+    if let Some(settings_file) = resolve_path(config_file)? {
         let config = read_settings(&settings_file)?;
         Ok((config.unwrap_or_default(), Some(settings_file)))
     } else {
@@ -354,4 +681,79 @@ mod tests {
             );
         });
     }
+
+    #[test]
+    fn test_select_profile() {
+        let config: ConfigurationSection = toml::from_str(
+            r#"
+            drop-output = true
+            drop-count = true
+            extend-exclude = ["build"]
+
+            [profiles.docs]
+            drop-output = false
+            extend-exclude = ["site"]
+
+            [profiles.student]
+            drop-count = false
+            "#,
+        )
+        .unwrap();
+
+        let docs = config.clone().select_profile("docs").unwrap();
+        assert_eq!(docs.drop_output, Some(false));
+        assert_eq!(docs.drop_count, Some(true));
+        assert_eq!(
+            docs.extend_exclude,
+            Some(vec!["build".to_owned(), "site".to_owned()])
+        );
+        assert!(docs.profiles.is_none());
+
+        // the configured student profile is applied on top of the built-in one
+        let student = config.clone().select_profile("student").unwrap();
+        assert_eq!(student.drop_count, Some(false));
+        assert_eq!(student.drop_output, Some(true));
+
+        let err = config.select_profile("publish").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Unknown profile `publish`. The available profiles are `docs`, `student`"
+        );
+    }
+
+    #[test]
+    fn test_canonical_kernelspec_allowed() {
+        let config = |allowed: &str| Configuration {
+            canonical_kernelspec: Some(serde_json::json!({"name": "python3"})),
+            allowed_kernels: Some(vec![allowed.to_owned()]),
+            ..Configuration::default()
+        };
+        assert!(config("python3").into_settings().is_ok());
+        let err = config("ir").into_settings().unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("must be one of the `allowed-kernels`")
+        );
+    }
+
+    #[test]
+    fn test_builtin_profile_tags() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let config_file = temp_dir.path().join("nbwipers.toml");
+        fs::write(&config_file, "drop-tagged-cells = [\"scratch\"]\n").unwrap();
+        let overrides = ConfigOverrides {
+            profile: Some("student".to_owned()),
+            clear_source_tags: Some(vec!["answer".to_owned()]),
+            ..ConfigOverrides::default()
+        };
+        let config = Configuration::load(Some(&config_file), false, &overrides).unwrap();
+        assert_eq!(
+            config.drop_tagged_cells,
+            Some(vec!["scratch".to_owned(), "hidden-tests".to_owned()])
+        );
+        assert_eq!(
+            config.clear_source_tags,
+            Some(vec!["answer".to_owned(), "solution".to_owned()])
+        );
+    }
 }
@@ -0,0 +1,203 @@
+use std::{
+    fs,
+    io::BufWriter,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Error, anyhow, bail};
+use colored::Colorize;
+use rayon::prelude::*;
+
+use crate::{
+    cli::DeriveCommand,
+    files::{
+        FoundNotebooks, find_notebooks_or_stdin, get_cwd, normalize_path, read_nb, relativize_path,
+    },
+    output_cache::source_text,
+    schema::{Cell, RawNotebook, SourceValue},
+    settings::Settings,
+    strip::{strip_nb, write_nb},
+};
+
+const BEGIN_SOLUTION: &str = "### BEGIN SOLUTION";
+const END_SOLUTION: &str = "### END SOLUTION";
+const BEGIN_HIDDEN_TESTS: &str = "### BEGIN HIDDEN TESTS";
+const END_HIDDEN_TESTS: &str = "### END HIDDEN TESTS";
+
+/// What replaces a solution block in a code cell, and in other cells.
+const CODE_PLACEHOLDER: &str = "# YOUR CODE HERE";
+const ANSWER_PLACEHOLDER: &str = "YOUR ANSWER HERE";
+
+/// Replace each block of lines of `source` from a `begin` marker line to an `end` marker line with
+/// `placeholder`, indented like the `begin` marker, or remove the block if there is no placeholder.
+///
+/// Returns the `begin` marker of a block that is never ended as the error.
+fn replace_blocks(
+    source: &str,
+    begin: &'static str,
+    end: &str,
+    placeholder: Option<&str>,
+) -> Result<String, &'static str> {
+    let mut out = String::with_capacity(source.len());
+    let mut in_block = false;
+    for line in source.split_inclusive('\n') {
+        let marker = line.trim();
+        if in_block {
+            in_block = marker != end;
+        } else if marker == begin {
+            in_block = true;
+            if let Some(placeholder) = placeholder {
+                let indent = &line[..line.len() - line.trim_start().len()];
+                out.push_str(indent);
+                out.push_str(placeholder);
+                if line.ends_with('\n') {
+                    out.push('\n');
+                }
+            }
+        } else {
+            out.push_str(line);
+        }
+    }
+    if in_block {
+        return Err(begin);
+    }
+    // a block at the end of the source leaves no trailing newline behind
+    if !source.ends_with('\n') && out.ends_with('\n') {
+        out.pop();
+    }
+    Ok(out)
+}
+
+/// Replace the solution blocks in the source of `cell` with a placeholder and remove its hidden
+/// test blocks.
+fn replace_markers(cell: &mut Cell) -> Result<(), &'static str> {
+    let placeholder = match cell {
+        Cell::Code(_) => CODE_PLACEHOLDER,
+        Cell::Markdown(_) | Cell::Raw(_) => ANSWER_PLACEHOLDER,
+    };
+    let source = source_text(cell.get_source());
+    let replaced = replace_blocks(&source, BEGIN_SOLUTION, END_SOLUTION, Some(placeholder))?;
+    let replaced = replace_blocks(&replaced, BEGIN_HIDDEN_TESTS, END_HIDDEN_TESTS, None)?;
+    if replaced != source {
+        let new_source = match cell.get_source() {
+            SourceValue::String(_) => SourceValue::String(replaced),
+            SourceValue::StringArray(_) => SourceValue::StringArray(
+                replaced.split_inclusive('\n').map(str::to_owned).collect(),
+            ),
+        };
+        *cell.get_source_mut() = new_source;
+    }
+    Ok(())
+}
+
+/// Derive a version of `nb` by replacing the marked blocks in its cells and cleaning it with
+/// `settings`, which come from the selected profile, such as the built-in `student` profile.
+pub fn derive_nb(mut nb: RawNotebook, settings: &Settings) -> Result<RawNotebook, Error> {
+    for (cell_number, cell) in nb.cells.iter_mut().enumerate() {
+        replace_markers(cell).map_err(|marker| {
+            anyhow!("cell {cell_number}: `{marker}` is not followed by an end marker")
+        })?;
+    }
+    Ok(strip_nb(nb, settings).0)
+}
+
+/// Where the derived version of `nb_path` is written: its path relative to the current directory,
+/// under `output_dir`.
+fn output_path(nb_path: &Path, output_dir: &Path) -> PathBuf {
+    let nb_path = normalize_path(nb_path);
+    let relative = nb_path
+        .strip_prefix(get_cwd())
+        .map_or_else(
+            |_| nb_path.file_name().map(PathBuf::from),
+            |p| Some(p.to_owned()),
+        )
+        .unwrap_or_default();
+    output_dir.join(relative)
+}
+
+fn derive_single(nb_path: &Path, out_path: &Path, settings: &Settings) -> Result<(), Error> {
+    if normalize_path(out_path) == normalize_path(nb_path) {
+        bail!("refusing to overwrite the notebook with its derived version");
+    }
+    let nb = derive_nb(read_nb(nb_path)?, settings)?;
+    if let Some(parent) = out_path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    write_nb(BufWriter::new(fs::File::create(out_path)?), &nb)?;
+    Ok(())
+}
+
+pub fn derive(cmd: DeriveCommand) -> Result<(), Error> {
+    let (args, overrides) = cmd.common.partition();
+    if overrides.profile.is_none() {
+        bail!("`derive` needs a profile, such as `--profile student`");
+    }
+    let settings = Settings::construct(args.config.as_deref(), args.isolated, &overrides)?;
+    let nbs = match find_notebooks_or_stdin(&cmd.files, &settings)? {
+        FoundNotebooks::Files(nbs) => nbs,
+        FoundNotebooks::NoFiles if args.allow_no_notebooks => return Ok(()),
+        FoundNotebooks::NoFiles => bail!("Could not find any notebooks in path(s)"),
+        FoundNotebooks::Stdin => bail!("`derive` does not support stdin"),
+    };
+    // notebooks derived by an earlier run are not derived again
+    let output_dir = normalize_path(&cmd.output_dir);
+    let nbs: Vec<PathBuf> = nbs
+        .into_iter()
+        .filter(|nb_path| !normalize_path(nb_path).starts_with(&output_dir))
+        .collect();
+
+    let results: Vec<(PathBuf, Result<(), Error>)> = nbs
+        .par_iter()
+        .map(|nb_path| {
+            let out_path = output_path(nb_path, &cmd.output_dir);
+            let result = derive_single(nb_path, &out_path, &settings);
+            (out_path, result)
+        })
+        .collect();
+
+    let mut any_errors = false;
+    for (nb_path, (out_path, result)) in nbs.iter().zip(results) {
+        let rel_path = relativize_path(nb_path).bold();
+        match result {
+            Ok(()) => println!("{rel_path}: written to {}", relativize_path(out_path)),
+            Err(e) => {
+                any_errors = true;
+                println!("{rel_path}: {e}");
+            }
+        }
+    }
+    if any_errors {
+        bail!("Could not derive every notebook")
+    }
+    Ok(())
+}
+
+#[allow(clippy::unwrap_used)]
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_replace_blocks() {
+        let source = "def f():\n    ### BEGIN SOLUTION\n    return 1\n    ### END SOLUTION\n";
+        assert_eq!(
+            replace_blocks(source, BEGIN_SOLUTION, END_SOLUTION, Some(CODE_PLACEHOLDER)).unwrap(),
+            "def f():\n    # YOUR CODE HERE\n"
+        );
+        let source =
+            "assert f() == 1\n### BEGIN HIDDEN TESTS\nassert f() != 2\n### END HIDDEN TESTS";
+        assert_eq!(
+            replace_blocks(source, BEGIN_HIDDEN_TESTS, END_HIDDEN_TESTS, None).unwrap(),
+            "assert f() == 1"
+        );
+        assert_eq!(
+            replace_blocks(
+                "### BEGIN SOLUTION\nx = 1",
+                BEGIN_SOLUTION,
+                END_SOLUTION,
+                None
+            ),
+            Err(BEGIN_SOLUTION)
+        );
+    }
+}
@@ -0,0 +1,587 @@
+use std::{
+    collections::BTreeMap,
+    fmt::{Display, Write as _},
+    fs,
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Error, bail};
+use colored::Colorize;
+use rustc_hash::FxHashSet;
+use serde::Serialize;
+use serde_json::Value;
+use toml::de::DeTable;
+
+use crate::{
+    check::{CheckResult, check_nb},
+    cli::{ConfigOverrides, ExplainCommand},
+    config::{Configuration, KERNEL_INFO_KEYS, builtin_profile, resolve_path},
+    files::{read_nb, relativize_path},
+    rules::{Rule, RuleSelector, Severity},
+    schema::RawNotebook,
+    settings::Settings,
+};
+
+/// The command line flags that set each boolean setting to true and to false.
+const BOOL_FLAGS: &[(&str, &str, &str)] = &[
+    (
+        "drop-empty-cells",
+        "--drop-empty-cells",
+        "--keep-empty-cells",
+    ),
+    ("drop-output", "--drop-output", "--keep-output"),
+    ("drop-count", "--drop-count", "--keep-count"),
+    ("strip-init-cell", "--strip-init-cell", "--keep-init-cell"),
+    (
+        "strip-kernel-info",
+        "--strip-kernel-info",
+        "--keep-kernel-info",
+    ),
+    ("cache-outputs", "--cache-outputs", "--no-cache-outputs"),
+    (
+        "record-on-clean",
+        "--record-on-clean",
+        "--no-record-on-clean",
+    ),
+];
+
+/// Where the value of a setting comes from.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Source {
+    Default,
+    /// a line of a configuration file
+    ConfigFile {
+        path: PathBuf,
+        line: usize,
+    },
+    /// a profile, at a line of a configuration file unless it is built in
+    Profile {
+        name: String,
+        location: Option<(PathBuf, usize)>,
+    },
+    /// an option on the command line
+    CommandLine {
+        flag: String,
+    },
+}
+
+static DEFAULT_SOURCE: Source = Source::Default;
+
+impl Display for Source {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Default => write!(f, "default"),
+            Self::ConfigFile { path, line } => write!(f, "{}:{line}", relativize_path(path)),
+            Self::Profile {
+                name,
+                location: Some((path, line)),
+            } => write!(f, "profile `{name}` at {}:{line}", relativize_path(path)),
+            Self::Profile {
+                name,
+                location: None,
+            } => write!(f, "built-in profile `{name}`"),
+            Self::CommandLine { flag } => write!(f, "command line `{flag}`"),
+        }
+    }
+}
+
+/// Where each setting that is not a default comes from, by its name in the configuration.
+#[derive(Clone, Debug, Default)]
+pub struct Provenance {
+    sources: BTreeMap<String, Source>,
+    /// the tags that a built-in profile adds to each of its tag settings, and the profile
+    builtin_tags: BTreeMap<String, (Vec<String>, Source)>,
+}
+
+impl Provenance {
+    /// Find where the settings come from in the same way as [`Configuration::load`]: the
+    /// configuration file, then the selected profile and then the command line.
+    pub fn load(
+        config_file: Option<&Path>,
+        isolated: bool,
+        overrides: &ConfigOverrides,
+    ) -> Result<Self, Error> {
+        let mut sources = BTreeMap::new();
+        let mut builtin_tags = BTreeMap::new();
+        let mut configured_profile = vec![];
+        let config_path = if isolated {
+            None
+        } else {
+            resolve_path(config_file)?
+        };
+        if let Some(path) = config_path {
+            let text = fs::read_to_string(&path)?;
+            let document = DeTable::parse(&text)?;
+            let section = if path.ends_with("pyproject.toml") {
+                child_table(document.get_ref(), "tool")
+                    .and_then(|tool| child_table(tool, "nbwipers"))
+            } else {
+                Some(document.get_ref())
+            };
+            if let Some(section) = section {
+                for (key, line) in keys_with_lines(section, &text) {
+                    let path = path.clone();
+                    sources.insert(key, Source::ConfigFile { path, line });
+                }
+                if let Some(name) = &overrides.profile
+                    && let Some(profile) =
+                        child_table(section, "profiles").and_then(|p| child_table(p, name))
+                {
+                    configured_profile = keys_with_lines(profile, &text)
+                        .into_iter()
+                        .map(|(key, line)| (key, Some((path.clone(), line))))
+                        .collect();
+                }
+            }
+        }
+        if let Some(name) = &overrides.profile {
+            let mut builtin = builtin_profile(name);
+            // the tags of the built-in profile are added to those in effect, wherever they come from
+            if let Some(builtin) = &mut builtin {
+                let tags = [
+                    ("drop-tagged-cells", builtin.drop_tagged_cells.take()),
+                    ("clear-source-tags", builtin.clear_source_tags.take()),
+                ];
+                for (key, tags) in tags {
+                    let source = Source::Profile {
+                        name: name.clone(),
+                        location: None,
+                    };
+                    builtin_tags.extend(tags.map(|tags| (key.to_owned(), (tags, source))));
+                }
+            }
+            // the built-in profile is applied before the configured one of the same name
+            let builtin = builtin.map(|p| set_values(&p)).transpose()?;
+            let builtin = builtin.into_iter().flatten().map(|(key, _)| (key, None));
+            for (key, location) in builtin.chain(configured_profile) {
+                let name = name.clone();
+                sources.insert(key, Source::Profile { name, location });
+            }
+        }
+        for (key, value) in set_values(overrides)? {
+            let flag = match overrides.id_action_flag {
+                Some(flag) if key == "id-action" => flag.to_owned(),
+                _ => cli_flag(&key, &value),
+            };
+            sources.insert(key, Source::CommandLine { flag });
+        }
+        Ok(Self {
+            sources,
+            builtin_tags,
+        })
+    }
+
+    /// Where the setting `name`, as named in the configuration, comes from, leaving out the
+    /// tags that a built-in profile adds to it.
+    pub fn source(&self, name: &str) -> &Source {
+        self.sources.get(name).unwrap_or(&DEFAULT_SOURCE)
+    }
+
+    /// Where the tag `tag` of the tag setting `name` comes from.
+    pub fn tag_source(&self, name: &str, tag: &str) -> &Source {
+        match self.builtin_tags.get(name) {
+            Some((tags, source)) if tags.iter().any(|t| t == tag) => source,
+            _ => self.source(name),
+        }
+    }
+
+    /// All the places that the value of the setting `name` comes from.
+    pub fn describe(&self, name: &str) -> String {
+        match (self.sources.get(name), self.builtin_tags.get(name)) {
+            (Some(source), Some((_, builtin))) => format!("{source} and {builtin}"),
+            (None, Some((_, builtin))) => builtin.to_string(),
+            _ => self.source(name).to_string(),
+        }
+    }
+}
+
+/// The table at `key` in `table`, if there is one.
+fn child_table<'a, 'i>(table: &'a DeTable<'i>, key: &str) -> Option<&'a DeTable<'i>> {
+    table
+        .iter()
+        .find(|(k, _)| *k.get_ref() == key)
+        .and_then(|(_, value)| value.get_ref().as_table())
+}
+
+/// The keys of `table` with the line of `text` that each of them is on.
+fn keys_with_lines(table: &DeTable<'_>, text: &str) -> Vec<(String, usize)> {
+    table
+        .keys()
+        .map(|key| {
+            let before = &text.as_bytes()[..key.span().start];
+            let line = before.iter().filter(|&&b| b == b'\n').count() + 1;
+            (key.get_ref().to_string(), line)
+        })
+        .collect()
+}
+
+/// The fields of `value` that are set, by their serialized names.
+fn set_values<T: Serialize>(value: &T) -> Result<Vec<(String, Value)>, serde_json::Error> {
+    let Value::Object(fields) = serde_json::to_value(value)? else {
+        return Ok(vec![]);
+    };
+    Ok(fields.into_iter().filter(|(_, v)| !v.is_null()).collect())
+}
+
+/// The command line flag that sets the setting `key` to `value`.
+fn cli_flag(key: &str, value: &Value) -> String {
+    BOOL_FLAGS
+        .iter()
+        .find(|(name, ..)| *name == key)
+        .zip(value.as_bool())
+        .map_or_else(
+            || format!("--{key}"),
+            |((_, yes, no), value)| if value { *yes } else { *no }.to_owned(),
+        )
+}
+
+/// `value` as inline TOML, with the items of an array of strings sorted.
+fn toml_value<T: Serialize>(value: T) -> String {
+    match toml::Value::try_from(value) {
+        Ok(toml::Value::Array(mut items)) => {
+            if items.iter().all(toml::Value::is_str) {
+                items.sort_by(|a, b| a.as_str().cmp(&b.as_str()));
+            }
+            toml::Value::Array(items).to_string()
+        }
+        Ok(value) => value.to_string(),
+        Err(_) => String::new(),
+    }
+}
+
+/// A finding of `check` with why it is made and how it is reported.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Explanation {
+    pub result: CheckResult,
+    /// the setting, or the content of the notebook, that makes the finding
+    pub reason: String,
+    /// how `check` reports the finding, and the setting that decides it
+    pub report: String,
+}
+
+/// The settings in effect, with where each of them comes from.
+pub struct Explainer {
+    configuration: Configuration,
+    pub settings: Settings,
+    provenance: Provenance,
+}
+
+impl Explainer {
+    pub fn load(
+        config_file: Option<&Path>,
+        isolated: bool,
+        overrides: &ConfigOverrides,
+    ) -> Result<Self, Error> {
+        let configuration = Configuration::load(config_file, isolated, overrides)?;
+        let settings = configuration.clone().into_settings()?;
+        let provenance = Provenance::load(config_file, isolated, overrides)?;
+        Ok(Self {
+            configuration,
+            settings,
+            provenance,
+        })
+    }
+
+    /// The setting `name` with its value and where it comes from.
+    fn setting<T: Serialize>(&self, name: &str, value: T) -> String {
+        format!(
+            "`{name} = {}` ({})",
+            toml_value(value),
+            self.provenance.source(name)
+        )
+    }
+
+    /// The setting that makes cleaning remove the metadata key `key`, or `None` if it is one of
+    /// the keys removed by default.
+    fn extra_key_setting(&self, key: &str) -> Option<&'static str> {
+        if (self.configuration.extra_keys.iter().flatten()).any(|k| k.to_string() == key) {
+            Some("extra-keys")
+        } else if self.settings.strip_kernel_info && KERNEL_INFO_KEYS.contains(&key) {
+            Some("strip-kernel-info")
+        } else {
+            None
+        }
+    }
+
+    /// Why `check` makes the finding `result` in `nb`.
+    fn reason(&self, nb: &RawNotebook, result: &CheckResult) -> String {
+        let settings = &self.settings;
+        let cell = result.cell_number().and_then(|n| nb.cells.get(n));
+        let tag_in = |name: &str, tags: &FxHashSet<String>| {
+            let tag = cell.and_then(|c| c.get_tags().find(|tag| tags.contains(*tag)))?;
+            let source = self.provenance.tag_source(name, tag);
+            Some(format!(
+                "the cell has the tag `{tag}` in `{name}` ({source})"
+            ))
+        };
+        match result {
+            CheckResult::IOError { .. } | CheckResult::InvalidNotebook { .. } => {
+                "the notebook could not be read".to_owned()
+            }
+            CheckResult::StripMeta { extra_key } | CheckResult::CellStripMeta { extra_key, .. } => {
+                match self.extra_key_setting(extra_key) {
+                    Some("extra-keys") => format!(
+                        "`{extra_key}` is in `extra-keys` ({})",
+                        self.provenance.source("extra-keys")
+                    ),
+                    Some(name) => self.setting(name, true),
+                    None => {
+                        format!("`{extra_key}` is removed by default, unless it is in `keep-keys`")
+                    }
+                }
+            }
+            CheckResult::DropCells { .. } => {
+                let drop_empty = self.setting("drop-empty-cells", true);
+                tag_in("drop-tagged-cells", &settings.drop_tagged_cells)
+                    .or_else(|| {
+                        let cleared = tag_in("clear-source-tags", &settings.clear_source_tags)?;
+                        Some(format!("{cleared}, which empties it, and {drop_empty}"))
+                    })
+                    .unwrap_or_else(|| format!("the cell is empty and {drop_empty}"))
+            }
+            CheckResult::ClearOutput { .. } => {
+                tag_in("drop-output-tags", &settings.drop_output_tags).unwrap_or_else(|| match cell
+                    .and_then(|c| c.get_metadata().get("init_cell"))
+                {
+                    Some(Value::Bool(true)) => self.setting("strip-init-cell", true),
+                    Some(_) => "the cell has `init_cell` metadata that is not `true`".to_owned(),
+                    None => self.setting("drop-output", true),
+                })
+            }
+            CheckResult::ClearCount { .. } => self.setting("drop-count", true),
+            CheckResult::ClearId { .. } => self.setting("id-action", settings.id_action),
+            CheckResult::ClearSource { .. } => {
+                tag_in("clear-source-tags", &settings.clear_source_tags).unwrap_or_default()
+            }
+            CheckResult::DowngradeNBFormat { .. } => format!(
+                "cell ids are removed by {}",
+                self.setting("id-action", settings.id_action)
+            ),
+            CheckResult::DisallowedKernel { .. } => {
+                self.setting("allowed-kernels", &settings.allowed_kernels)
+            }
+            CheckResult::WrongLanguage { .. } => {
+                self.setting("required-language", &settings.required_language)
+            }
+            CheckResult::WrongLanguageVersion { .. } => self.setting(
+                "required-language-version",
+                &settings.required_language_version,
+            ),
+            CheckResult::ReplaceKernelspec => format!(
+                "the kernelspec differs from `canonical-kernelspec` ({})",
+                self.provenance.source("canonical-kernelspec")
+            ),
+        }
+    }
+
+    /// How `check` reports findings of `rule`, and the setting that decides it.
+    fn report(&self, rule: Rule) -> String {
+        let matching = |name: &str, selectors: &[RuleSelector]| {
+            let selector = selectors.iter().find(|s| s.matches(rule))?;
+            let source = self.provenance.source(name);
+            Some(format!("`{selector}` is in `{name}` ({source})"))
+        };
+        let settings = &self.settings;
+        match settings.severity(rule) {
+            _ if rule.is_read_error() => {
+                "reported as an error, as the notebook could not be read".to_owned()
+            }
+            Some(Severity::Error) => format!(
+                "reported as an error, as {}",
+                matching("select", &settings.select).unwrap_or_default()
+            ),
+            Some(Severity::Warning) => format!(
+                "reported as a warning, as {}",
+                matching("warn", &settings.warn).unwrap_or_default()
+            ),
+            None => format!(
+                "not reported by `check`, as {}",
+                matching("ignore", &settings.ignore).unwrap_or_else(|| format!(
+                    "`{}` is not in `select` ({})",
+                    rule.code(),
+                    self.provenance.source("select")
+                ))
+            ),
+        }
+    }
+
+    /// The findings of `check` in `nb`, including those of rules that are not selected, with
+    /// why each of them is made.
+    pub fn explain_nb(&self, nb: &RawNotebook) -> Vec<Explanation> {
+        check_nb(nb, &self.settings)
+            .into_iter()
+            .map(|result| Explanation {
+                reason: self.reason(nb, &result),
+                report: self.report(result.rule()),
+                result,
+            })
+            .collect()
+    }
+
+    /// The settings in effect as TOML, each with a comment saying where it comes from.
+    pub fn explain_settings(&self) -> Result<String, Error> {
+        let toml::Value::Table(mut fields) = toml::Value::try_from(&self.settings)? else {
+            bail!("Settings should serialize to a table");
+        };
+        // the keys in `keep-keys` are taken out of `extra-keys` in the settings
+        let keep_keys = self.configuration.keep_keys.clone().unwrap_or_default();
+        fields.insert("keep_keys".to_owned(), toml::Value::try_from(keep_keys)?);
+        let mut out = String::new();
+        for (field, value) in fields {
+            let name = field.replace('_', "-");
+            if name == "extra-keys" {
+                let mut keys: Vec<String> = self
+                    .settings
+                    .extra_keys
+                    .iter()
+                    .map(ToString::to_string)
+                    .collect();
+                keys.sort_unstable();
+                writeln!(out, "extra-keys = [")?;
+                for key in keys {
+                    let source = match self.extra_key_setting(&key) {
+                        Some(name) => format!("`{name}` ({})", self.provenance.source(name)),
+                        None => Source::Default.to_string(),
+                    };
+                    writeln!(out, "    {}, # {source}", toml_value(key))?;
+                }
+                writeln!(out, "]")?;
+            } else if name == "smudge-defaults" {
+                let source = self.provenance.source("smudge");
+                writeln!(out, "smudge.defaults = {} # {source}", toml_value(value))?;
+            } else {
+                let source = self.provenance.describe(&name);
+                writeln!(out, "{name} = {} # {source}", toml_value(value))?;
+            }
+        }
+        Ok(out)
+    }
+}
+
+pub fn explain(cmd: ExplainCommand) -> Result<(), Error> {
+    let (args, overrides) = cmd.common.partition();
+    let explainer = Explainer::load(args.config.as_deref(), args.isolated, &overrides)?;
+    let nb = read_nb(&cmd.notebook)?;
+    if let Some(cell) = cmd.cell
+        && cell >= nb.cells.len()
+    {
+        bail!(
+            "There is no cell {cell}, as the notebook has {} cells",
+            nb.cells.len()
+        );
+    }
+    let explanations: Vec<Explanation> = explainer
+        .explain_nb(&nb)
+        .into_iter()
+        .filter(|e| cmd.cell.is_none() || e.result.cell_number() == cmd.cell)
+        .collect();
+
+    let rel_path = relativize_path(&cmd.notebook).bold();
+    let mut stdout = std::io::stdout().lock();
+    if explanations.is_empty() {
+        writeln!(stdout, "{rel_path}: nothing to clean or report")?;
+    }
+    for Explanation {
+        result,
+        reason,
+        report,
+    } in explanations
+    {
+        writeln!(stdout, "{rel_path}: {} {result}", result.rule().code())?;
+        writeln!(stdout, "    because {reason}")?;
+        writeln!(stdout, "    {report}")?;
+    }
+    Ok(())
+}
+
+#[allow(clippy::unwrap_used)]
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    #[test]
+    fn test_provenance() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let pyproject = temp_dir.path().join("pyproject.toml");
+        fs::write(
+            &pyproject,
+            "[project]\nname = \"course\"\n\n[tool.nbwipers]\nid-action = \"drop\"\n\
+             drop-tagged-cells = [\"scratch\"]\n\n\
+             [tool.nbwipers.profiles.student]\nextra-keys = [\"metadata.celltoolbar\"]\n",
+        )
+        .unwrap();
+        let overrides = ConfigOverrides {
+            profile: Some("student".to_owned()),
+            drop_output: Some(false),
+            ..ConfigOverrides::default()
+        };
+        let provenance = Provenance::load(Some(&pyproject), false, &overrides).unwrap();
+
+        assert_eq!(
+            provenance.source("id-action"),
+            &Source::ConfigFile {
+                path: pyproject.clone(),
+                line: 5
+            }
+        );
+        assert_eq!(
+            provenance.source("extra-keys"),
+            &Source::Profile {
+                name: "student".to_owned(),
+                location: Some((pyproject.clone(), 9))
+            }
+        );
+        let configured = Source::ConfigFile {
+            path: pyproject,
+            line: 6,
+        };
+        let builtin = Source::Profile {
+            name: "student".to_owned(),
+            location: None,
+        };
+        assert_eq!(provenance.source("drop-tagged-cells"), &configured);
+        assert_eq!(
+            provenance.tag_source("drop-tagged-cells", "scratch"),
+            &configured
+        );
+        assert_eq!(
+            provenance.tag_source("drop-tagged-cells", "hidden-tests"),
+            &builtin
+        );
+        assert_eq!(
+            provenance.describe("drop-tagged-cells"),
+            format!("{configured} and {builtin}")
+        );
+        assert_eq!(provenance.source("clear-source-tags"), &Source::Default);
+        assert_eq!(
+            provenance.tag_source("clear-source-tags", "solution"),
+            &builtin
+        );
+        assert_eq!(
+            provenance.describe("clear-source-tags"),
+            builtin.to_string()
+        );
+        assert_eq!(
+            provenance.source("drop-output"),
+            &Source::CommandLine {
+                flag: "--keep-output".to_owned()
+            }
+        );
+        assert_eq!(provenance.source("strip-init-cell"), &Source::Default);
+
+        let isolated = Provenance::load(None, true, &ConfigOverrides::default()).unwrap();
+        assert_eq!(isolated.source("id-action"), &Source::Default);
+    }
+
+    #[test]
+    fn test_toml_value() {
+        assert_eq!(toml_value(true), "true");
+        assert_eq!(toml_value(["b", "a"]), r#"["a", "b"]"#);
+        assert_eq!(
+            toml_value(vec![RuleSelector::from_str("NBW0").unwrap()]),
+            r#"["NBW0"]"#
+        );
+    }
+}
@@ -178,6 +178,20 @@ pub fn read_nb_stdin() -> Result<RawNotebook, NBReadError> {
     Ok(out)
 }
 
+/// Read a notebook along with its text, in which findings can then be located.
+pub fn read_nb_with_text<P: AsRef<Path>>(path: P) -> Result<(RawNotebook, String), NBReadError> {
+    let text = std::fs::read_to_string(path)?;
+    let nb = serde_json::from_str(&text)?;
+    Ok((nb, text))
+}
+
+/// Read a notebook from stdin along with its text, in which findings can then be located.
+pub fn read_nb_stdin_with_text() -> Result<(RawNotebook, String), NBReadError> {
+    let text = std::io::read_to_string(stdin().lock())?;
+    let nb = serde_json::from_str(&text)?;
+    Ok((nb, text))
+}
+
 #[allow(clippy::unwrap_used)]
 #[cfg(test)]
 mod tests {
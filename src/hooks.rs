@@ -1,16 +1,18 @@
 use std::ffi::OsStr;
-use std::io::Write;
 use std::path::{Path, PathBuf};
-use std::process::{Command, Stdio};
-use std::str::FromStr;
 use std::sync::RwLock;
 
-use crate::cli::{CheckLargeFilesCommand, ConfigOverrides, HookCommands};
-use crate::files::read_nb;
+use crate::cli::{
+    CheckFilterInstalledCommand, CheckJsonCommand, CheckKernelCommand, CheckLargeFilesCommand,
+    CheckMergeConflictCommand, ConfigOverrides, HookCommands,
+};
+use crate::files::{normalize_path, read_nb};
+use crate::install::{check_install_none_type, check_should_exit_zero};
+use crate::schema::{RawNotebook, SourceValue};
 use crate::settings::Settings;
-use crate::strip::{strip_nb, write_nb};
+use crate::strip::stripped_size;
 use anyhow::{Context, Error, anyhow, bail};
-use itertools::Itertools;
+use gix::attrs::StateRef;
 
 use rayon::prelude::*;
 use rustc_hash::FxHashSet;
@@ -18,6 +20,168 @@ use rustc_hash::FxHashSet;
 pub fn hooks(cmd: &HookCommands) -> Result<(), Error> {
     match cmd {
         HookCommands::CheckLargeFiles(inner_cmd) => check_large_files(inner_cmd),
+        HookCommands::CheckMergeConflict(inner_cmd) => check_merge_conflict(inner_cmd),
+        HookCommands::CheckJson(inner_cmd) => check_json(inner_cmd),
+        HookCommands::CheckKernel(inner_cmd) => check_kernel(inner_cmd),
+        HookCommands::CheckFilterInstalled(inner_cmd) => check_filter_installed(inner_cmd),
+    }
+}
+
+fn is_notebook(path: &Path) -> bool {
+    path.extension().and_then(OsStr::to_str) == Some("ipynb")
+}
+
+/// Lines that git inserts when a merge leaves conflicts in a file.
+const CONFLICT_MARKERS: [&str; 3] = ["<<<<<<< ", "=======", ">>>>>>> "];
+
+fn is_conflict_marker(line: &str) -> bool {
+    let line = line.trim_end_matches(['\r', '\n']);
+    line == "=======" || CONFLICT_MARKERS.iter().any(|m| line.starts_with(m))
+}
+
+/// The conflict markers among the lines of a cell source. A `=======` line only counts after a
+/// `<<<<<<< ` line, as it also underlines markdown headings.
+fn source_conflict_markers<'a>(lines: &[&'a str]) -> Vec<&'a str> {
+    let [begin, separator, end] = CONFLICT_MARKERS;
+    let mut in_conflict = false;
+    lines
+        .iter()
+        .copied()
+        .filter(|line| {
+            let line = line.trim_end_matches(['\r', '\n']);
+            if line.starts_with(begin) {
+                in_conflict = true;
+                true
+            } else if line.starts_with(end) {
+                in_conflict = false;
+                true
+            } else {
+                in_conflict && line == separator
+            }
+        })
+        .collect()
+}
+
+fn source_lines(source: &SourceValue) -> Vec<&str> {
+    match source {
+        SourceValue::String(s) => s.lines().collect(),
+        SourceValue::StringArray(lines) => lines.iter().flat_map(|l| l.lines()).collect(),
+    }
+}
+
+/// Locations of merge conflict markers in the raw file and, if it parses, in its cell sources.
+fn find_merge_conflicts(contents: &str) -> Vec<String> {
+    let mut found: Vec<String> = contents
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| is_conflict_marker(line))
+        .map(|(i, line)| format!("line {}: {}", i + 1, line.trim_end()))
+        .collect();
+    if let Ok(nb) = serde_json::from_str::<RawNotebook>(contents) {
+        for (cell_number, cell) in nb.cells.iter().enumerate() {
+            found.extend(
+                source_conflict_markers(&source_lines(cell.get_source()))
+                    .into_iter()
+                    .map(|line| format!("cell {cell_number}: {}", line.trim_end())),
+            );
+        }
+    }
+    found
+}
+
+fn check_merge_conflict(cmd: &CheckMergeConflictCommand) -> Result<(), Error> {
+    let mut status = false;
+    for file in cmd.filenames.iter().filter(|f| is_notebook(f)) {
+        let contents = std::fs::read_to_string(file)?;
+        for conflict in find_merge_conflicts(&contents) {
+            println!(
+                "{}: merge conflict marker at {conflict}",
+                file.to_string_lossy()
+            );
+            status = true;
+        }
+    }
+    if status {
+        bail!("Some notebooks contain merge conflict markers")
+    }
+    Ok(())
+}
+
+/// Describe why `contents` is not a valid notebook, with the line and column of the problem.
+fn validate_json(contents: &[u8]) -> Option<String> {
+    let err = match serde_json::from_slice::<serde_json::Value>(contents) {
+        Ok(_) => serde_json::from_slice::<RawNotebook>(contents).err()?,
+        Err(err) => err,
+    };
+    let kind = if err.is_data() {
+        "does not follow the notebook schema"
+    } else {
+        "is not valid JSON"
+    };
+    let message = err.to_string();
+    let message = message
+        .rsplit_once(" at line ")
+        .map_or(message.as_str(), |(m, _)| m);
+    Some(format!(
+        "{}:{}: {kind}: {message}",
+        err.line(),
+        err.column()
+    ))
+}
+
+fn check_json(cmd: &CheckJsonCommand) -> Result<(), Error> {
+    let mut status = false;
+    for file in cmd.filenames.iter().filter(|f| is_notebook(f)) {
+        if let Some(problem) = validate_json(&std::fs::read(file)?) {
+            println!("{}:{problem}", file.to_string_lossy());
+            status = true;
+        }
+    }
+    if status {
+        bail!("Some notebooks are not valid")
+    }
+    Ok(())
+}
+
+fn check_kernel(cmd: &CheckKernelCommand) -> Result<(), Error> {
+    let mut status = false;
+    for file in cmd.filenames.iter().filter(|f| is_notebook(f)) {
+        let Ok(nb) = read_nb(file) else {
+            println!("Could not parse nb file {}", file.to_string_lossy());
+            status = true;
+            continue;
+        };
+        let Some(kernel) = nb
+            .metadata
+            .pointer("/kernelspec/name")
+            .and_then(serde_json::Value::as_str)
+        else {
+            continue;
+        };
+        if !cmd.allowed_kernels.iter().any(|k| k == kernel) {
+            println!(
+                "{} uses kernel {kernel:?}, which is not one of {}",
+                file.to_string_lossy(),
+                cmd.allowed_kernels.join(", ")
+            );
+            status = true;
+        }
+    }
+    if status {
+        bail!("Some notebooks use kernels that are not allowed")
+    }
+    Ok(())
+}
+
+fn check_filter_installed(cmd: &CheckFilterInstalledCommand) -> Result<(), Error> {
+    if !cmd.filenames.is_empty() && !cmd.filenames.iter().any(|f| is_notebook(f)) {
+        return Ok(());
+    }
+    let check_result = check_install_none_type();
+    if check_should_exit_zero(false) {
+        Ok(())
+    } else {
+        check_result
     }
 }
 const DEFAULT_MAX_SIZE_KB: u64 = 500; // 500 KB
@@ -27,10 +191,11 @@ fn check_normal_filesize<P: AsRef<Path>>(path: P) -> Result<u64, Error> {
 fn check_large_files(cmd: &CheckLargeFilesCommand) -> Result<(), Error> {
     let max_size_kb = cmd.maxkb.unwrap_or(DEFAULT_MAX_SIZE_KB);
     let mut files: FxHashSet<PathBuf> = cmd.filenames.iter().map(PathBuf::to_owned).collect();
-    filter_lfs(&mut files)?;
+    let repo = gix::discover(".").context("Not inside a git repository")?;
+    filter_lfs(&repo, &mut files, &cmd.lfs_filters)?;
     if !cmd.enforce_all {
-        let added = get_added_files()?;
-        files = &files & &added;
+        let added = get_added_files(&repo)?;
+        files.retain(|f| added.contains(&normalize_path(f)));
     }
     let lazy_settings = SizeFinder::new();
 
@@ -121,73 +286,102 @@ impl SizeFinder {
 
         let nb = read_nb(path)?;
 
-        let (stripped_nb, _) = strip_nb(nb, binding.as_ref().expect("settings should be loaded"));
-        drop(binding); // release lock early at clippy's suggestion
-        let mut out: Vec<u8> = Vec::new();
-
-        write_nb(&mut out, &stripped_nb)?;
-        Ok(out.len() as u64)
+        Ok(stripped_size(nb, x)?)
     }
 }
 
-fn get_added_files() -> Result<FxHashSet<PathBuf>, Error> {
-    let output = Command::new("git")
-        .args(["diff", "--name-only", "--staged", "--diff-filter=A"])
-        .output()?;
-    if !output.status.success() {
-        bail!("Git diff failed");
-    }
-    let stdout = String::from_utf8(output.stdout)?;
-    stdout
-        .lines()
-        .map(PathBuf::from_str)
-        .collect::<Result<FxHashSet<PathBuf>, _>>()
-        .map_err(Error::from)
-}
-
-fn filter_lfs(files: &mut FxHashSet<PathBuf>) -> Result<(), Error> {
-    let file_list = Itertools::intersperse(
-        files.iter().filter_map(|p| p.to_str().map(str::as_bytes)),
-        b"\0",
-    )
-    .collect::<Vec<&[u8]>>()
-    .concat();
-
-    let mut check_attr = Command::new("git")
-        .args(["check-attr", "filter", "-z", "--stdin"])
-        .stdin(Stdio::piped())
-        .stdout(Stdio::piped())
-        .stderr(Stdio::piped())
-        .spawn()?;
-    {
-        let mut stdin = check_attr.stdin.take().context("Could not open stdin")?;
-        // git may exit without reading stdin (e.g. outside a repository);
-        // ignore the broken pipe so the exit-status check reports the real error
-        match stdin.write_all(&file_list) {
-            Err(e) if e.kind() != std::io::ErrorKind::BrokenPipe => return Err(e.into()),
-            _ => {}
-        }
-    }
-    let check_output = check_attr.wait_with_output()?;
-    if !check_output.status.success() {
-        bail!("Git check-attr failed");
+/// Absolute paths of files that are staged in the index but absent from `HEAD`.
+///
+/// As with `git diff --cached --diff-filter=A`, copies and renamed files with edits count as
+/// added.
+fn get_added_files(repo: &gix::Repository) -> Result<FxHashSet<PathBuf>, Error> {
+    let work_dir = repo
+        .workdir()
+        .map(normalize_path)
+        .context("Cannot check added files in a bare repository")?;
+    let mut head_files = gix::traverse::tree::Recorder::default();
+    repo.head_tree_id_or_empty()?
+        .object()?
+        .into_tree()
+        .traverse()
+        .breadthfirst(&mut head_files)?;
+    let head_files: FxHashSet<_> = head_files
+        .records
+        .into_iter()
+        .map(|entry| entry.filepath)
+        .collect();
+
+    let index = repo.index_or_empty()?;
+    Ok(index
+        .entries()
+        .iter()
+        .map(|entry| entry.path(&index))
+        .filter(|path| !head_files.contains(*path))
+        .map(|path| work_dir.join(gix::path::from_bstr(path)))
+        .collect())
+}
+
+/// Whether the git filter `name` stores file contents outside the repository, as git-lfs does.
+fn is_large_file_filter(
+    config: &gix::config::Snapshot,
+    name: &str,
+    lfs_filters: &[String],
+) -> bool {
+    if name == "lfs" || lfs_filters.iter().any(|f| f == name) {
+        return true;
     }
-    let stdout = String::from_utf8(check_output.stdout)?;
+    ["process", "clean"].iter().any(|key| {
+        config
+            .string(format!("filter.{name}.{key}").as_str())
+            .is_some_and(|cmd| cmd.starts_with(b"git-lfs") || cmd.starts_with(b"git lfs"))
+    })
+}
 
-    let parts = stdout.trim_matches('\0').split('\0');
+/// Remove files whose `filter` attribute points at git-lfs or a similar large file store.
+///
+/// Attributes are read from `.gitattributes` files in the work tree, `.git/info/attributes`
+/// and the global and system attribute files. A file with the `filter` attribute unset
+/// (`-filter`) is always kept.
+fn filter_lfs(
+    repo: &gix::Repository,
+    files: &mut FxHashSet<PathBuf>,
+    lfs_filters: &[String],
+) -> Result<(), Error> {
+    let Some(work_dir) = repo.workdir().map(normalize_path) else {
+        return Ok(());
+    };
+    let index = repo.index_or_empty()?;
+    let mut attributes = repo.attributes_only(
+        &index,
+        gix::worktree::stack::state::attributes::Source::WorktreeThenIdMapping,
+    )?;
+    let mut outcome = attributes.selected_attribute_matches(["filter"]);
+    let config = repo.config_snapshot();
 
-    for chunk in &parts.chunks(3) {
-        let mut chunk = chunk;
-        // 1st element index 0
-        let fname = chunk.next();
-        // 3rd element index 2, but we already consumed one...
-        let info = chunk.nth(1);
-        if let (Some(fname), Some(info)) = (fname.map(PathBuf::from), info)
-            && info == "lfs"
-        {
-            files.remove(&fname);
+    let mut lfs_files = vec![];
+    for file in files.iter() {
+        let Ok(relative) = normalize_path(file)
+            .strip_prefix(&work_dir)
+            .map(Path::to_owned)
+        else {
+            continue;
+        };
+        outcome.reset();
+        attributes
+            .at_path(&relative, None)?
+            .matching_attributes(&mut outcome);
+        let is_lfs = outcome.iter_selected().any(|m| match m.assignment.state {
+            StateRef::Value(name) => {
+                is_large_file_filter(&config, &name.as_bstr().to_string(), lfs_filters)
+            }
+            _ => false,
+        });
+        if is_lfs {
+            lfs_files.push(file.clone());
         }
     }
-
+    for file in lfs_files {
+        files.remove(&file);
+    }
     Ok(())
 }
@@ -1,21 +1,13 @@
-use anyhow::Error;
-use gix_attributes::{AssignmentRef, StateRef, parse::Kind};
+use anyhow::{Error, bail};
+use gix::attrs::{AssignmentRef, StateRef, parse::Kind};
 
-use std::{
-    fmt::Write as _,
-    fs,
-    io::{BufRead, BufReader},
-    path::Path,
-    path::PathBuf,
-};
-
-use std::{collections::BTreeMap, io::Write};
+use std::{collections::BTreeMap, fmt::Write as _, fs, io::Write as _, path::Path, path::PathBuf};
 
 use super::{InstallStatus, get_git_repo_and_work_tree};
 use crate::cli::GitConfigType;
 use itertools::Itertools;
 
-fn resolve_attribute_file(
+pub(super) fn resolve_attribute_file(
     config_type: GitConfigType,
     attribute_file: Option<&Path>,
 ) -> Result<PathBuf, Error> {
@@ -25,25 +17,25 @@ fn resolve_attribute_file(
         let cur_dir = std::env::current_dir()?;
 
         let source = match config_type {
-            GitConfigType::Global => gix_attributes::Source::Git,
-            GitConfigType::Local => gix_attributes::Source::Local,
-            GitConfigType::System => gix_attributes::Source::System,
+            GitConfigType::Global => gix::attrs::Source::Git,
+            GitConfigType::Local => gix::attrs::Source::Local,
+            GitConfigType::System => gix::attrs::Source::System,
         };
 
         let file_path: PathBuf = match config_type {
             #[allow(clippy::unwrap_used)]
             GitConfigType::Global | GitConfigType::System => source
-                .storage_location(&mut gix_path::env::var)
+                .storage_location(&mut gix::path::env::var)
                 .as_deref()
                 .unwrap()
                 .to_owned(),
             GitConfigType::Local => {
-                let dotgit = gix_discover::upwards(&cur_dir)?
+                let dotgit = gix::discover::upwards(&cur_dir)?
                     .0
                     .into_repository_and_work_tree_directories()
                     .0;
                 #[allow(clippy::unwrap_used)]
-                dotgit.join(source.storage_location(&mut gix_path::env::var).unwrap())
+                dotgit.join(source.storage_location(&mut gix::path::env::var).unwrap())
             }
         };
         Ok(file_path)
@@ -62,123 +54,139 @@ fn get_default_attribute_file() -> Result<Option<PathBuf>, Error> {
     }
 }
 
+/// The `.gitattributes` file at the root of the work tree, which is committed with the project.
+pub fn project_attribute_file() -> Result<PathBuf, Error> {
+    let Some(work_dir) = get_git_repo_and_work_tree()?.1 else {
+        bail!("A project install needs a repository with a work tree");
+    };
+    Ok(work_dir.join(".gitattributes"))
+}
+
 const ATTRIBUTE_LINES: &[&str; 2] = &["*.ipynb filter=nbwipers", "*.ipynb diff=nbwipers"];
 
+/// The text to append to `contents` to add the missing nbwipers attribute lines, or `None` if
+/// they are all there already.
+pub(super) fn missing_attribute_lines(contents: &[u8]) -> Option<String> {
+    #[allow(clippy::unwrap_used)]
+    let to_add_values = ATTRIBUTE_LINES
+        .iter()
+        .map(|x| gix::attrs::parse(x.as_bytes()).next().unwrap().unwrap())
+        .flat_map(|(kind, rhs, _)| {
+            rhs.filter_map(Result::ok)
+                .map(move |a| (kind.clone(), a.to_owned()))
+        });
+
+    let mut to_add: BTreeMap<_, _> = to_add_values.zip(ATTRIBUTE_LINES).collect();
+    let extra = match contents.last() {
+        None | Some(&b'\n') => "",
+        _ => "\n",
+    };
+    let lines = gix::attrs::parse(contents);
+
+    for (kind, x, _) in lines.filter_map(Result::ok) {
+        for ass in x.filter_map(Result::ok) {
+            to_add.remove(&(kind.clone(), ass.to_owned()));
+        }
+    }
+    if to_add.is_empty() {
+        return None;
+    }
+    Some(format!("{}{}\n", extra, to_add.values().join("\n")))
+}
+
 pub fn install_attributes(
     config_type: GitConfigType,
     attribute_file: Option<&Path>,
 ) -> Result<(), Error> {
     let file_path = resolve_attribute_file(config_type, attribute_file)?;
-    if file_path.is_file() {
-        let attribute_bytes = fs::read(&file_path)?;
-
-        // let to_add_str = to_add_lines.join("\n").as_bytes();
-        #[allow(clippy::unwrap_used)]
-        let to_add_values = ATTRIBUTE_LINES
-            .iter()
-            .map(|x| gix_attributes::parse(x.as_bytes()).next().unwrap().unwrap())
-            .flat_map(|(kind, rhs, _)| {
-                rhs.filter_map(Result::ok)
-                    .map(move |a| (kind.clone(), a.to_owned()))
-            });
-
-        let mut to_add: BTreeMap<_, _> = to_add_values.zip(ATTRIBUTE_LINES).collect();
-        let extra = match attribute_bytes.last() {
-            None | Some(&b'\n') => "",
-            _ => "\n",
-        };
-        let lines = gix_attributes::parse(&attribute_bytes);
-
-        for (kind, x, _) in lines.filter_map(Result::ok) {
-            //
-            for ass in x.filter_map(Result::ok) {
-                to_add.remove(&(kind.clone(), ass.to_owned()));
-            }
-        }
-        if !to_add.is_empty() {
-            println!("Writing to {}", file_path.display());
-
-            let mut writer = fs::OpenOptions::new()
-                .create(true)
-                .append(true)
-                .open(&file_path)?;
-
-            writeln!(writer, "{}{}", extra, to_add.values().join("\n"))?;
-        }
+    let contents = if file_path.is_file() {
+        fs::read(&file_path)?
     } else {
-        println!("Writing to {}", file_path.display());
         if let Some(parent) = file_path.parent() {
             fs::create_dir_all(parent)?;
         }
-        let mut writer = fs::File::create(file_path)?;
-
-        for line in ATTRIBUTE_LINES {
-            writeln!(writer, "{line}")?;
-        }
+        vec![]
+    };
+    if let Some(lines) = missing_attribute_lines(&contents) {
+        println!("Writing to {}", file_path.display());
+        let mut writer = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&file_path)?;
+        writer.write_all(lines.as_bytes())?;
     }
 
     Ok(())
 }
 
+/// `contents` without the assignments for which `remove` is true, or `None` if there are none.
+///
+/// Lines left without any assignments are removed, as are blank lines.
+pub(super) fn remove_attributes(
+    contents: &str,
+    remove: impl Fn(&str, &AssignmentRef) -> bool,
+) -> Result<Option<String>, Error> {
+    let mut out = String::new();
+    let mut to_write = false;
+    for line in contents.lines() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        // the parser yields nothing for comment lines; preserve them verbatim
+        let (kind, x, _) = match gix::attrs::parse(line.as_bytes()).next() {
+            None => {
+                writeln!(out, "{line}")?;
+                continue;
+            }
+            Some(Ok(res)) => res,
+            Some(res) => res?,
+        };
+        let mut line = line.to_string();
+        if let Kind::Pattern(patt) = kind {
+            let patt = patt.to_string();
+            let to_delete = x
+                .into_iter()
+                .map(|x| {
+                    let x = x?;
+                    let delete = remove(&patt, &x);
+                    Ok((x, delete))
+                })
+                .collect::<Result<Vec<(AssignmentRef, bool)>, gix::attrs::name::Error>>()?;
+            if to_delete.iter().any(|(_, y)| *y) {
+                to_write = true;
+                let assignments = to_delete
+                    .iter()
+                    .filter(|(_x, y)| !y)
+                    .map(|(x, _y)| x.to_string())
+                    .join(" ");
+                if assignments.is_empty() {
+                    line = String::new();
+                } else {
+                    line = format!("{patt} {assignments}");
+                }
+            }
+        }
+        if !line.is_empty() {
+            writeln!(out, "{line}")?;
+        }
+    }
+    Ok(to_write.then_some(out))
+}
+
 pub fn uninstall_attributes(
     config_type: GitConfigType,
     attribute_file: Option<&Path>,
 ) -> Result<(), Error> {
     let file_path = resolve_attribute_file(config_type, attribute_file)?;
     if file_path.is_file() {
-        let f = BufReader::new(fs::File::open(&file_path)?);
-        let mut out = String::new();
-        let mut to_write = false;
-        for line in f.lines() {
-            let mut line = line?;
-            if line.trim().is_empty() {
-                continue;
-            }
-            // the parser yields nothing for comment lines; preserve them verbatim
-            let (kind, x, _) = match gix_attributes::parse(line.as_bytes()).next() {
-                None => {
-                    writeln!(out, "{line}")?;
-                    continue;
-                }
-                Some(Ok(res)) => res,
-                Some(res) => res?,
-            };
-            if let Kind::Pattern(patt) = kind
-                && patt.to_string() == "*.ipynb"
-            {
-                let to_delete = x
-                    .into_iter()
-                    .map(|x| {
-                        let x = x?;
-                        if let StateRef::Value(s) = x.state {
-                            Ok((x, s.as_bstr() == "nbwipers"))
-                        } else {
-                            Ok((x, false))
-                        }
-                    })
-                    .collect::<Result<Vec<(AssignmentRef, bool)>, gix_attributes::name::Error>>()?;
-                if to_delete.iter().any(|(_, y)| *y) {
-                    to_write = true;
-                    let assignments = to_delete
-                        .iter()
-                        .filter(|(_x, y)| !y)
-                        .map(|(x, _y)| x.to_string())
-                        .join(" ");
-                    if assignments.is_empty() {
-                        line = String::new();
-                    } else {
-                        line = format!("{patt} {assignments}");
-                    }
-                }
-            };
-            if !line.is_empty() {
-                writeln!(out, "{line}")?;
-            }
-        }
-        if to_write {
+        let contents = fs::read_to_string(&file_path)?;
+        let out = remove_attributes(&contents, |patt, assignment| {
+            patt == "*.ipynb"
+                && matches!(assignment.state, StateRef::Value(s) if s.as_bstr() == "nbwipers")
+        })?;
+        if let Some(out) = out {
             println!("Removing entries from {}", file_path.display());
-            let mut f = fs::File::create(&file_path)?;
-            write!(f, "{out}")?;
+            fs::write(&file_path, out)?;
         }
     } else {
         println!("Attribute file does not exist. Nothing to do.");
@@ -191,7 +199,7 @@ fn check_attribute_file(attr_file_path: &Path) -> Result<InstallStatus, Error> {
         let mut status = InstallStatus::default();
 
         let bytes = fs::read(attr_file_path)?;
-        let lines = gix_attributes::parse(&bytes);
+        let lines = gix::attrs::parse(&bytes);
         for line in lines {
             let (kind, assignments, _) = line?;
 
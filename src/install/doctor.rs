@@ -0,0 +1,386 @@
+use std::{
+    fmt::{Display, Formatter},
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use anyhow::Error;
+use bstr::ByteSlice;
+use gix::attrs::{
+    StateRef,
+    glob::{Pattern, pattern::Case, wildmatch},
+    parse::Kind,
+};
+use gix::config::{File, Source};
+use serde::Serialize;
+
+use super::{
+    attributes::resolve_attribute_file,
+    get_git_repo_and_work_tree,
+    gitconfig::{command_program, find_on_path},
+    installed,
+};
+use crate::cli::GitConfigType;
+
+/// Git config keys that make up an install of nbwipers or nbstripout.
+const CONFIG_KEYS: &[(&str, &str, &str)] = &[
+    ("filter", "nbwipers", "clean"),
+    ("filter", "nbwipers", "smudge"),
+    ("diff", "nbwipers", "textconv"),
+    ("filter", "nbstripout", "clean"),
+    ("filter", "nbstripout", "smudge"),
+    ("diff", "ipynb", "textconv"),
+];
+
+/// Attributes that git uses to run a tool on notebooks.
+const ATTRIBUTE_NAMES: &[&str] = &["filter", "diff"];
+
+/// Notebook paths, at the top of the repository and in a directory, that attribute patterns are
+/// matched against to tell whether they apply to notebooks.
+const SAMPLE_NOTEBOOKS: &[&str] = &["notebook.ipynb", "notebooks/notebook.ipynb"];
+
+/// Where and how nbwipers and nbstripout are set up, as reported by `check-install --report`.
+#[derive(Debug, Clone, Serialize)]
+pub struct InstallReport {
+    /// The tools set up in both the git config and the attributes.
+    pub installed: Vec<&'static str>,
+    pub config_keys: Vec<ConfigKeyReport>,
+    pub attribute_files: Vec<AttributeFileReport>,
+    /// The driver git uses for each attribute of `*.ipynb` files, after precedence is applied.
+    pub effective_attributes: Vec<AttributeAssignment>,
+    pub conflicts: Vec<String>,
+}
+
+/// A definition of one of the config keys of an install.
+#[derive(Debug, Clone, Serialize)]
+pub struct ConfigKeyReport {
+    pub key: String,
+    pub scope: &'static str,
+    pub file: Option<PathBuf>,
+    pub command: String,
+    /// Whether this definition is the one git uses, rather than one overridden by a later file.
+    pub effective: bool,
+    pub executable: Option<ExecutableReport>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ExecutableReport {
+    /// The program as written in the command.
+    pub program: String,
+    /// The program's location, found on PATH if it is not an absolute path.
+    pub path: Option<PathBuf>,
+    pub exists: bool,
+    /// The version reported by the program, if it is nbwipers.
+    pub version: Option<String>,
+    pub version_matches: Option<bool>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct AttributeFileReport {
+    pub scope: &'static str,
+    pub path: PathBuf,
+    pub exists: bool,
+    pub assignments: Vec<AttributeAssignment>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct AttributeAssignment {
+    pub attribute: String,
+    pub value: String,
+    pub file: PathBuf,
+}
+
+const fn source_scope(source: Source) -> &'static str {
+    match source {
+        Source::GitInstallation => "installation",
+        Source::System => "system",
+        Source::Git | Source::User => "global",
+        Source::Local => "local",
+        Source::Worktree => "worktree",
+        Source::Env | Source::EnvOverride => "environment",
+        Source::Cli | Source::Api => "command line",
+    }
+}
+
+fn executable_report(command: &str) -> Option<ExecutableReport> {
+    let program = command_program(command)?;
+    let program_path = Path::new(&program);
+    let path = if program_path.is_absolute() {
+        Some(program_path.to_path_buf())
+    } else {
+        find_on_path(&program)
+    };
+    let exists = path.as_ref().is_some_and(|path| path.is_file());
+    let is_nbwipers = program_path
+        .file_stem()
+        .is_some_and(|stem| stem == "nbwipers");
+    let version = path
+        .as_ref()
+        .filter(|_| exists && is_nbwipers)
+        .and_then(|path| Command::new(path).arg("--version").output().ok())
+        .filter(|output| output.status.success())
+        .and_then(|output| {
+            String::from_utf8_lossy(&output.stdout)
+                .split_whitespace()
+                .nth(1)
+                .map(str::to_string)
+        });
+    let version_matches = version
+        .as_deref()
+        .map(|version| version == env!("CARGO_PKG_VERSION"));
+    Some(ExecutableReport {
+        program,
+        path,
+        exists,
+        version,
+        version_matches,
+    })
+}
+
+fn config_key_reports(config: &File) -> Vec<ConfigKeyReport> {
+    let mut reports = vec![];
+    for (section_name, subsection, value_name) in CONFIG_KEYS {
+        let key = format!("{section_name}.{subsection}.{value_name}");
+        let first = reports.len();
+        for section in config
+            .sections_by_name(section_name)
+            .into_iter()
+            .flatten()
+            .filter(|section| section.header().subsection_name() == Some((*subsection).into()))
+        {
+            let Some(command) = section.value(value_name) else {
+                continue;
+            };
+            let command = command.to_str_lossy().into_owned();
+            reports.push(ConfigKeyReport {
+                key: key.clone(),
+                scope: source_scope(section.meta().source),
+                file: section.meta().path.clone(),
+                executable: executable_report(&command),
+                command,
+                effective: false,
+            });
+        }
+        // later definitions override earlier ones
+        if let Some(last) = reports[first..].last_mut() {
+            last.effective = true;
+        }
+    }
+    reports
+}
+
+/// The attribute files that can apply to notebooks, from lowest to highest precedence.
+fn attribute_files(config: &File) -> Result<Vec<(&'static str, PathBuf)>, Error> {
+    let mut files = vec![(
+        "system",
+        resolve_attribute_file(GitConfigType::System, None)?,
+    )];
+    let global = match config.string("core.attributesFile") {
+        Some(path) => {
+            let path = path.to_str_lossy().into_owned();
+            match (path.strip_prefix("~/"), gix::path::env::home_dir()) {
+                (Some(rest), Some(home)) => home.join(rest),
+                _ => PathBuf::from(path),
+            }
+        }
+        None => resolve_attribute_file(GitConfigType::Global, None)?,
+    };
+    files.push(("global", global));
+    if let Some(work_dir) = get_git_repo_and_work_tree()?.1 {
+        files.push(("repository", work_dir.join(".gitattributes")));
+    }
+    files.push(("info", resolve_attribute_file(GitConfigType::Local, None)?));
+    Ok(files)
+}
+
+/// Whether an attribute pattern such as `*.ipynb`, `**/*.ipynb` or `notebooks/*.ipynb` applies to
+/// notebooks, matching it as git does against sample notebook paths.
+fn matches_notebooks(pattern: &Pattern) -> bool {
+    SAMPLE_NOTEBOOKS.iter().any(|path| {
+        let path = path.as_bytes().as_bstr();
+        pattern.matches_repo_relative_path(
+            path,
+            path.rfind_byte(b'/').map(|pos| pos + 1),
+            Some(false),
+            Case::Sensitive,
+            wildmatch::Mode::NO_MATCH_SLASH_LITERAL,
+        )
+    })
+}
+
+fn attribute_file_report(scope: &'static str, path: PathBuf) -> AttributeFileReport {
+    let mut assignments = vec![];
+    let bytes = fs::read(&path).ok();
+    for (kind, line_assignments, _) in bytes
+        .as_deref()
+        .map(gix::attrs::parse)
+        .into_iter()
+        .flatten()
+        .filter_map(Result::ok)
+    {
+        let Kind::Pattern(pattern) = kind else {
+            continue;
+        };
+        if !matches_notebooks(&pattern) {
+            continue;
+        }
+        for assignment in line_assignments.filter_map(Result::ok) {
+            let name = assignment.name.as_str();
+            if !ATTRIBUTE_NAMES.contains(&name) {
+                continue;
+            }
+            let value = match assignment.state {
+                StateRef::Value(value) => value.as_bstr().to_str_lossy().into_owned(),
+                StateRef::Set => "set".to_string(),
+                StateRef::Unset => "unset".to_string(),
+                StateRef::Unspecified => "unspecified".to_string(),
+            };
+            assignments.push(AttributeAssignment {
+                attribute: name.to_string(),
+                value,
+                file: path.clone(),
+            });
+        }
+    }
+    AttributeFileReport {
+        scope,
+        exists: bytes.is_some(),
+        path,
+        assignments,
+    }
+}
+
+fn find_conflicts(
+    attribute_files: &[AttributeFileReport],
+    effective_attributes: &[AttributeAssignment],
+) -> Vec<String> {
+    let mut conflicts = vec![];
+    for effective in effective_attributes {
+        for overridden in attribute_files
+            .iter()
+            .flat_map(|file| &file.assignments)
+            .filter(|a| a.attribute == effective.attribute && a.value != effective.value)
+        {
+            conflicts.push(format!(
+                "{}={} in {} is overridden by {}={} in {}",
+                overridden.attribute,
+                overridden.value,
+                overridden.file.display(),
+                effective.attribute,
+                effective.value,
+                effective.file.display()
+            ));
+        }
+        if !["nbwipers", "nbstripout", "ipynb"].contains(&effective.value.as_str()) {
+            conflicts.push(format!(
+                "*.ipynb files use {}={} from {}, so neither nbwipers nor nbstripout runs",
+                effective.attribute,
+                effective.value,
+                effective.file.display()
+            ));
+        }
+    }
+    conflicts
+}
+
+/// Report on the install for `config_type`. The whole report is always collected, but whether
+/// a tool counts as installed only considers `config_type`, as for `check-install`.
+pub fn install_report(config_type: Option<GitConfigType>) -> Result<InstallReport, Error> {
+    let config = File::from_git_dir(get_git_repo_and_work_tree()?.0)?;
+    let attribute_files: Vec<_> = attribute_files(&config)?
+        .into_iter()
+        .map(|(scope, path)| attribute_file_report(scope, path))
+        .collect();
+    let effective_attributes: Vec<_> = ATTRIBUTE_NAMES
+        .iter()
+        .filter_map(|name| {
+            attribute_files
+                .iter()
+                .flat_map(|file| &file.assignments)
+                .rfind(|a| a.attribute == *name)
+                .cloned()
+        })
+        .collect();
+    let conflicts = find_conflicts(&attribute_files, &effective_attributes);
+    Ok(InstallReport {
+        installed: installed(config_type)?,
+        config_keys: config_key_reports(&config),
+        attribute_files,
+        effective_attributes,
+        conflicts,
+    })
+}
+
+impl Display for InstallReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if self.installed.is_empty() {
+            writeln!(f, "Neither nbstripout nor nbwipers are installed.")?;
+        }
+        for tool in &self.installed {
+            writeln!(f, "{tool} is installed")?;
+        }
+        writeln!(f, "Git config:")?;
+        if self.config_keys.is_empty() {
+            writeln!(f, "  no filter or diff driver for notebooks is configured")?;
+        }
+        for key in &self.config_keys {
+            let file = key
+                .file
+                .as_ref()
+                .map_or_else(String::new, |file| format!(" ({})", file.display()));
+            let overridden = if key.effective { "" } else { " [overridden]" };
+            writeln!(
+                f,
+                "  {} = {} [{}{file}]{overridden}",
+                key.key, key.command, key.scope
+            )?;
+            if let Some(executable) = &key.executable {
+                match &executable.path {
+                    Some(path) if executable.exists => {
+                        write!(f, "    {} found at {}", executable.program, path.display())?;
+                    }
+                    _ => write!(f, "    {} not found", executable.program)?,
+                }
+                match (&executable.version, executable.version_matches) {
+                    (Some(version), Some(false)) => writeln!(
+                        f,
+                        ", version {version} differs from this nbwipers ({})",
+                        env!("CARGO_PKG_VERSION")
+                    )?,
+                    (Some(version), _) => writeln!(f, ", version {version}")?,
+                    (None, _) => writeln!(f)?,
+                }
+            }
+        }
+        writeln!(f, "Attribute files:")?;
+        for file in &self.attribute_files {
+            let status = if !file.exists {
+                "does not exist".to_string()
+            } else if file.assignments.is_empty() {
+                "no notebook attributes".to_string()
+            } else {
+                file.assignments
+                    .iter()
+                    .map(|a| format!("{}={}", a.attribute, a.value))
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            };
+            writeln!(f, "  {} ({}): {status}", file.scope, file.path.display())?;
+        }
+        writeln!(f, "Effective attributes for *.ipynb:")?;
+        if self.effective_attributes.is_empty() {
+            writeln!(f, "  none")?;
+        }
+        for a in &self.effective_attributes {
+            writeln!(f, "  {}={} from {}", a.attribute, a.value, a.file.display())?;
+        }
+        if !self.conflicts.is_empty() {
+            writeln!(f, "Conflicts:")?;
+            for conflict in &self.conflicts {
+                writeln!(f, "  {conflict}")?;
+            }
+        }
+        Ok(())
+    }
+}
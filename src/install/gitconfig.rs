@@ -2,34 +2,89 @@ use anyhow::{Error, anyhow};
 
 use std::{fs, io::BufWriter, path::Path, path::PathBuf};
 
-use gix_config::{Source, parse::section::ValueName};
+use gix::config::{Source, parse::section::ValueName};
 
-use super::{InstallStatus, InstallToolStatus, get_git_repo_and_work_tree};
-use bstr::BStr;
+use super::{InstallStatus, InstallToolStatus, StaleCommand, get_git_repo_and_work_tree};
+use bstr::{BStr, ByteSlice};
 
-use crate::cli::GitConfigType;
+use crate::cli::{FilterCommand, GitConfigType};
 
-pub fn install_config(config_file: Option<&Path>, config_type: GitConfigType) -> Result<(), Error> {
-    let cur_exe = std::env::current_exe()?;
+/// The start of the filter commands, which runs nbwipers as chosen by `command`.
+pub(super) fn filter_executable(command: FilterCommand) -> Result<String, Error> {
+    match command {
+        FilterCommand::Absolute => {
+            let cur_exe = std::env::current_exe()?;
+            let cur_exe_str = cur_exe
+                .to_str()
+                .map_or_else(
+                    || Err(anyhow!("Executable path cannot be converted to unicode")),
+                    Ok,
+                )?
+                .replace('\\', "/");
+            Ok(format!("\"{cur_exe_str}\""))
+        }
+        FilterCommand::Path => {
+            if find_on_path("nbwipers").is_none() {
+                eprintln!(
+                    "Warning: nbwipers was not found on PATH, so git will not be able to run it"
+                );
+            }
+            Ok("nbwipers".to_string())
+        }
+        FilterCommand::Python => Ok("python -m nbwipers".to_string()),
+    }
+}
+
+pub(super) fn find_on_path(name: &str) -> Option<PathBuf> {
+    let exe_name = format!("{name}{}", std::env::consts::EXE_SUFFIX);
+    std::env::var_os("PATH").and_then(|paths| {
+        std::env::split_paths(&paths)
+            .map(|dir| dir.join(&exe_name))
+            .find(|path| path.is_file())
+    })
+}
+
+/// The git config file at `file_path`, or an empty one if it does not exist yet.
+pub(super) fn read_config_file(
+    file_path: &Path,
+    config_type: GitConfigType,
+) -> Result<gix::config::File<'static>, Error> {
     let source = config_type.into();
-    let cur_exe_str = cur_exe
-        .to_str()
-        .map_or_else(
-            || Err(anyhow!("Executable path cannot be converted to unicode")),
-            Ok,
-        )?
-        .replace('\\', "/");
+    if file_path.is_file() {
+        Ok(gix::config::File::from_path_no_includes(
+            file_path.to_path_buf(),
+            source,
+        )?)
+    } else {
+        Ok(gix::config::File::new(gix::config::file::Metadata::from(
+            source,
+        )))
+    }
+}
+
+pub fn install_config(
+    config_file: Option<&Path>,
+    config_type: GitConfigType,
+    command: FilterCommand,
+) -> Result<(), Error> {
+    let executable = filter_executable(command)?;
     let file_path = resolve_config_file(config_file, config_type)?;
 
-    let mut file = if file_path.is_file() {
-        gix_config::File::from_path_no_includes(file_path.clone(), source)?
-    } else {
-        if let Some(parent) = file_path.parent() {
-            fs::create_dir_all(parent)?;
-        }
-        gix_config::File::new(gix_config::file::Metadata::from(source))
-    };
+    let mut file = read_config_file(&file_path, config_type)?;
+    if let Some(parent) = file_path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    add_nbwipers_sections(&mut file, &executable);
+    println!("Writing to {}", file_path.display());
+    {
+        let mut writer = BufWriter::new(fs::File::create(file_path)?);
+        file.write_to(&mut writer)?;
+    }
+    Ok(())
+}
 
+/// Set the filter and diff driver sections that run nbwipers through `executable`.
+pub(super) fn add_nbwipers_sections(file: &mut gix::config::File, executable: &str) {
     // fails for invalid section names. This one is ok
     #[allow(clippy::unwrap_used)]
     let mut nbwipers_section = file
@@ -40,18 +95,14 @@ pub fn install_config(config_file: Option<&Path>, config_type: GitConfigType) ->
     nbwipers_section.set(
         ValueName::try_from("clean").unwrap(),
         BStr::new(
-            format!(
-                "\"{}\" clean - --stdin-file-name %f --respect-exclusions",
-                cur_exe_str.as_str()
-            )
-            .as_str(),
+            format!("{executable} clean - --stdin-file-name %f --respect-exclusions").as_str(),
         ),
     );
 
     #[allow(clippy::unwrap_used)]
     nbwipers_section.set(
         ValueName::try_from("smudge").unwrap(),
-        BStr::new(format!("\"{}\" smudge %f", cur_exe_str.as_str()).as_str()),
+        BStr::new(format!("{executable} smudge %f").as_str()),
     );
 
     // fails for invalid section names. This one is ok
@@ -64,14 +115,8 @@ pub fn install_config(config_file: Option<&Path>, config_type: GitConfigType) ->
     #[allow(clippy::unwrap_used)]
     diff_section.set(
         ValueName::try_from("textconv").unwrap(),
-        BStr::new(format!("\"{}\" clean --respect-exclusions -t", cur_exe_str.as_str()).as_str()),
+        BStr::new(format!("{executable} clean --respect-exclusions -t").as_str()),
     );
-    println!("Writing to {}", file_path.display());
-    {
-        let mut writer = BufWriter::new(fs::File::create(file_path)?);
-        file.write_to(&mut writer)?;
-    }
-    Ok(())
 }
 
 pub(super) fn resolve_config_file(
@@ -85,13 +130,13 @@ pub(super) fn resolve_config_file(
         #[allow(clippy::unwrap_used)]
         let file_path = match config_type {
             GitConfigType::Global | GitConfigType::System => source
-                .storage_location(&mut gix_path::env::var)
+                .storage_location(&mut gix::path::env::var)
                 .as_deref()
                 .unwrap()
                 .to_owned(),
             GitConfigType::Local => {
                 let dotgit = get_git_repo_and_work_tree()?.0;
-                dotgit.join(source.storage_location(&mut gix_path::env::var).unwrap())
+                dotgit.join(source.storage_location(&mut gix::path::env::var).unwrap())
             }
         };
         Ok(file_path)
@@ -104,7 +149,7 @@ pub fn uninstall_config(
     let source = config_type.into();
     let file_path = resolve_config_file(config_file, config_type)?;
     let mut file = if file_path.exists() {
-        gix_config::File::from_path_no_includes(file_path.clone(), source)?
+        gix::config::File::from_path_no_includes(file_path.clone(), source)?
     } else {
         println!("Config file does not exist. nothing to do.");
         return Ok(());
@@ -125,35 +170,122 @@ pub fn uninstall_config(
     Ok(())
 }
 
+/// The program run by a filter command, which may be quoted.
+pub(super) fn command_program(command: &str) -> Option<String> {
+    let command = command.trim();
+    let program = match command.strip_prefix('"') {
+        Some(rest) => rest.split('"').next()?,
+        None => command.split_whitespace().next()?,
+    };
+    Some(program.to_string())
+}
+
+/// The executable run by a filter command, if it is given as an absolute path.
+fn absolute_executable(command: &BStr) -> Option<PathBuf> {
+    let executable = PathBuf::from(command_program(command.to_str().ok()?)?);
+    executable.is_absolute().then_some(executable)
+}
+
+/// Whether a command of an install is set, and whether it can still run.
+enum CommandState {
+    Missing,
+    /// the command runs an executable, by its absolute path, that does not exist
+    Stale(PathBuf),
+    Runnable,
+}
+
+/// The state of the command for `value_name` in `section`.
+///
+/// Commands written with the absolute path of an executable break when the executable is moved
+/// or removed, for example by upgrading or recreating a virtual environment.
+fn command_state(section: &gix::config::file::Section, value_name: &str) -> CommandState {
+    let Some(command) = section.value(value_name) else {
+        return CommandState::Missing;
+    };
+    match absolute_executable(command.as_ref()) {
+        Some(executable) if !executable.is_file() => CommandState::Stale(executable),
+        _ => CommandState::Runnable,
+    }
+}
+
 fn check_config_sections(
-    filter_section: &Result<&gix_config::file::Section, gix_config::lookup::existing::Error>,
-    diff_section: &Result<&gix_config::file::Section, gix_config::lookup::existing::Error>,
+    filter_section: &Result<&gix::config::file::Section, gix::config::lookup::existing::Error>,
+    diff_section: &Result<&gix::config::file::Section, gix::config::lookup::existing::Error>,
+    filter_label: &str,
+    diff_label: &str,
+    stale: &mut Vec<StaleCommand>,
 ) -> InstallToolStatus {
+    let mut runnable =
+        |section: &gix::config::file::Section, label: &str, value_name: &str| match command_state(
+            section, value_name,
+        ) {
+            CommandState::Missing => false,
+            CommandState::Stale(executable) => {
+                stale.push(StaleCommand {
+                    key: format!("{label}.{value_name}"),
+                    executable,
+                });
+                false
+            }
+            CommandState::Runnable => true,
+        };
     InstallToolStatus {
         diff: diff_section
             .as_ref()
-            .is_ok_and(|x| x.contains_value_name("textconv")),
-        filter: filter_section
-            .as_ref()
-            .is_ok_and(|x| x.contains_value_name("clean") && x.contains_value_name("smudge")),
+            .is_ok_and(|x| runnable(x, diff_label, "textconv")),
+        filter: filter_section.as_ref().is_ok_and(|x| {
+            // check both, so that both are reported if stale
+            let clean = runnable(x, filter_label, "clean");
+            let smudge = runnable(x, filter_label, "smudge");
+            clean && smudge
+        }),
     }
 }
 
-pub(super) fn check_install_config_file(config_file: &gix_config::File) -> InstallStatus {
+pub(super) fn check_install_config_file(config_file: &gix::config::File) -> InstallStatus {
     let filter_section = config_file.section("filter", Some("nbwipers".into()));
     let diff_section = config_file.section("diff", Some("nbwipers".into()));
     let filter_section_nbstripout = config_file.section("filter", Some("nbstripout".into()));
     let diff_section_nbstripout = config_file.section("diff", Some("ipynb".into()));
 
+    let mut stale = vec![];
     InstallStatus {
-        nbstripout: check_config_sections(&filter_section_nbstripout, &diff_section_nbstripout),
-        nbwipers: check_config_sections(&filter_section, &diff_section),
+        nbstripout: check_config_sections(
+            &filter_section_nbstripout,
+            &diff_section_nbstripout,
+            "filter.nbstripout",
+            "diff.ipynb",
+            &mut stale,
+        ),
+        nbwipers: check_config_sections(
+            &filter_section,
+            &diff_section,
+            "filter.nbwipers",
+            "diff.nbwipers",
+            &mut stale,
+        ),
+        stale,
     }
 }
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[cfg(unix)]
+    #[test]
+    fn test_absolute_executable() {
+        assert_eq!(
+            absolute_executable(BStr::new("\"/opt/venv/bin/nbwipers\" smudge %f")),
+            Some(PathBuf::from("/opt/venv/bin/nbwipers"))
+        );
+        assert_eq!(
+            absolute_executable(BStr::new("/usr/bin/python3 -m nbstripout")),
+            Some(PathBuf::from("/usr/bin/python3"))
+        );
+        assert_eq!(absolute_executable(BStr::new("nbwipers smudge %f")), None);
+        assert_eq!(absolute_executable(BStr::new("python -m nbwipers")), None);
+    }
+
     #[test]
     fn test_resolve() {
         assert!(resolve_config_file(None, GitConfigType::Global).is_ok());
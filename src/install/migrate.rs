@@ -0,0 +1,318 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use anyhow::{Error, bail};
+use bstr::ByteSlice;
+use colored::Colorize;
+use gix::attrs::{AssignmentRef, StateRef};
+use similar::TextDiff;
+use toml::Value;
+
+use super::{
+    attributes::{missing_attribute_lines, remove_attributes, resolve_attribute_file},
+    get_git_repo_and_work_tree,
+    gitconfig::{add_nbwipers_sections, filter_executable, read_config_file, resolve_config_file},
+};
+use crate::{
+    cli::{GitConfigType, MigrateCommand},
+    config::find_settings,
+    extra_keys::ExtraKey,
+};
+
+/// nbstripout's boolean settings, the nbwipers setting they map to and the nbwipers value to
+/// use when the nbstripout setting is true. When false, both tools behave the same by default.
+const BOOLEAN_SETTINGS: &[(&str, &str, bool)] = &[
+    ("keepcount", "drop-count", false),
+    ("keepoutput", "drop-output", false),
+    ("dropemptycells", "drop-empty-cells", true),
+];
+
+/// A file rewritten by the migration.
+struct FileChange {
+    path: PathBuf,
+    before: String,
+    after: String,
+}
+
+impl FileChange {
+    fn new(path: PathBuf) -> Result<Self, Error> {
+        let before = if path.is_file() {
+            fs::read_to_string(&path)?
+        } else {
+            String::new()
+        };
+        Ok(Self {
+            path,
+            after: before.clone(),
+            before,
+        })
+    }
+
+    fn is_changed(&self) -> bool {
+        self.before != self.after
+    }
+
+    fn print_diff(&self) {
+        let path = self.path.display().to_string();
+        let diff = TextDiff::from_lines(&self.before, &self.after);
+        for line in diff.unified_diff().header(&path, &path).to_string().lines() {
+            if line.starts_with("---") || line.starts_with("+++") {
+                println!("{}", line.bold());
+            } else if line.starts_with("@@") {
+                println!("{}", line.cyan());
+            } else if line.starts_with('+') {
+                println!("{}", line.green());
+            } else if line.starts_with('-') {
+                println!("{}", line.red());
+            } else {
+                println!("{line}");
+            }
+        }
+    }
+
+    fn write(&self) -> Result<(), Error> {
+        println!("Writing to {}", self.path.display());
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&self.path, &self.after)?;
+        Ok(())
+    }
+}
+
+/// The nbwipers settings equivalent to the nbstripout settings in `config`.
+fn nbstripout_settings(config: &gix::config::File) -> Result<Vec<(&'static str, Value)>, Error> {
+    let mut settings = vec![];
+    if let Some(extra_keys) = config.string("filter.nbstripout.extrakeys") {
+        let extra_keys: Vec<_> = extra_keys
+            .to_str_lossy()
+            .split_whitespace()
+            .filter_map(|key| match ExtraKey::from_str(key) {
+                Ok(_) => Some(Value::String(key.to_string())),
+                Err(e) => {
+                    eprintln!("Skipping nbstripout extra key {key}: {e}");
+                    None
+                }
+            })
+            .collect();
+        if !extra_keys.is_empty() {
+            settings.push(("extra-keys", Value::Array(extra_keys)));
+        }
+    }
+    for (nbstripout_key, nbwipers_key, value) in BOOLEAN_SETTINGS {
+        let key = format!("filter.nbstripout.{nbstripout_key}");
+        if config.boolean(key.as_str()).transpose()? == Some(true) {
+            settings.push((nbwipers_key, Value::Boolean(*value)));
+        }
+    }
+    Ok(settings)
+}
+
+/// The nbwipers configuration file to write the migrated settings to.
+fn nbwipers_config_file(config: Option<&Path>) -> Result<PathBuf, Error> {
+    if let Some(config) = config {
+        return Ok(config.to_path_buf());
+    }
+    if let Some(config) = find_settings()? {
+        return Ok(config);
+    }
+    let dir = match get_git_repo_and_work_tree()?.1 {
+        Some(work_dir) => work_dir,
+        None => std::env::current_dir()?,
+    };
+    Ok(dir.join(".nbwipers.toml"))
+}
+
+/// `contents` of the nbwipers configuration file at `path` with `settings` added.
+///
+/// Settings already in the file are kept, so that the file is only ever added to. The new lines
+/// are inserted as text to keep the formatting and comments of the file.
+fn add_settings(path: &Path, contents: &str, settings: &[(&str, Value)]) -> Result<String, Error> {
+    let is_pyproject = path.ends_with("pyproject.toml");
+    let table: toml::Table = toml::from_str(contents)?;
+    let existing = if is_pyproject {
+        table
+            .get("tool")
+            .and_then(|tool| tool.get("nbwipers"))
+            .and_then(Value::as_table)
+    } else {
+        Some(&table)
+    };
+    let mut lines = vec![];
+    for (key, value) in settings {
+        match existing.and_then(|existing| existing.get(*key)) {
+            Some(current) if current != value => eprintln!(
+                "Keeping {key} = {current} from {}, rather than nbstripout's {value}",
+                path.display()
+            ),
+            Some(_) => {}
+            None => lines.push(format!("{key} = {value}\n")),
+        }
+    }
+    if lines.is_empty() {
+        return Ok(contents.to_string());
+    }
+    let lines = lines.concat();
+    if !is_pyproject {
+        // top level keys must come before any table
+        return Ok(format!("{lines}{contents}"));
+    }
+    let mut out = String::new();
+    let mut inserted = false;
+    for line in contents.lines() {
+        out.push_str(line);
+        out.push('\n');
+        if !inserted && line.trim() == "[tool.nbwipers]" {
+            out.push_str(&lines);
+            inserted = true;
+        }
+    }
+    if !inserted {
+        if !out.is_empty() {
+            out.push('\n');
+        }
+        out.push_str("[tool.nbwipers]\n");
+        out.push_str(&lines);
+    }
+    Ok(out)
+}
+
+/// Whether `assignment` makes git run nbstripout on files matching `pattern`.
+fn is_nbstripout_attribute(pattern: &str, assignment: &AssignmentRef) -> bool {
+    let StateRef::Value(value) = assignment.state else {
+        return false;
+    };
+    match assignment.name.as_str() {
+        "filter" => value.as_bstr() == "nbstripout",
+        "diff" => pattern == "*.ipynb" && value.as_bstr() == "ipynb",
+        _ => false,
+    }
+}
+
+/// The attribute files that nbstripout may have been installed to. If it is in none of them,
+/// nbwipers is installed to the first.
+fn candidate_attribute_files(cmd: &MigrateCommand) -> Result<Vec<PathBuf>, Error> {
+    let mut files = vec![resolve_attribute_file(
+        cmd.config_type,
+        cmd.attribute_file.as_deref(),
+    )?];
+    if cmd.attribute_file.is_none()
+        && cmd.config_type == GitConfigType::Local
+        && let Some(work_dir) = get_git_repo_and_work_tree()?.1
+    {
+        files.push(work_dir.join(".gitattributes"));
+    }
+    Ok(files)
+}
+
+pub fn migrate_from_nbstripout(cmd: &MigrateCommand) -> Result<(), Error> {
+    let config_path = resolve_config_file(cmd.git_config_file.as_deref(), cmd.config_type)?;
+    let mut git_config_change = FileChange::new(config_path)?;
+    let mut git_config = read_config_file(&git_config_change.path, cmd.config_type)?;
+    let settings = nbstripout_settings(&git_config)?;
+    let filter_removed = git_config
+        .remove_section("filter", Some("nbstripout".into()))
+        .is_some();
+    let diff_removed = git_config
+        .remove_section("diff", Some("ipynb".into()))
+        .is_some();
+
+    let mut attribute_changes = vec![];
+    for path in candidate_attribute_files(cmd)? {
+        let mut change = FileChange::new(path)?;
+        if let Some(mut removed) = remove_attributes(&change.before, is_nbstripout_attribute)? {
+            if let Some(lines) = missing_attribute_lines(removed.as_bytes()) {
+                removed.push_str(&lines);
+            }
+            change.after = removed;
+            attribute_changes.push(change);
+        }
+    }
+    if !filter_removed && !diff_removed && attribute_changes.is_empty() {
+        bail!(
+            "nbstripout is not installed in {}",
+            git_config_change.path.display()
+        );
+    }
+    if attribute_changes.is_empty() {
+        let mut change = FileChange::new(resolve_attribute_file(
+            cmd.config_type,
+            cmd.attribute_file.as_deref(),
+        )?)?;
+        if let Some(lines) = missing_attribute_lines(change.before.as_bytes()) {
+            change.after = change.before.clone() + &lines;
+        }
+        attribute_changes.push(change);
+    }
+
+    add_nbwipers_sections(&mut git_config, &filter_executable(cmd.command)?);
+    git_config_change.after = git_config.to_string();
+
+    let mut config_change = FileChange::new(nbwipers_config_file(cmd.config.as_deref())?)?;
+    config_change.after = add_settings(&config_change.path, &config_change.before, &settings)?;
+
+    let changes = std::iter::once(config_change)
+        .chain(std::iter::once(git_config_change))
+        .chain(attribute_changes)
+        .filter(FileChange::is_changed);
+    for change in changes {
+        if cmd.dry_run {
+            change.print_diff();
+        } else {
+            change.write()?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[allow(clippy::unwrap_used)]
+    #[test]
+    fn test_add_settings() {
+        let settings = [
+            ("drop-count", Value::Boolean(false)),
+            (
+                "extra-keys",
+                Value::Array(vec![Value::String("metadata.foo".into())]),
+            ),
+        ];
+        let nbwipers_toml = add_settings(
+            Path::new(".nbwipers.toml"),
+            "# comment\ndrop-count = true\n[[smudge.defaults]]\npath = \"*.ipynb\"\n",
+            &settings,
+        )
+        .unwrap();
+        assert_eq!(
+            nbwipers_toml,
+            "extra-keys = [\"metadata.foo\"]\n# comment\ndrop-count = true\n[[smudge.defaults]]\npath = \"*.ipynb\"\n"
+        );
+
+        let pyproject = add_settings(
+            Path::new("pyproject.toml"),
+            "[project]\nname = \"a\"\n",
+            &settings,
+        )
+        .unwrap();
+        assert_eq!(
+            pyproject,
+            "[project]\nname = \"a\"\n\n[tool.nbwipers]\ndrop-count = false\nextra-keys = [\"metadata.foo\"]\n"
+        );
+
+        let pyproject = add_settings(
+            Path::new("pyproject.toml"),
+            "[tool.nbwipers]\nstrip-init-cell = true\n",
+            &settings,
+        )
+        .unwrap();
+        assert_eq!(
+            pyproject,
+            "[tool.nbwipers]\ndrop-count = false\nextra-keys = [\"metadata.foo\"]\nstrip-init-cell = true\n"
+        );
+    }
+}
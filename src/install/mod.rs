@@ -1,21 +1,26 @@
 mod attributes;
+mod doctor;
 mod gitconfig;
+mod migrate;
 use anyhow::{Error, bail};
 
 use std::{
     env,
+    fmt::{Display, Formatter},
     ops::{BitAnd, BitOrAssign},
     path::PathBuf,
 };
 
-use gix_config::Source;
+use gix::config::Source;
 
 use crate::cli::GitConfigType;
 use attributes::check_install_attr_files;
-pub use attributes::{install_attributes, uninstall_attributes};
+pub use attributes::{install_attributes, project_attribute_file, uninstall_attributes};
+pub use doctor::{InstallReport, install_report};
 use gitconfig::{check_install_config_file, resolve_config_file};
 pub use gitconfig::{install_config, uninstall_config};
-use gix_config::File;
+use gix::config::File;
+pub use migrate::migrate_from_nbstripout;
 
 impl From<GitConfigType> for Source {
     fn from(value: GitConfigType) -> Self {
@@ -29,7 +34,7 @@ impl From<GitConfigType> for Source {
 
 fn get_git_repo_and_work_tree() -> Result<(PathBuf, Option<PathBuf>), Error> {
     let cur_dir = std::env::current_dir()?;
-    let (git_dir, _) = gix_discover::upwards(&cur_dir)?;
+    let (git_dir, _) = gix::discover::upwards(&cur_dir)?;
     Ok(git_dir.into_repository_and_work_tree_directories())
 }
 
@@ -42,6 +47,26 @@ struct InstallToolStatus {
 struct InstallStatus {
     pub nbstripout: InstallToolStatus,
     pub nbwipers: InstallToolStatus,
+    /// the commands that are not counted as installed because their executable is gone
+    pub stale: Vec<StaleCommand>,
+}
+
+/// A git config command that runs an executable, by its absolute path, that does not exist.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct StaleCommand {
+    key: String,
+    executable: PathBuf,
+}
+
+impl Display for StaleCommand {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} runs {}, which does not exist. Run install again to update it",
+            self.key,
+            self.executable.display()
+        )
+    }
 }
 
 impl InstallToolStatus {
@@ -91,10 +116,12 @@ impl BitAnd for InstallToolStatus {
 // }
 impl BitAnd for InstallStatus {
     type Output = Self;
-    fn bitand(self, rhs: Self) -> Self::Output {
+    fn bitand(mut self, rhs: Self) -> Self::Output {
+        self.stale.extend(rhs.stale);
         Self {
             nbstripout: self.nbstripout & rhs.nbstripout,
             nbwipers: self.nbwipers & rhs.nbwipers,
+            stale: self.stale,
         }
     }
 }
@@ -109,39 +136,47 @@ impl BitOrAssign for InstallStatus {
     fn bitor_assign(&mut self, rhs: Self) {
         self.nbstripout |= rhs.nbstripout;
         self.nbwipers |= rhs.nbwipers;
+        self.stale.extend(rhs.stale);
     }
 }
 
-fn combine_install_status(
-    attr_install_status: InstallStatus,
-    config_install_status: InstallStatus,
-) -> Result<(), Error> {
-    let overall_status = attr_install_status & config_install_status;
-    let mut installed = false;
-    if overall_status.nbstripout.is_installed() {
-        installed = true;
-        println!("nbstripout is installed");
+/// The tools that are installed both in the attributes and the git config.
+fn installed_tools(install_status: &InstallStatus) -> Vec<&'static str> {
+    let mut installed = vec![];
+    if install_status.nbstripout.is_installed() {
+        installed.push("nbstripout");
     }
-    if overall_status.nbwipers.is_installed() {
-        installed = true;
-        println!("nbwipers is installed");
+    if install_status.nbwipers.is_installed() {
+        installed.push("nbwipers");
     }
-    if installed {
-        Ok(())
-    } else {
+    installed
+}
+
+fn combine_install_status(install_status: &InstallStatus) -> Result<(), Error> {
+    for stale in &install_status.stale {
+        eprintln!("{stale}");
+    }
+    let installed = installed_tools(install_status);
+    for tool in &installed {
+        println!("{tool} is installed");
+    }
+    if installed.is_empty() {
         bail!("Neither nbstripout nor nbwipers are installed.")
     }
+    Ok(())
 }
-pub fn check_install_some_type(config_type: GitConfigType) -> Result<(), Error> {
+
+fn install_status_some_type(config_type: GitConfigType) -> Result<InstallStatus, Error> {
     let attr_install_status = check_install_attr_files(&[config_type])?;
 
     let file_path = resolve_config_file(None, config_type)?;
     let config_file = File::from_path_no_includes(file_path, config_type.into())?;
     let config_install_status = check_install_config_file(&config_file);
 
-    combine_install_status(attr_install_status, config_install_status)
+    Ok(attr_install_status & config_install_status)
 }
-pub fn check_install_none_type() -> Result<(), Error> {
+
+fn install_status_none_type() -> Result<InstallStatus, Error> {
     let config_types = vec![
         GitConfigType::Local,
         GitConfigType::Global,
@@ -152,7 +187,24 @@ pub fn check_install_none_type() -> Result<(), Error> {
     let config_file = File::from_git_dir(get_git_repo_and_work_tree()?.0)?;
     let config_install_status = check_install_config_file(&config_file);
 
-    combine_install_status(attr_install_status, config_install_status)
+    Ok(attr_install_status & config_install_status)
+}
+
+pub fn check_install_some_type(config_type: GitConfigType) -> Result<(), Error> {
+    combine_install_status(&install_status_some_type(config_type)?)
+}
+pub fn check_install_none_type() -> Result<(), Error> {
+    combine_install_status(&install_status_none_type()?)
+}
+
+/// The tools installed for `config_type`, or for any config type if it is `None`, without
+/// printing anything.
+pub fn installed(config_type: Option<GitConfigType>) -> Result<Vec<&'static str>, Error> {
+    let install_status = match config_type {
+        Some(config_type) => install_status_some_type(config_type)?,
+        None => install_status_none_type()?,
+    };
+    Ok(installed_tools(&install_status))
 }
 
 pub fn check_should_exit_zero(exit_zero: bool) -> bool {
@@ -1,16 +1,26 @@
+pub mod audit;
 pub mod cell_impl;
 pub mod check;
 pub mod cli;
 pub mod config;
+pub mod derive;
+pub mod explain;
 pub mod extra_keys;
 pub mod files;
 pub mod hooks;
 pub mod install;
+pub mod output_cache;
 pub mod record;
+pub mod report;
+pub mod rewrite;
+pub mod rules;
 pub mod schema;
+pub mod server;
 pub mod settings;
 pub mod smudge;
+pub mod span;
 pub mod strip;
+pub mod suppress;
 pub mod utils;
 #[allow(clippy::unwrap_used)]
 #[cfg(test)]
@@ -17,24 +17,33 @@ use std::{
 use anyhow::{Error, anyhow, bail};
 use clap::Parser;
 use colored::Colorize;
-use nbwipers::config::{Configuration, resolve};
+use nbwipers::audit::audit;
+use nbwipers::config::Configuration;
+use nbwipers::derive::derive;
+use nbwipers::explain::{Explainer, explain};
 use nbwipers::files::{
-    FoundNotebooks, find_notebooks_or_stdin, read_nb, read_nb_stdin, relativize_path,
+    FoundNotebooks, find_notebooks_or_stdin, read_nb_stdin_with_text, read_nb_with_text,
+    relativize_path,
 };
 use nbwipers::hooks::hooks;
 use nbwipers::install;
+use nbwipers::output_cache::cache;
 use nbwipers::record::record;
+use nbwipers::report::reporter;
+use nbwipers::rewrite::rewrite_history;
+use nbwipers::rules::Severity;
+use nbwipers::server::server;
 use nbwipers::settings::Settings;
 use nbwipers::strip::{StripResult, strip_single};
 use nbwipers::{
-    check::{self as check, PathCheckResult},
+    check::{self, CheckResult},
     files::{check_exclusions, normalize_path},
 };
 use nbwipers::{
     cli::{
-        self as cli, CheckCommand, CheckInstallCommand, CleanAllCommand, CleanCommand, Commands,
-        CommonArgs, InstallCommand, OutputFormat, ShowConfigCommand, SmudgeCommand,
-        UninstallCommand, resolve_bool_arg,
+        self as cli, CheckCommand, CheckInstallCommand, CheckOutputFormat, CleanAllCommand,
+        CleanCommand, Commands, CommonArgs, FilterCommand, InstallCommand, OutputFormat,
+        ShowConfigCommand, SmudgeCommand, UninstallCommand, resolve_bool_arg,
     },
     smudge::smudge,
 };
@@ -43,7 +52,7 @@ use std::io::Write;
 
 fn check_all(
     files: &[PathBuf],
-    output_format: Option<OutputFormat>,
+    output_format: Option<CheckOutputFormat>,
     stdin_file_name: Option<&Path>,
     cli: CommonArgs,
 ) -> Result<(), Error> {
@@ -52,15 +61,15 @@ fn check_all(
     let settings = Settings::construct(args.config.as_deref(), args.isolated, &overrides)?;
     let nbs = find_notebooks_or_stdin(files, &settings)?;
     let check_results_by_file = match nbs {
-        FoundNotebooks::Stdin => match read_nb_stdin() {
-            Ok(nb) => vec![(
+        FoundNotebooks::Stdin => match read_nb_stdin_with_text() {
+            Ok((nb, text)) => vec![(
                 Path::new("-"),
                 match stdin_file_name.map(|sfn| check_exclusions(&normalize_path(sfn), &settings)) {
                     Some(true) => vec![],
-                    _ => nbwipers::check::check_nb(&nb, &settings),
+                    _ => check::check_located(&nb, &text, &settings),
                 },
             )],
-            Err(e) => vec![(Path::new("-"), vec![e.into()])],
+            Err(e) => vec![(Path::new("-"), vec![CheckResult::from(e).into()])],
         },
         FoundNotebooks::NoFiles => {
             if args.allow_no_notebooks {
@@ -72,35 +81,28 @@ fn check_all(
             nbs.par_iter()
                 .map(|nb_path| {
                     // println!("{nb_path:?}");
-                    match read_nb(nb_path) {
-                        Ok(nb) => (nb_path.as_path(), check::check_nb(&nb, &settings)),
-                        Err(e) => (nb_path.as_path(), vec![e.into()]),
+                    match read_nb_with_text(nb_path) {
+                        Ok((nb, text)) => (
+                            nb_path.as_path(),
+                            check::check_located(&nb, &text, &settings),
+                        ),
+                        Err(e) => (nb_path.as_path(), vec![CheckResult::from(e).into()]),
                     }
                 })
                 .collect()
         }
     };
-    let mut check_results = Vec::new();
+    reporter(output_format, &settings)
+        .report(&check_results_by_file, &mut std::io::stdout().lock())?;
 
-    for (path, res) in &check_results_by_file {
-        check_results.extend(res.iter().map(|result| PathCheckResult { path, result }));
-    }
-
-    match output_format {
-        OutputFormat::Text => {
-            for PathCheckResult { path, result } in &check_results {
-                let rel_path = relativize_path(path).bold();
-                println!("{rel_path}:{result}");
-            }
-        }
-        OutputFormat::Json => print!("{}", serde_json::to_string_pretty(&check_results)?),
-    }
-
-    if check_results.is_empty() {
+    let n_checks = check_results_by_file
+        .iter()
+        .flat_map(|(_, res)| res)
+        .filter(|located| located.severity == Severity::Error)
+        .count();
+    if n_checks == 0 {
         Ok(())
     } else {
-        let n_checks = check_results.len();
-
         Err(anyhow!("Found {n_checks} items to strip"))
     }
 }
@@ -160,8 +162,22 @@ fn strip(
 }
 
 fn install(cmd: &InstallCommand) -> Result<(), Error> {
-    install::install_config(cmd.git_config_file.as_deref(), cmd.config_type)?;
-    install::install_attributes(cmd.config_type, cmd.attribute_file.as_deref())
+    let default_command = if cmd.project {
+        FilterCommand::Path
+    } else {
+        FilterCommand::Absolute
+    };
+    install::install_config(
+        cmd.git_config_file.as_deref(),
+        cmd.config_type,
+        cmd.command.unwrap_or(default_command),
+    )?;
+    let attribute_file = if cmd.project {
+        Some(install::project_attribute_file()?)
+    } else {
+        cmd.attribute_file.clone()
+    };
+    install::install_attributes(cmd.config_type, attribute_file.as_deref())
 }
 
 fn uninstall(cmd: &UninstallCommand) -> Result<(), Error> {
@@ -170,6 +186,9 @@ fn uninstall(cmd: &UninstallCommand) -> Result<(), Error> {
 }
 
 fn check_install(cmd: &CheckInstallCommand) -> Result<(), Error> {
+    if cmd.report || cmd.output_format.is_some() {
+        return check_install_report(cmd);
+    }
     let check_result = cmd
         .config_type
         .map_or_else(install::check_install_none_type, |config_type| {
@@ -182,19 +201,27 @@ fn check_install(cmd: &CheckInstallCommand) -> Result<(), Error> {
     }
 }
 
-fn show_config(common: CommonArgs, show_all: bool) -> Result<(), Error> {
+fn check_install_report(cmd: &CheckInstallCommand) -> Result<(), Error> {
+    let report = install::install_report(cmd.config_type)?;
+    match cmd.output_format.unwrap_or_default() {
+        OutputFormat::Text => print!("{report}"),
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&report)?),
+    }
+    if report.installed.is_empty() && !install::check_should_exit_zero(cmd.exit_zero) {
+        bail!("Neither nbstripout nor nbwipers are installed.");
+    }
+    Ok(())
+}
+
+fn show_config(common: CommonArgs, show_all: bool, explain: bool) -> Result<(), Error> {
     let (args, overrides) = common.partition();
-    let settings_str = if show_all {
+    let settings_str = if explain {
+        Explainer::load(args.config.as_deref(), args.isolated, &overrides)?.explain_settings()?
+    } else if show_all {
         let settings = Settings::construct(args.config.as_deref(), args.isolated, &overrides)?;
         toml::to_string(&settings)?
     } else {
-        let mut config = if args.isolated {
-            Configuration::default()
-        } else {
-            let (config_sec, config_path) = resolve(args.config.as_deref())?;
-            config_sec.make_configuration(config_path.as_deref())
-        };
-        config = overrides.override_config(config);
+        let config = Configuration::load(args.config.as_deref(), args.isolated, &overrides)?;
         toml::to_string(&config)?
     };
 
@@ -245,13 +272,22 @@ fn main() -> Result<(), Error> {
             common,
             show_all,
             no_show_defaults,
+            explain,
         }) => show_config(
             common,
             resolve_bool_arg(show_all, no_show_defaults).unwrap_or(false),
+            explain,
         ),
         Commands::Hook(ref cmd) => hooks(cmd),
         Commands::Record(cmd) => record(cmd),
         Commands::Smudge(SmudgeCommand { path }) => smudge(path),
+        Commands::RewriteHistory(cmd) => rewrite_history(cmd),
+        Commands::Audit(cmd) => audit(cmd),
+        Commands::Cache(ref cmd) => cache(cmd),
+        Commands::MigrateFromNbstripout(ref cmd) => install::migrate_from_nbstripout(cmd),
+        Commands::Server(cmd) => server(cmd),
+        Commands::Derive(cmd) => derive(cmd),
+        Commands::Explain(cmd) => explain(cmd),
     }
 }
 
@@ -0,0 +1,291 @@
+use std::{
+    fs::{self, File},
+    io::{BufReader, BufWriter, Write},
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use anyhow::Error;
+use rustc_hash::FxHashSet;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{
+    cli::{CacheClearCommand, CacheCommands, CacheGcCommand, ConfigOverrides},
+    files::{FoundNotebooks, find_notebooks, get_cwd, read_nb},
+    record::{RecordError, get_nbwipers_dir},
+    schema::{Cell, CodeCell, RawNotebook, SourceValue},
+    settings::Settings,
+};
+
+/// Outputs of a single code cell, as stored in the cache.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct CachedOutputs {
+    pub execution_count: Option<i64>,
+    pub outputs: Vec<Value>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct GcStats {
+    pub removed: usize,
+    pub removed_bytes: u64,
+    pub kept: usize,
+}
+
+/// Content-addressed store of cell outputs in `.git/x-nbwipers/outputs`.
+///
+/// Entries are keyed by a hash of the cell id and source, so that outputs are only restored to
+/// cells whose code has not changed since the outputs were stored.
+#[derive(Clone, Debug)]
+pub struct OutputCache {
+    dir: PathBuf,
+}
+
+pub(crate) fn source_text(source: &SourceValue) -> String {
+    match source {
+        SourceValue::String(s) => s.clone(),
+        SourceValue::StringArray(lines) => lines.concat(),
+    }
+}
+
+/// Cache key of a code cell, derived from its id and source.
+///
+/// A cell whose source trips sha1 collision detection has no key, and is not cached.
+pub fn cell_key(cell: &CodeCell) -> Option<String> {
+    let mut hasher = gix::hash::hasher(gix::hash::Kind::Sha1);
+    hasher.update(cell.id.as_deref().unwrap_or_default().as_bytes());
+    hasher.update(b"\0");
+    hasher.update(source_text(&cell.source).as_bytes());
+    hasher.try_finalize().ok().map(|id| id.to_hex().to_string())
+}
+
+fn has_outputs(cell: &CodeCell) -> bool {
+    !cell.outputs.is_empty() || cell.execution_count.is_some()
+}
+
+impl OutputCache {
+    pub fn for_repo<P: AsRef<Path>>(path: P) -> Result<Self, RecordError> {
+        Ok(Self {
+            dir: get_nbwipers_dir(path)?.join("outputs"),
+        })
+    }
+
+    fn entry_path(&self, key: &str) -> PathBuf {
+        self.dir.join(&key[..2]).join(&key[2..])
+    }
+
+    /// Store the outputs of every code cell that has any. Returns the number of cells stored.
+    pub fn store(&self, nb: &RawNotebook) -> Result<usize, Error> {
+        let mut stored = 0;
+        for cell in nb.cells.iter().filter_map(Cell::as_codecell) {
+            let Some(key) = cell_key(cell).filter(|_| has_outputs(cell)) else {
+                continue;
+            };
+            let path = self.entry_path(&key);
+            if path.is_file() {
+                // refresh the entry so that it survives garbage collection
+                File::options()
+                    .append(true)
+                    .open(&path)?
+                    .set_modified(SystemTime::now())?;
+                continue;
+            }
+            let parent = path.parent().expect("entry path should have a parent");
+            fs::create_dir_all(parent)?;
+            // write to a temporary file first, since git may run several filters at once
+            let tmp_path = parent.join(format!(
+                ".{}.{}.tmp",
+                path.file_name().unwrap_or_default().to_string_lossy(),
+                std::process::id()
+            ));
+            {
+                let mut writer = BufWriter::new(File::create(&tmp_path)?);
+                serde_json::to_writer(
+                    &mut writer,
+                    &CachedOutputs {
+                        execution_count: cell.execution_count,
+                        outputs: cell.outputs.clone(),
+                    },
+                )?;
+                writer.flush()?;
+            }
+            fs::rename(&tmp_path, &path)?;
+            stored += 1;
+        }
+        Ok(stored)
+    }
+
+    fn read_entry(&self, key: &str) -> Option<CachedOutputs> {
+        let file = File::open(self.entry_path(key)).ok()?;
+        serde_json::from_reader(BufReader::new(file)).ok()
+    }
+
+    /// Restore cached outputs to code cells that have none. Returns the number of cells restored.
+    pub fn restore(&self, nb: &mut RawNotebook) -> usize {
+        if !self.dir.is_dir() {
+            return 0;
+        }
+        let mut restored = 0;
+        for cell in nb.cells.iter_mut().filter_map(Cell::as_codecell_mut) {
+            if has_outputs(cell) {
+                continue;
+            }
+            if let Some(cached) = cell_key(cell).and_then(|key| self.read_entry(&key)) {
+                cell.execution_count = cached.execution_count;
+                cell.outputs = cached.outputs;
+                restored += 1;
+            }
+        }
+        restored
+    }
+
+    /// Remove entries older than `max_age`, unless their key is in `keep`.
+    pub fn gc(&self, keep: &FxHashSet<String>, max_age: Duration) -> Result<GcStats, Error> {
+        let mut stats = GcStats::default();
+        if !self.dir.is_dir() {
+            return Ok(stats);
+        }
+        let now = SystemTime::now();
+        for prefix_dir in fs::read_dir(&self.dir)? {
+            let prefix_dir = prefix_dir?;
+            if !prefix_dir.file_type()?.is_dir() {
+                continue;
+            }
+            let prefix = prefix_dir.file_name().to_string_lossy().into_owned();
+            for entry in fs::read_dir(prefix_dir.path())? {
+                let entry = entry?;
+                let key = format!("{prefix}{}", entry.file_name().to_string_lossy());
+                let metadata = entry.metadata()?;
+                let age = now.duration_since(metadata.modified()?).unwrap_or_default();
+                if keep.contains(&key) || age < max_age {
+                    stats.kept += 1;
+                } else {
+                    fs::remove_file(entry.path())?;
+                    stats.removed += 1;
+                    stats.removed_bytes += metadata.len();
+                }
+            }
+            if fs::read_dir(prefix_dir.path())?.next().is_none() {
+                fs::remove_dir(prefix_dir.path())?;
+            }
+        }
+        Ok(stats)
+    }
+
+    pub fn clear(&self) -> Result<(), Error> {
+        if self.dir.is_dir() {
+            fs::remove_dir_all(&self.dir)?;
+        }
+        Ok(())
+    }
+}
+
+/// Keys of the code cells with outputs in the notebooks under `path`.
+fn live_keys(path: &Path, settings: &Settings) -> Result<FxHashSet<String>, Error> {
+    let FoundNotebooks::Files(files) = find_notebooks(&[path], settings)? else {
+        return Ok(FxHashSet::default());
+    };
+    Ok(files
+        .iter()
+        .filter_map(|f| read_nb(f).ok())
+        .flat_map(|nb| {
+            nb.cells
+                .iter()
+                .filter_map(Cell::as_codecell)
+                .filter(|c| has_outputs(c))
+                .filter_map(cell_key)
+                .collect::<Vec<_>>()
+        })
+        .collect())
+}
+
+fn cache_gc(cmd: &CacheGcCommand) -> Result<(), Error> {
+    let path = cmd.path.clone().unwrap_or_else(get_cwd);
+    let settings = Settings::construct(
+        cmd.config.as_deref(),
+        cmd.isolated,
+        &ConfigOverrides::default(),
+    )?;
+    let cache = OutputCache::for_repo(&path)?;
+    let keep = live_keys(&path, &settings)?;
+    let max_age = Duration::from_secs(cmd.max_age_days * 24 * 60 * 60);
+    let stats = cache.gc(&keep, max_age)?;
+    println!(
+        "Removed {} cached outputs ({} bytes), kept {}",
+        stats.removed, stats.removed_bytes, stats.kept
+    );
+    Ok(())
+}
+
+fn cache_clear(cmd: &CacheClearCommand) -> Result<(), Error> {
+    let path = cmd.path.clone().unwrap_or_else(get_cwd);
+    OutputCache::for_repo(path)?.clear()
+}
+
+pub fn cache(cmd: &CacheCommands) -> Result<(), Error> {
+    match cmd {
+        CacheCommands::Gc(inner_cmd) => cache_gc(inner_cmd),
+        CacheCommands::Clear(inner_cmd) => cache_clear(inner_cmd),
+    }
+}
+
+#[allow(clippy::unwrap_used)]
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn code_cell(id: &str, source: &str) -> CodeCell {
+        CodeCell {
+            execution_count: Some(1),
+            id: Some(id.to_owned()),
+            metadata: json!({}),
+            outputs: vec![json!({"name": "stdout", "output_type": "stream", "text": ["1\n"]})],
+            source: SourceValue::String(source.to_owned()),
+        }
+    }
+
+    #[test]
+    fn test_cell_key() {
+        let cell = code_cell("a", "print(1)");
+        let mut split = cell.clone();
+        split.source = SourceValue::StringArray(vec!["print(".into(), "1)".into()]);
+        let mut cleared = cell.clone();
+        cleared.outputs.clear();
+        assert_eq!(cell_key(&cell), cell_key(&split));
+        assert_eq!(cell_key(&cell), cell_key(&cleared));
+        assert_ne!(cell_key(&cell), cell_key(&code_cell("b", "print(1)")));
+        assert_ne!(cell_key(&cell), cell_key(&code_cell("a", "print(2)")));
+    }
+
+    #[test]
+    fn test_store_restore_gc() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        fs::create_dir_all(temp_dir.path().join(".git/objects")).unwrap();
+        fs::create_dir_all(temp_dir.path().join(".git/refs")).unwrap();
+        fs::write(temp_dir.path().join(".git/HEAD"), "ref: refs/heads/main\n").unwrap();
+        let cache = OutputCache::for_repo(temp_dir.path()).unwrap();
+
+        let dirty = RawNotebook {
+            cells: vec![Cell::Code(code_cell("a", "print(1)"))],
+            ..Default::default()
+        };
+        assert_eq!(cache.store(&dirty).unwrap(), 1);
+
+        let mut clean = dirty.clone();
+        let clean_cell = clean.cells[0].as_codecell_mut().unwrap();
+        clean_cell.clear_outputs();
+        clean_cell.clear_counts();
+        let mut edited = clean.clone();
+        edited.cells[0].as_codecell_mut().unwrap().source = SourceValue::String("print(2)".into());
+
+        assert_eq!(cache.restore(&mut edited), 0);
+        assert_eq!(cache.restore(&mut clean), 1);
+        assert_eq!(clean, dirty);
+
+        let stats = cache.gc(&FxHashSet::default(), Duration::ZERO).unwrap();
+        assert_eq!(stats.removed, 1);
+        assert_eq!(cache.restore(&mut edited), 0);
+    }
+}
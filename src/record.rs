@@ -1,19 +1,26 @@
 use std::{
     fmt::Debug,
-    fs::{self, File},
-    io::{BufReader, BufWriter},
+    fs::{self, File, OpenOptions},
+    io::{BufReader, BufWriter, ErrorKind, Write},
     path::{Path, PathBuf},
+    thread,
+    time::{Duration, Instant, SystemTime},
 };
 
 use crate::{
-    cli::RecordCommand,
-    files::{FoundNotebooks, find_notebooks, get_cwd, normalize_path, read_nb, relativize_path},
+    cli::{OutputFormat, RecordCommand},
+    extra_keys::{ExtraKey, partition_extra_keys},
+    files::{FoundNotebooks, find_notebooks, get_cwd, normalize_path, normalize_path_to, read_nb},
+    output_cache::source_text,
     schema::RawNotebook,
     settings::Settings,
+    utils::get_value_child,
 };
 use anyhow::Error;
 use indexmap::IndexMap;
+use itertools::Itertools;
 use rayon::prelude::*;
+use rustc_hash::FxHashSet;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use thiserror::Error as ThisError;
@@ -23,7 +30,7 @@ pub enum RecordError {
     #[error("No .git dir")]
     NoGitDir,
     #[error("Invalid git repo")]
-    InvalidGitRepo(#[from] gix_discover::is_git::Error),
+    InvalidGitRepo(#[from] gix::discover::is_git::Error),
     #[error("Not a git worktree")]
     NotAGitWorktree,
     #[error("Failed to create nbwipers dir")]
@@ -36,41 +43,315 @@ pub enum RecordError {
     SerdeWriteError(serde_json::Error),
     #[error("No Notebooks to record")]
     NoNotebooks,
+    #[error("Failed to lock kernelspec file")]
+    FailedLockKernelspecFile(std::io::Error),
+    #[error("Timed out waiting for the lock on the kernelspec file")]
+    KernelspecFileLocked,
+    #[error(
+        "Could not safely remove the stale lock {}. Delete it if no nbwipers process is running",
+        .0.display()
+    )]
+    StaleKernelspecFileLock(PathBuf),
+    #[error("Failed to write kernelspec file")]
+    FailedWriteKernelspecFile(std::io::Error),
+    #[error("Kernelspec file has version {0}, which needs a newer version of nbwipers")]
+    UnsupportedKernelspecFileVersion(u64),
+    #[error("Nothing recorded for {0}")]
+    NotRecorded(String),
+}
+
+/// How long to wait for another process to release the lock on the kernelspec store.
+const LOCK_TIMEOUT: Duration = Duration::from_secs(10);
+/// Locks older than this were left behind by a process that crashed and are removed.
+const STALE_LOCK_AGE: Duration = Duration::from_secs(60);
+
+/// Exclusive lock on the kernelspec store, released when dropped.
+///
+/// Git may run several clean filters at once, so every read-modify-write of the store happens
+/// while holding this lock.
+struct StoreLock {
+    path: PathBuf,
+}
+
+impl StoreLock {
+    fn acquire(store_path: &Path) -> Result<Self, RecordError> {
+        let path = store_path.with_extension("lock");
+        let start = Instant::now();
+        loop {
+            match OpenOptions::new().write(true).create_new(true).open(&path) {
+                Ok(_) => return Ok(Self { path }),
+                Err(e) if e.kind() == ErrorKind::AlreadyExists => {
+                    let stale = fs::metadata(&path)
+                        .and_then(|m| m.modified())
+                        .ok()
+                        .filter(|modified| modified.elapsed().unwrap_or_default() > STALE_LOCK_AGE);
+                    if let Some(modified) = stale {
+                        Self::remove_stale(&path, modified)?;
+                    } else if start.elapsed() > LOCK_TIMEOUT {
+                        return Err(RecordError::KernelspecFileLocked);
+                    } else {
+                        thread::sleep(Duration::from_millis(10));
+                    }
+                }
+                Err(e) => return Err(RecordError::FailedLockKernelspecFile(e)),
+            }
+        }
+    }
+
+    /// Remove the lock at `path`, last modified at `modified`, which was judged stale.
+    ///
+    /// Another process may remove the stale lock and take a new one between the check and the
+    /// removal, so the lock is first moved aside, and only removed if it is the one judged stale.
+    fn remove_stale(path: &Path, modified: SystemTime) -> Result<(), RecordError> {
+        let aside = path.with_extension(format!("lock.{}.stale", std::process::id()));
+        match fs::rename(path, &aside) {
+            Ok(()) => {}
+            // another process already removed it
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(RecordError::FailedLockKernelspecFile(e)),
+        }
+        let same = fs::metadata(&aside)
+            .and_then(|m| m.modified())
+            .is_ok_and(|m| m == modified);
+        // put back a lock that was taken since, unless yet another one has been taken
+        let restored = same || fs::hard_link(&aside, path).is_ok();
+        let _ = fs::remove_file(&aside);
+        if restored {
+            Ok(())
+        } else {
+            Err(RecordError::StaleKernelspecFileLock(path.to_path_buf()))
+        }
+    }
+}
+
+impl Drop for StoreLock {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// Write the store to a temporary file and move it into place, so readers never see a
+/// partially written store.
+fn write_kernelspec_file(
+    path: &Path,
+    kernelspec_records: &IndexMap<String, KernelSpecInfo>,
+) -> Result<(), RecordError> {
+    let tmp_path = path.with_extension(format!("{}.tmp", std::process::id()));
+    let out_file = File::create(&tmp_path).map_err(RecordError::FailedWriteKernelspecFile)?;
+    let mut buf = BufWriter::new(out_file);
+    let store = KernelspecStore {
+        version: KERNELSPEC_STORE_VERSION,
+        notebooks: kernelspec_records,
+    };
+    serde_json::to_writer(&mut buf, &store).map_err(RecordError::SerdeWriteError)?;
+    buf.flush()
+        .map_err(RecordError::FailedWriteKernelspecFile)?;
+    drop(buf);
+    fs::rename(&tmp_path, path).map_err(RecordError::FailedWriteKernelspecFile)
+}
+
+/// Apply `update` to the records in the store at `path` while holding the store lock.
+pub fn update_kernelspec_file<P: AsRef<Path>>(
+    path: P,
+    update: impl FnOnce(&mut IndexMap<String, KernelSpecInfo>),
+) -> Result<(), RecordError> {
+    let path = path.as_ref();
+    let _lock = StoreLock::acquire(path)?;
+    let mut kernelspec_records = match read_kernelspec_file(path) {
+        Ok(records) => records.unwrap_or_default(),
+        Err(RecordError::InvalidKernelspecFile(e)) => {
+            // keep the corrupt store for inspection rather than failing every filter run
+            let corrupt_path = path.with_extension("json.corrupt");
+            fs::rename(path, &corrupt_path).map_err(RecordError::FailedWriteKernelspecFile)?;
+            eprintln!(
+                "Kernelspec file was invalid ({e}). Moved it to {} and started a new one",
+                corrupt_path.display()
+            );
+            IndexMap::new()
+        }
+        Err(e) => return Err(e),
+    };
+    update(&mut kernelspec_records);
+    write_kernelspec_file(path, &kernelspec_records)
+}
+
+/// Record `info`, extracted from a notebook before it was cleaned, under `nb_path`.
+///
+/// Used by the clean filter when `record-on-clean` is set, so the kernel info it strips can be
+/// restored by the smudge filter.
+pub fn record_kernel_info(nb_path: &Path, info: KernelSpecInfo) -> Result<(), RecordError> {
+    let key = record_key(&get_work_tree(get_cwd())?, nb_path);
+    update_kernelspec_file(get_kernelspec_file(get_cwd())?, |records| {
+        records.insert(key, info);
+    })
 }
 
 pub fn get_kernelspec_file<P: AsRef<Path>>(path: P) -> Result<PathBuf, RecordError> {
-    let git_dir = path.as_ref().join(gix_discover::DOT_GIT_DIR);
-    if !git_dir.is_dir() {
-        return Err(RecordError::NoGitDir);
-    }
-    // check for a valid git directory
-    gix_discover::is_git(&git_dir)?;
-    // I don't know how to test this
-
-    // if !matches!(git_type, gix_discover::repository::Kind::WorkTree { .. }) {
-    //     return Err(RecordError::NotAGitWorktree);
-    // }
-    let nbwipers_dir = git_dir.join("x-nbwipers");
+    Ok(get_nbwipers_dir(path)?.join("kernelspec_store.json"))
+}
+
+/// Directory inside the git dir where nbwipers keeps its local, uncommitted state.
+///
+/// The state lives in the common git dir, so it is shared by all linked worktrees of a
+/// repository.
+pub fn get_nbwipers_dir<P: AsRef<Path>>(path: P) -> Result<PathBuf, RecordError> {
+    let nbwipers_dir = get_common_git_dir(path)?.join("x-nbwipers");
     fs::create_dir_all(&nbwipers_dir).map_err(RecordError::FailedCreateNbwipersDir)?;
-    Ok(nbwipers_dir.join("kernelspec_store.json"))
+    Ok(nbwipers_dir)
 }
+
+/// Root of the work tree of the repository containing `path`.
+///
+/// Records are keyed by paths relative to it, as git names files to the filters, so that
+/// `record` run from a subdirectory stores the keys that smudge looks up.
+pub fn get_work_tree<P: AsRef<Path>>(path: P) -> Result<PathBuf, RecordError> {
+    get_git_dir_and_work_tree(path)?
+        .1
+        .ok_or(RecordError::NotAGitWorktree)
+}
+
+/// The key of the notebook at `path` in the kernelspec store: its path relative to `work_tree`,
+/// with `/` as the separator.
+pub fn record_key<P: AsRef<Path>>(work_tree: &Path, path: P) -> String {
+    let path = normalize_path(path);
+    let path = path.strip_prefix(work_tree).unwrap_or(&path);
+    path.display()
+        .to_string()
+        .replace(std::path::MAIN_SEPARATOR, "/")
+}
+
+/// Find the git dir and work tree of the repository containing `path`, honouring `GIT_DIR` and
+/// `GIT_WORK_TREE`.
+///
+/// Git runs filters from the root of the worktree, where `.git` may be a file pointing elsewhere,
+/// as in linked worktrees and submodules.
+fn get_git_dir_and_work_tree<P: AsRef<Path>>(
+    path: P,
+) -> Result<(PathBuf, Option<PathBuf>), RecordError> {
+    if let Some(git_dir) = std::env::var_os("GIT_DIR") {
+        let git_dir = normalize_path(git_dir);
+        gix::discover::is_git(&git_dir)?;
+        // as in git, the current directory is the work tree unless it is given
+        let work_tree =
+            std::env::var_os("GIT_WORK_TREE").map_or_else(|| normalize_path(path), normalize_path);
+        Ok((git_dir, Some(work_tree)))
+    } else {
+        let path = normalize_path(path);
+        let dot_git = path.join(gix::discover::DOT_GIT_DIR);
+        let repo_path = if dot_git.exists() {
+            // a broken `.git` here should be reported rather than skipped over
+            let kind = gix::discover::is_git(&dot_git)?;
+            gix::discover::repository::Path::from_dot_git_dir(dot_git, kind, &path)
+                .ok_or(RecordError::NoGitDir)?
+        } else {
+            gix::discover::upwards(&path)
+                .map_err(|_| RecordError::NoGitDir)?
+                .0
+        };
+        Ok(repo_path.into_repository_and_work_tree_directories())
+    }
+}
+
+/// Find the git dir of the repository containing `path`, and resolve it to the common git dir if
+/// it belongs to a linked worktree.
+fn get_common_git_dir<P: AsRef<Path>>(path: P) -> Result<PathBuf, RecordError> {
+    let git_dir = get_git_dir_and_work_tree(path)?.0;
+    // linked worktrees point to the common git dir with a `commondir` file
+    match fs::read_to_string(git_dir.join("commondir")) {
+        Ok(common_dir) => Ok(normalize_path_to(common_dir.trim(), &git_dir)),
+        Err(_) => Ok(git_dir),
+    }
+}
+/// Version of the kernelspec store format written by this version of nbwipers.
+///
+/// Stores without a version are the plain map of notebook paths written before versioning.
+pub const KERNELSPEC_STORE_VERSION: u64 = 1;
+
+#[derive(Serialize, Deserialize)]
+struct KernelspecStore<T> {
+    version: u64,
+    notebooks: T,
+}
+
 pub fn read_kernelspec_file<P: AsRef<Path>>(
     path: P,
 ) -> Result<Option<IndexMap<String, KernelSpecInfo>>, RecordError> {
-    if path.as_ref().exists() {
-        let file = File::open(path).map_err(RecordError::FailedReadKernelspecFile)?;
-        let buf = BufReader::new(file);
-        let kernelspec_info =
-            serde_json::from_reader(buf).map_err(RecordError::InvalidKernelspecFile)?;
-        Ok(Some(kernelspec_info))
-    } else {
-        Ok(None)
+    if !path.as_ref().exists() {
+        return Ok(None);
+    }
+    let file = File::open(path).map_err(RecordError::FailedReadKernelspecFile)?;
+    let buf = BufReader::new(file);
+    let value: Value = serde_json::from_reader(buf).map_err(RecordError::InvalidKernelspecFile)?;
+    let kernelspec_info = match value.get("version").and_then(Value::as_u64) {
+        Some(version) if version > KERNELSPEC_STORE_VERSION => {
+            return Err(RecordError::UnsupportedKernelspecFileVersion(version));
+        }
+        Some(_) => serde_json::from_value::<KernelspecStore<_>>(value).map(|s| s.notebooks),
+        None => serde_json::from_value(value),
     }
+    .map_err(RecordError::InvalidKernelspecFile)?;
+    Ok(Some(kernelspec_info))
 }
-#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
 pub struct KernelSpecInfo {
+    #[serde(default)]
     pub kernelspec: Value,
+    #[serde(default)]
     pub python_version: Option<String>,
+    /// Values of notebook metadata keys listed in `record-keys`, keyed by the `record-keys` entry.
+    #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
+    pub metadata: IndexMap<String, Value>,
+    /// Values of cell metadata keys listed in `record-keys`, keyed by cell id and then by the
+    /// `record-keys` entry.
+    #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
+    pub cells: IndexMap<String, IndexMap<String, Value>>,
+    /// Fingerprint of the notebook's cell sources, used to find the entry again after the notebook
+    /// is renamed or moved.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fingerprint: Option<String>,
+}
+
+/// Fingerprint of the sources of the non-empty cells of `nb`.
+///
+/// Cleaning leaves sources alone, so a notebook has the same fingerprint in the working tree and
+/// when it passes through the smudge filter. Notebooks without any non-empty cell have no
+/// fingerprint, since they cannot be told apart, and neither do notebooks whose sources trip sha1
+/// collision detection.
+pub fn notebook_fingerprint(nb: &RawNotebook) -> Option<String> {
+    let mut hasher = gix::hash::hasher(gix::hash::Kind::Sha1);
+    let mut any_source = false;
+    for source in nb.cells.iter().map(|cell| source_text(cell.get_source())) {
+        if source.trim().is_empty() {
+            continue;
+        }
+        hasher.update(source.as_bytes());
+        hasher.update(b"\0");
+        any_source = true;
+    }
+    any_source
+        .then(|| hasher.try_finalize().ok())
+        .flatten()
+        .map(|id| id.to_hex().to_string())
+}
+
+/// Path of the only recorded notebook that no longer exists in `work_tree` and has the same
+/// fingerprint as `nb`.
+///
+/// This is how the record of a notebook is found after it is renamed or moved.
+pub fn find_moved_record<'a>(
+    records: &'a IndexMap<String, KernelSpecInfo>,
+    nb: &RawNotebook,
+    work_tree: &Path,
+) -> Option<&'a str> {
+    let fingerprint = notebook_fingerprint(nb)?;
+    records
+        .iter()
+        .filter(|(path, info)| {
+            info.fingerprint.as_ref() == Some(&fingerprint) && !work_tree.join(path).exists()
+        })
+        .map(|(path, _)| path.as_str())
+        .exactly_one()
+        .ok()
 }
 
 pub fn record(cmd: RecordCommand) -> Result<(), Error> {
@@ -80,29 +361,171 @@ pub fn record(cmd: RecordCommand) -> Result<(), Error> {
     let settings = Settings::construct(args.config.as_deref(), args.isolated, &overrides)?;
 
     let kernelspec_file = get_kernelspec_file(&path)?;
-    let mut kernelspec_records = if cmd.sync || cmd.clear {
+    let output_format = cmd.output_format.unwrap_or_default();
+    if cmd.list {
+        return list_records(&kernelspec_file, output_format);
+    }
+    let work_tree = get_work_tree(&path)?;
+    if let Some(show_path) = &cmd.show {
+        return show_record(
+            &kernelspec_file,
+            &record_key(&work_tree, show_path),
+            output_format,
+        );
+    }
+    if cmd.prune {
+        return prune_records(&kernelspec_file, &path, &work_tree, &settings);
+    }
+    let kernelspecs = if cmd.clear {
         IndexMap::new()
     } else {
-        read_kernelspec_file(&kernelspec_file)?.unwrap_or_default()
-    };
-    if !cmd.clear {
         let FoundNotebooks::Files(files) = find_notebooks(&[&path], &settings)? else {
             return Err(RecordError::NoNotebooks.into());
         };
-        let kernelspecs = get_kernelspecs(&files);
-        for (nb, kernel) in kernelspecs {
-            kernelspec_records.insert(nb, kernel);
+        get_kernelspecs(&files, &work_tree, &settings.record_keys)
+    };
+    Ok(update_kernelspec_file(
+        kernelspec_file,
+        |kernelspec_records| {
+            if cmd.sync || cmd.clear {
+                kernelspec_records.clear();
+            }
+            kernelspec_records.extend(kernelspecs);
+            for remove_path in &cmd.remove {
+                kernelspec_records.shift_remove(&record_key(&work_tree, remove_path));
+            }
+        },
+    )?)
+}
+fn describe_record(info: &KernelSpecInfo) -> String {
+    let mut parts = vec![];
+    if let Some(name) = info.kernelspec.get("name").and_then(Value::as_str) {
+        parts.push(format!("kernel {name}"));
+    }
+    if let Some(version) = &info.python_version {
+        parts.push(format!("python {version}"));
+    }
+    if !info.metadata.is_empty() {
+        parts.push(format!("{} metadata keys", info.metadata.len()));
+    }
+    if !info.cells.is_empty() {
+        parts.push(format!("metadata for {} cells", info.cells.len()));
+    }
+    if parts.is_empty() {
+        parts.push("nothing recorded".to_string());
+    }
+    parts.join(", ")
+}
+
+fn list_records(kernelspec_file: &Path, output_format: OutputFormat) -> Result<(), Error> {
+    let records = read_kernelspec_file(kernelspec_file)?.unwrap_or_default();
+    match output_format {
+        OutputFormat::Text => {
+            for (path, info) in &records {
+                println!("{path}: {}", describe_record(info));
+            }
         }
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&records)?),
     }
-    for remove_path in &cmd.remove {
-        kernelspec_records.shift_remove(&relativize_path(normalize_path(remove_path)));
+    Ok(())
+}
+
+fn show_record(
+    kernelspec_file: &Path,
+    key: &str,
+    output_format: OutputFormat,
+) -> Result<(), Error> {
+    let records = read_kernelspec_file(kernelspec_file)?.unwrap_or_default();
+    let info = records
+        .get(key)
+        .ok_or_else(|| RecordError::NotRecorded(key.to_owned()))?;
+    match output_format {
+        OutputFormat::Text => {
+            println!("{key}");
+            if !info.kernelspec.is_null() {
+                println!("  kernelspec: {}", info.kernelspec);
+            }
+            if let Some(version) = &info.python_version {
+                println!("  python version: {version}");
+            }
+            for (meta_key, value) in &info.metadata {
+                println!("  {meta_key}: {value}");
+            }
+            for (cell_id, values) in &info.cells {
+                for (cell_key, value) in values {
+                    println!("  cell {cell_id} {cell_key}: {value}");
+                }
+            }
+        }
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(info)?),
     }
-    let out_file = File::create(kernelspec_file)?;
-    let mut buf = BufWriter::new(out_file);
-    Ok(serde_json::to_writer(&mut buf, &kernelspec_records)
-        .map_err(RecordError::SerdeWriteError)?)
+    Ok(())
+}
+
+/// Drop entries for notebooks that no longer exist.
+///
+/// If a notebook under `path` without an entry has the same fingerprint as exactly one missing
+/// entry, it is taken to be that notebook renamed or moved, and the entry is moved to it instead.
+fn prune_records(
+    kernelspec_file: &Path,
+    path: &Path,
+    work_tree: &Path,
+    settings: &Settings,
+) -> Result<(), Error> {
+    let files = match find_notebooks(&[path], settings)? {
+        FoundNotebooks::Files(files) => files,
+        FoundNotebooks::Stdin | FoundNotebooks::NoFiles => vec![],
+    };
+    let fingerprints: Vec<(String, String)> = files
+        .par_iter()
+        .filter_map(|file| {
+            let nb = read_nb(file).ok()?;
+            Some((record_key(work_tree, file), notebook_fingerprint(&nb)?))
+        })
+        .collect();
+    update_kernelspec_file(kernelspec_file, |records| {
+        let missing: Vec<String> = records
+            .keys()
+            .filter(|path| !work_tree.join(path).exists())
+            .cloned()
+            .collect();
+        // a fingerprint shared by several missing entries cannot say which one was moved
+        let unique_fingerprints: FxHashSet<String> = missing
+            .iter()
+            .filter_map(|path| records.get(path)?.fingerprint.clone())
+            .counts()
+            .into_iter()
+            .filter_map(|(fingerprint, count)| (count == 1).then_some(fingerprint))
+            .collect();
+        for old_path in missing {
+            let Some(info) = records.shift_remove(&old_path) else {
+                continue;
+            };
+            let new_path = info
+                .fingerprint
+                .as_ref()
+                .filter(|fingerprint| unique_fingerprints.contains(*fingerprint))
+                .and_then(|fingerprint| {
+                    fingerprints
+                        .iter()
+                        .filter(|(path, other)| other == fingerprint && !records.contains_key(path))
+                        .map(|(path, _)| path.clone())
+                        .exactly_one()
+                        .ok()
+                });
+            match new_path {
+                Some(new_path) => {
+                    println!("Moved {old_path} to {new_path}");
+                    records.insert(new_path, info);
+                }
+                None => println!("Removed {old_path}"),
+            }
+        }
+    })?;
+    Ok(())
 }
-fn extract_kernel_info(nb: &RawNotebook) -> Option<KernelSpecInfo> {
+
+pub(crate) fn extract_kernel_info(nb: &RawNotebook) -> Option<KernelSpecInfo> {
     let kernelspec = nb.metadata.get("kernelspec");
     let python_version = nb
         .metadata
@@ -114,10 +537,49 @@ fn extract_kernel_info(nb: &RawNotebook) -> Option<KernelSpecInfo> {
     Some(KernelSpecInfo {
         kernelspec: kernelspec.cloned().unwrap_or(Value::Null),
         python_version: python_version.and_then(Value::as_str).map(str::to_string),
+        ..Default::default()
     })
 }
 
-fn get_kernelspecs<P: AsRef<Path> + Sync + Debug>(nbs: &[P]) -> IndexMap<String, KernelSpecInfo> {
+/// Kernel info plus the values of any `record_keys` present in the notebook.
+///
+/// Cell metadata is only recorded for cells with an id, since the id is used to find the cell
+/// again when restoring.
+pub(crate) fn extract_record(nb: &RawNotebook, record_keys: &[ExtraKey]) -> Option<KernelSpecInfo> {
+    let mut info = extract_kernel_info(nb).unwrap_or_default();
+    let (cell_keys, meta_keys) = partition_extra_keys(record_keys);
+    for key in meta_keys {
+        if let Some(value) = get_value_child(&nb.metadata, key.get_parts()) {
+            info.metadata.insert(key.to_string(), value.clone());
+        }
+    }
+    for cell in &nb.cells {
+        let Some(id) = cell.get_id() else {
+            continue;
+        };
+        let values: IndexMap<String, Value> = cell_keys
+            .iter()
+            .filter_map(|key| {
+                get_value_child(cell.get_metadata(), key.get_parts())
+                    .map(|value| (key.to_string(), value.clone()))
+            })
+            .collect();
+        if !values.is_empty() {
+            info.cells.insert(id.clone(), values);
+        }
+    }
+    if info == KernelSpecInfo::default() {
+        return None;
+    }
+    info.fingerprint = notebook_fingerprint(nb);
+    Some(info)
+}
+
+fn get_kernelspecs<P: AsRef<Path> + Sync + Debug>(
+    nbs: &[P],
+    work_tree: &Path,
+    record_keys: &[ExtraKey],
+) -> IndexMap<String, KernelSpecInfo> {
     nbs.par_iter()
         .map(|nb| (nb, read_nb(nb)))
         .filter_map(|(path, nb_res)| match nb_res {
@@ -125,7 +587,7 @@ fn get_kernelspecs<P: AsRef<Path> + Sync + Debug>(nbs: &[P]) -> IndexMap<String,
             Err(_) => None,
         })
         .filter_map(|(path, nb_res)| {
-            extract_kernel_info(&nb_res).map(|k| (relativize_path(path), k))
+            extract_record(&nb_res, record_keys).map(|k| (record_key(work_tree, path), k))
         })
         .collect()
 }
@@ -134,8 +596,14 @@ fn get_kernelspecs<P: AsRef<Path> + Sync + Debug>(nbs: &[P]) -> IndexMap<String,
 mod test {
     use serde_json::json;
 
-    use super::extract_kernel_info;
-    use crate::schema::RawNotebook;
+    use std::str::FromStr;
+
+    use super::{
+        KERNELSPEC_STORE_VERSION, KernelSpecInfo, RecordError, STALE_LOCK_AGE, StoreLock,
+        extract_kernel_info, extract_record, notebook_fingerprint, read_kernelspec_file,
+        update_kernelspec_file,
+    };
+    use crate::{extra_keys::ExtraKey, schema::RawNotebook};
 
     #[test]
     fn test_blank_not_recorded() {
@@ -170,4 +638,160 @@ mod test {
         assert_eq!(extracted.python_version, Some(python_version));
         assert_eq!(extracted.kernelspec, kernelspec);
     }
+
+    #[test]
+    fn test_record_keys_extracted() {
+        let notebook: RawNotebook = serde_json::from_value(json!({
+            "cells": [
+                {
+                    "cell_type": "markdown",
+                    "id": "intro",
+                    "metadata": {"collapsed": true, "tags": ["a"]},
+                    "source": []
+                },
+                {
+                    "cell_type": "markdown",
+                    "metadata": {"collapsed": true},
+                    "source": []
+                }
+            ],
+            "metadata": {"widgets": {"state": {}}},
+            "nbformat": 4,
+            "nbformat_minor": 5
+        }))
+        .unwrap();
+        let record_keys =
+            ["metadata.widgets", "cell.metadata.collapsed"].map(|k| ExtraKey::from_str(k).unwrap());
+        let extracted = extract_record(&notebook, &record_keys).unwrap();
+        assert!(extracted.kernelspec.is_null());
+        assert_eq!(
+            extracted.metadata.get("metadata.widgets"),
+            Some(&json!({"state": {}}))
+        );
+        assert_eq!(extracted.cells.len(), 1);
+        assert_eq!(
+            extracted.cells["intro"].get("cell.metadata.collapsed"),
+            Some(&json!(true))
+        );
+        assert!(extract_record(&notebook, &[]).is_none());
+    }
+
+    #[test]
+    fn test_notebook_fingerprint() {
+        let notebook: RawNotebook = serde_json::from_value(json!({
+            "cells": [
+                {
+                    "cell_type": "code",
+                    "execution_count": 1,
+                    "id": "a",
+                    "metadata": {},
+                    "outputs": [{"name": "stdout", "output_type": "stream", "text": ["1\n"]}],
+                    "source": ["print(", "1)"]
+                },
+                {"cell_type": "markdown", "metadata": {}, "source": "  "}
+            ],
+            "metadata": {"kernelspec": {"name": "python3"}},
+            "nbformat": 4,
+            "nbformat_minor": 5
+        }))
+        .unwrap();
+        let mut stripped = notebook.clone();
+        stripped.metadata = json!({});
+        stripped.cells.pop();
+        let cell = stripped.cells[0].as_codecell_mut().unwrap();
+        cell.clear_outputs();
+        cell.clear_counts();
+        cell.id = None;
+        let fingerprint = notebook_fingerprint(&notebook);
+        assert!(fingerprint.is_some());
+        assert_eq!(fingerprint, notebook_fingerprint(&stripped));
+
+        stripped.cells[0].as_codecell_mut().unwrap().source =
+            crate::schema::SourceValue::String("print(2)".into());
+        assert_ne!(fingerprint, notebook_fingerprint(&stripped));
+        assert!(notebook_fingerprint(&RawNotebook::new()).is_none());
+    }
+
+    #[test]
+    fn test_store_versions() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let store_path = temp_dir.path().join("kernelspec_store.json");
+        let info = KernelSpecInfo {
+            python_version: Some("3.12.4".to_string()),
+            ..Default::default()
+        };
+
+        // stores written before versioning are a plain map
+        std::fs::write(&store_path, json!({"a.ipynb": info}).to_string()).unwrap();
+        let records = read_kernelspec_file(&store_path).unwrap().unwrap();
+        assert_eq!(records.get("a.ipynb"), Some(&info));
+
+        update_kernelspec_file(&store_path, |records| {
+            records.insert("b.ipynb".to_string(), info.clone());
+        })
+        .unwrap();
+        let written: serde_json::Value =
+            serde_json::from_slice(&std::fs::read(&store_path).unwrap()).unwrap();
+        assert_eq!(written["version"], json!(KERNELSPEC_STORE_VERSION));
+        assert_eq!(read_kernelspec_file(&store_path).unwrap().unwrap().len(), 2);
+
+        std::fs::write(
+            &store_path,
+            json!({"version": KERNELSPEC_STORE_VERSION + 1, "notebooks": {}}).to_string(),
+        )
+        .unwrap();
+        assert!(matches!(
+            read_kernelspec_file(&store_path),
+            Err(RecordError::UnsupportedKernelspecFileVersion(_))
+        ));
+        // a newer store is never overwritten
+        assert!(update_kernelspec_file(&store_path, |_| {}).is_err());
+    }
+
+    #[test]
+    fn test_corrupt_store_recovered() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let store_path = temp_dir.path().join("kernelspec_store.json");
+        std::fs::write(&store_path, "{\"a.ipynb\": {").unwrap();
+        assert!(matches!(
+            read_kernelspec_file(&store_path),
+            Err(RecordError::InvalidKernelspecFile(_))
+        ));
+
+        update_kernelspec_file(&store_path, |records| {
+            records.insert("b.ipynb".to_string(), KernelSpecInfo::default());
+        })
+        .unwrap();
+        let records = read_kernelspec_file(&store_path).unwrap().unwrap();
+        assert_eq!(records.len(), 1);
+        assert!(
+            temp_dir
+                .path()
+                .join("kernelspec_store.json.corrupt")
+                .is_file()
+        );
+        assert!(!temp_dir.path().join("kernelspec_store.lock").exists());
+    }
+
+    #[test]
+    fn test_stale_lock_removed() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let store_path = temp_dir.path().join("kernelspec_store.json");
+        let lock_path = temp_dir.path().join("kernelspec_store.lock");
+        let stale_time = std::time::SystemTime::now() - STALE_LOCK_AGE * 2;
+        std::fs::File::create(&lock_path)
+            .unwrap()
+            .set_modified(stale_time)
+            .unwrap();
+        let lock = StoreLock::acquire(&store_path).unwrap();
+        assert!(lock_path.is_file());
+        drop(lock);
+        assert!(!lock_path.exists());
+
+        // a lock taken after the stale one was found is put back rather than removed
+        std::fs::File::create(&lock_path).unwrap();
+        StoreLock::remove_stale(&lock_path, stale_time).unwrap();
+        assert!(lock_path.is_file());
+        assert_eq!(std::fs::read_dir(temp_dir.path()).unwrap().count(), 1);
+    }
 }
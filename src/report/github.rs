@@ -0,0 +1,68 @@
+use std::{io::Write, path::Path};
+
+use anyhow::Error;
+
+use super::{Reporter, path_results};
+use crate::{
+    check::{LocatedCheckResult, PathCheckResult},
+    files::relativize_path,
+};
+
+/// GitHub Actions workflow commands, which show each finding as an annotation on its notebook.
+pub(super) struct GithubReporter;
+
+/// Escape the message of a workflow command.
+fn escape_data(value: &str) -> String {
+    value
+        .replace('%', "%25")
+        .replace('\r', "%0D")
+        .replace('\n', "%0A")
+}
+
+/// Escape a property of a workflow command, such as `file`.
+fn escape_property(value: &str) -> String {
+    escape_data(value).replace(':', "%3A").replace(',', "%2C")
+}
+
+impl Reporter for GithubReporter {
+    fn report(
+        &self,
+        results: &[(&Path, Vec<LocatedCheckResult>)],
+        out: &mut dyn Write,
+    ) -> Result<(), Error> {
+        for PathCheckResult {
+            path,
+            result,
+            severity,
+            span,
+        } in path_results(results)
+        {
+            let rule = result.rule();
+            let position = span.map_or_else(String::new, |span| {
+                format!(
+                    ",line={},col={},endLine={},endColumn={}",
+                    span.start.line, span.start.column, span.end.line, span.end.column
+                )
+            });
+            writeln!(
+                out,
+                "::{severity} file={}{position},title={}::{}",
+                escape_property(&relativize_path(path)),
+                escape_property(&format!("{} {}", rule.code(), rule.name())),
+                escape_data(&result.to_string())
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_escape() {
+        assert_eq!(escape_data("50%\nof cells"), "50%25%0Aof cells");
+        assert_eq!(escape_property("a:b,c"), "a%3Ab%2Cc");
+    }
+}
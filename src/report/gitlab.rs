@@ -0,0 +1,91 @@
+use std::{io::Write, path::Path};
+
+use anyhow::Error;
+use serde::Serialize;
+
+use super::{Reporter, path_results};
+use crate::{
+    check::{LocatedCheckResult, PathCheckResult},
+    files::relativize_path,
+    rules::Severity,
+};
+
+/// A GitLab Code Quality report, which shows findings in merge requests.
+pub(super) struct GitlabReporter;
+
+#[derive(Debug, Serialize)]
+struct CodeQualityIssue {
+    description: String,
+    check_name: &'static str,
+    fingerprint: String,
+    severity: &'static str,
+    location: IssueLocation,
+}
+
+#[derive(Debug, Serialize)]
+struct IssueLocation {
+    path: String,
+    lines: IssueLines,
+}
+
+#[derive(Debug, Serialize)]
+struct IssueLines {
+    begin: usize,
+}
+
+/// Identifies a finding across pipelines, so that GitLab can tell new findings from fixed ones.
+fn fingerprint(path: &str, check_name: &str, description: &str) -> Result<String, Error> {
+    let mut hasher = gix::hash::hasher(gix::hash::Kind::Sha1);
+    for part in [path, check_name, description] {
+        hasher.update(part.as_bytes());
+        hasher.update(b"\0");
+    }
+    Ok(hasher.try_finalize()?.to_hex().to_string())
+}
+
+impl TryFrom<PathCheckResult<'_>> for CodeQualityIssue {
+    type Error = Error;
+
+    fn try_from(
+        PathCheckResult {
+            path,
+            result,
+            severity,
+            span,
+        }: PathCheckResult,
+    ) -> Result<Self, Error> {
+        let path = relativize_path(path);
+        let description = result.to_string();
+        let check_name = result.rule().code();
+        Ok(Self {
+            fingerprint: fingerprint(&path, check_name, &description)?,
+            description,
+            check_name,
+            severity: match severity {
+                Severity::Warning => "minor",
+                Severity::Error => "major",
+            },
+            location: IssueLocation {
+                path,
+                lines: IssueLines {
+                    begin: span.map_or(1, |span| span.start.line),
+                },
+            },
+        })
+    }
+}
+
+impl Reporter for GitlabReporter {
+    fn report(
+        &self,
+        results: &[(&Path, Vec<LocatedCheckResult>)],
+        out: &mut dyn Write,
+    ) -> Result<(), Error> {
+        let issues: Vec<CodeQualityIssue> = path_results(results)
+            .into_iter()
+            .map(CodeQualityIssue::try_from)
+            .collect::<Result<_, _>>()?;
+        writeln!(out, "{}", serde_json::to_string_pretty(&issues)?)?;
+        Ok(())
+    }
+}
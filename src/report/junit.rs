@@ -0,0 +1,92 @@
+use std::{io::Write, path::Path};
+
+use anyhow::Error;
+
+use super::Reporter;
+use crate::{check::LocatedCheckResult, files::relativize_path, rules::Severity};
+
+/// A JUnit XML report with a test case for each notebook, which fails if it has any errors.
+/// Warnings are written to the output of the test case.
+pub(super) struct JunitReporter;
+
+fn escape_xml(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+/// Write the findings of a notebook with `severity`, one per line.
+fn write_findings(
+    out: &mut dyn Write,
+    res: &[LocatedCheckResult],
+    severity: Severity,
+) -> Result<(), Error> {
+    for LocatedCheckResult { result, span, .. } in res.iter().filter(|r| r.severity == severity) {
+        let position = span.map_or_else(String::new, |span| {
+            format!("{}:{}: ", span.start.line, span.start.column)
+        });
+        writeln!(
+            out,
+            "{}: {position}{}",
+            result.rule().code(),
+            escape_xml(&result.to_string())
+        )?;
+    }
+    Ok(())
+}
+
+fn count(res: &[LocatedCheckResult], severity: Severity) -> usize {
+    res.iter().filter(|r| r.severity == severity).count()
+}
+
+impl Reporter for JunitReporter {
+    fn report(
+        &self,
+        results: &[(&Path, Vec<LocatedCheckResult>)],
+        out: &mut dyn Write,
+    ) -> Result<(), Error> {
+        let tests = results.len();
+        let failures = results
+            .iter()
+            .filter(|(_, res)| count(res, Severity::Error) > 0)
+            .count();
+        writeln!(out, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+        writeln!(
+            out,
+            r#"<testsuites name="nbwipers" tests="{tests}" failures="{failures}">"#
+        )?;
+        writeln!(
+            out,
+            r#"  <testsuite name="nbwipers check" tests="{tests}" failures="{failures}">"#
+        )?;
+        for (path, res) in results {
+            let name = escape_xml(&relativize_path(path));
+            if res.is_empty() {
+                writeln!(out, r#"    <testcase name="{name}" classname="nbwipers"/>"#)?;
+                continue;
+            }
+            writeln!(out, r#"    <testcase name="{name}" classname="nbwipers">"#)?;
+            let errors = count(res, Severity::Error);
+            if errors > 0 {
+                writeln!(
+                    out,
+                    r#"      <failure message="Found {errors} items to strip" type="nbwipers">"#,
+                )?;
+                write_findings(out, res, Severity::Error)?;
+                writeln!(out, "      </failure>")?;
+            }
+            if count(res, Severity::Warning) > 0 {
+                writeln!(out, "      <system-out>")?;
+                write_findings(out, res, Severity::Warning)?;
+                writeln!(out, "      </system-out>")?;
+            }
+            writeln!(out, "    </testcase>")?;
+        }
+        writeln!(out, "  </testsuite>")?;
+        writeln!(out, "</testsuites>")?;
+        Ok(())
+    }
+}
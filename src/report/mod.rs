@@ -0,0 +1,108 @@
+mod github;
+mod gitlab;
+mod junit;
+mod sarif;
+
+use std::{io::Write, path::Path};
+
+use anyhow::Error;
+use colored::Colorize;
+
+use crate::{
+    check::{LocatedCheckResult, PathCheckResult},
+    cli::CheckOutputFormat,
+    files::relativize_path,
+    rules::Severity,
+    settings::Settings,
+};
+
+/// Writes the results of `check` in one output format.
+///
+/// Reporters are given the results of every notebook that was checked, including notebooks
+/// without any findings.
+pub trait Reporter {
+    fn report(
+        &self,
+        results: &[(&Path, Vec<LocatedCheckResult>)],
+        out: &mut dyn Write,
+    ) -> Result<(), Error>;
+}
+
+/// The reporter for `format`, which describes the rules with their severity in `settings`.
+pub fn reporter(format: CheckOutputFormat, settings: &Settings) -> Box<dyn Reporter> {
+    match format {
+        CheckOutputFormat::Text => Box::new(TextReporter),
+        CheckOutputFormat::Json => Box::new(JsonReporter),
+        CheckOutputFormat::Sarif => Box::new(sarif::SarifReporter::new(settings)),
+        CheckOutputFormat::Github => Box::new(github::GithubReporter),
+        CheckOutputFormat::Gitlab => Box::new(gitlab::GitlabReporter),
+        CheckOutputFormat::Junit => Box::new(junit::JunitReporter),
+    }
+}
+
+/// Each finding with the path of its notebook.
+fn path_results<'a>(
+    results: &'a [(&'a Path, Vec<LocatedCheckResult>)],
+) -> Vec<PathCheckResult<'a>> {
+    results
+        .iter()
+        .flat_map(|(path, res)| {
+            res.iter().map(|located| PathCheckResult {
+                path,
+                result: &located.result,
+                severity: located.severity,
+                span: located.span,
+            })
+        })
+        .collect()
+}
+
+struct TextReporter;
+
+impl Reporter for TextReporter {
+    fn report(
+        &self,
+        results: &[(&Path, Vec<LocatedCheckResult>)],
+        out: &mut dyn Write,
+    ) -> Result<(), Error> {
+        for PathCheckResult {
+            path,
+            result,
+            severity,
+            span,
+        } in path_results(results)
+        {
+            let rel_path = relativize_path(path).bold();
+            let label = match severity {
+                Severity::Warning => format!("{}: ", "warning".yellow()),
+                Severity::Error => String::new(),
+            };
+            match span {
+                Some(span) => writeln!(
+                    out,
+                    "{rel_path}:{}:{}:{label}{result}",
+                    span.start.line, span.start.column
+                )?,
+                None => writeln!(out, "{rel_path}:{label}{result}")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+struct JsonReporter;
+
+impl Reporter for JsonReporter {
+    fn report(
+        &self,
+        results: &[(&Path, Vec<LocatedCheckResult>)],
+        out: &mut dyn Write,
+    ) -> Result<(), Error> {
+        write!(
+            out,
+            "{}",
+            serde_json::to_string_pretty(&path_results(results))?
+        )?;
+        Ok(())
+    }
+}
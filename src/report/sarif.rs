@@ -0,0 +1,211 @@
+use std::{io::Write, path::Path};
+
+use anyhow::Error;
+use serde::Serialize;
+use strum::IntoEnumIterator;
+
+use super::{Reporter, path_results};
+use crate::{
+    check::{LocatedCheckResult, PathCheckResult},
+    files::relativize_path,
+    rules::{Rule, Severity},
+    settings::Settings,
+    span::Span,
+};
+
+const SARIF_SCHEMA: &str = "https://json.schemastore.org/sarif-2.1.0.json";
+const SARIF_VERSION: &str = "2.1.0";
+
+/// A SARIF 2.1.0 log, as read by code scanning tools.
+pub(super) struct SarifReporter {
+    /// the severity of each rule, in the order of `Rule::iter`
+    severities: Vec<Option<Severity>>,
+}
+
+impl SarifReporter {
+    pub(super) fn new(settings: &Settings) -> Self {
+        Self {
+            severities: Rule::iter().map(|rule| settings.severity(rule)).collect(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct SarifLog {
+    #[serde(rename = "$schema")]
+    schema: &'static str,
+    version: &'static str,
+    runs: Vec<Run>,
+}
+
+#[derive(Debug, Serialize)]
+struct Run {
+    tool: Tool,
+    results: Vec<SarifResult>,
+}
+
+#[derive(Debug, Serialize)]
+struct Tool {
+    driver: Driver,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Driver {
+    name: &'static str,
+    version: &'static str,
+    information_uri: &'static str,
+    rules: Vec<ReportingDescriptor>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ReportingDescriptor {
+    id: &'static str,
+    name: &'static str,
+    short_description: Message,
+    default_configuration: ReportingConfiguration,
+}
+
+#[derive(Debug, Serialize)]
+struct ReportingConfiguration {
+    level: &'static str,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SarifResult {
+    rule_id: &'static str,
+    rule_index: usize,
+    level: Severity,
+    message: Message,
+    locations: Vec<Location>,
+}
+
+#[derive(Debug, Serialize)]
+struct Message {
+    text: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Location {
+    physical_location: PhysicalLocation,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct PhysicalLocation {
+    artifact_location: ArtifactLocation,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    region: Option<Region>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Region {
+    start_line: usize,
+    start_column: usize,
+    end_line: usize,
+    end_column: usize,
+}
+
+impl From<Span> for Region {
+    fn from(span: Span) -> Self {
+        Self {
+            start_line: span.start.line,
+            start_column: span.start.column,
+            end_line: span.end.line,
+            end_column: span.end.column,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct ArtifactLocation {
+    uri: String,
+}
+
+impl ReportingDescriptor {
+    /// The descriptor of `rule`, whose findings have `severity`, or are not reported if `None`.
+    fn new(rule: Rule, severity: Option<Severity>) -> Self {
+        let level = match severity {
+            Some(Severity::Error) => "error",
+            Some(Severity::Warning) => "warning",
+            None => "none",
+        };
+        Self {
+            id: rule.code(),
+            name: rule.name(),
+            short_description: Message {
+                text: rule.description().to_string(),
+            },
+            default_configuration: ReportingConfiguration { level },
+        }
+    }
+}
+
+impl From<&PathCheckResult<'_>> for SarifResult {
+    fn from(
+        PathCheckResult {
+            path,
+            result,
+            severity,
+            span,
+        }: &PathCheckResult,
+    ) -> Self {
+        let rule = result.rule();
+        Self {
+            rule_id: rule.code(),
+            #[allow(clippy::unwrap_used)] // every rule is listed
+            rule_index: Rule::iter().position(|r| r == rule).unwrap(),
+            level: *severity,
+            message: Message {
+                text: result.to_string(),
+            },
+            locations: vec![Location {
+                physical_location: PhysicalLocation {
+                    artifact_location: ArtifactLocation {
+                        uri: relativize_path(path),
+                    },
+                    region: span.map(Region::from),
+                },
+            }],
+        }
+    }
+}
+
+impl SarifLog {
+    fn new(check_results: &[PathCheckResult], severities: &[Option<Severity>]) -> Self {
+        Self {
+            schema: SARIF_SCHEMA,
+            version: SARIF_VERSION,
+            runs: vec![Run {
+                tool: Tool {
+                    driver: Driver {
+                        name: env!("CARGO_PKG_NAME"),
+                        version: env!("CARGO_PKG_VERSION"),
+                        information_uri: env!("CARGO_PKG_REPOSITORY"),
+                        rules: Rule::iter()
+                            .zip(severities)
+                            .map(|(rule, severity)| ReportingDescriptor::new(rule, *severity))
+                            .collect(),
+                    },
+                },
+                results: check_results.iter().map(SarifResult::from).collect(),
+            }],
+        }
+    }
+}
+
+impl Reporter for SarifReporter {
+    fn report(
+        &self,
+        results: &[(&Path, Vec<LocatedCheckResult>)],
+        out: &mut dyn Write,
+    ) -> Result<(), Error> {
+        let log = SarifLog::new(&path_results(results), &self.severities);
+        writeln!(out, "{}", serde_json::to_string_pretty(&log)?)?;
+        Ok(())
+    }
+}
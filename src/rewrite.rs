@@ -0,0 +1,391 @@
+use std::{
+    io::{BufRead, BufReader, BufWriter, Read, Write},
+    path::{Path, PathBuf},
+    process::{Child, ChildStdin, ChildStdout, Command, Stdio},
+};
+
+use anyhow::{Context, Error, bail};
+use colored::Colorize;
+use indexmap::IndexMap;
+use rustc_hash::{FxHashMap, FxHashSet};
+
+use crate::{
+    cli::RewriteHistoryCommand,
+    files::{check_exclusions, normalize_path_to},
+    schema::RawNotebook,
+    settings::Settings,
+    strip::{strip_nb, write_nb},
+};
+
+/// Outcome of stripping a single blob, cached by its object id.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct BlobRewrite {
+    new_id: Option<String>,
+    original_size: u64,
+    stripped_size: u64,
+}
+
+impl BlobRewrite {
+    const fn saved(&self) -> u64 {
+        self.original_size.saturating_sub(self.stripped_size)
+    }
+}
+
+/// A long-running `git cat-file --batch` process used to read blob contents.
+struct BlobReader {
+    child: Child,
+    stdin: ChildStdin,
+    stdout: BufReader<ChildStdout>,
+}
+
+impl BlobReader {
+    fn new(repo_dir: &Path) -> Result<Self, Error> {
+        let mut child = Command::new("git")
+            .current_dir(repo_dir)
+            .args(["cat-file", "--batch"])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()?;
+        let stdin = child.stdin.take().context("Could not open stdin")?;
+        let stdout = BufReader::new(child.stdout.take().context("Could not open stdout")?);
+        Ok(Self {
+            child,
+            stdin,
+            stdout,
+        })
+    }
+
+    fn read_blob(&mut self, id: &str) -> Result<Vec<u8>, Error> {
+        writeln!(self.stdin, "{id}")?;
+        self.stdin.flush()?;
+        let mut header = String::new();
+        self.stdout.read_line(&mut header)?;
+        let mut parts = header.split_whitespace();
+        let (Some(_), Some("blob"), Some(size)) = (parts.next(), parts.next(), parts.next()) else {
+            bail!("Could not read blob {id}: {}", header.trim());
+        };
+        let mut contents = vec![0; size.parse()?];
+        self.stdout.read_exact(&mut contents)?;
+        // each object is followed by a newline
+        let mut newline = [0; 1];
+        self.stdout.read_exact(&mut newline)?;
+        Ok(contents)
+    }
+
+    fn finish(mut self) -> Result<(), Error> {
+        drop(self.stdin);
+        self.child.wait()?;
+        Ok(())
+    }
+}
+
+fn write_blob(repo_dir: &Path, contents: &[u8]) -> Result<String, Error> {
+    let mut child = Command::new("git")
+        .current_dir(repo_dir)
+        .args(["hash-object", "-w", "--stdin"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()?;
+    child
+        .stdin
+        .take()
+        .context("Could not open stdin")?
+        .write_all(contents)?;
+    let output = child.wait_with_output()?;
+    if !output.status.success() {
+        bail!("Git hash-object failed");
+    }
+    Ok(String::from_utf8(output.stdout)?.trim().to_owned())
+}
+
+fn strip_blob(contents: &[u8], settings: &Settings) -> Option<Vec<u8>> {
+    let nb: RawNotebook = serde_json::from_slice(contents).ok()?;
+    let (stripped_nb, stripped) = strip_nb(nb, settings);
+    if !stripped {
+        return None;
+    }
+    let mut out = Vec::new();
+    write_nb(&mut out, &stripped_nb).ok()?;
+    Some(out)
+}
+
+/// Undo the C-style quoting git applies to paths with unusual characters.
+fn unquote_path(path: &[u8]) -> Vec<u8> {
+    let Some(inner) = path.strip_prefix(b"\"").and_then(|p| p.strip_suffix(b"\"")) else {
+        return path.to_vec();
+    };
+    let mut out = Vec::with_capacity(inner.len());
+    let mut iter = inner.iter().copied().peekable();
+    while let Some(c) = iter.next() {
+        if c != b'\\' {
+            out.push(c);
+            continue;
+        }
+        match iter.next() {
+            Some(b'n') => out.push(b'\n'),
+            Some(b't') => out.push(b'\t'),
+            Some(d @ b'0'..=b'7') => {
+                let mut value = d - b'0';
+                for _ in 0..2 {
+                    if let Some(d @ b'0'..=b'7') = iter.peek().copied() {
+                        value = value.wrapping_mul(8) + (d - b'0');
+                        iter.next();
+                    }
+                }
+                out.push(value);
+            }
+            Some(other) => out.push(other),
+            None => {}
+        }
+    }
+    out
+}
+
+fn is_object_id(s: &str) -> bool {
+    matches!(s.len(), 40 | 64) && s.bytes().all(|b| b.is_ascii_hexdigit())
+}
+
+struct HistoryRewriter<'a> {
+    repo_dir: &'a Path,
+    settings: &'a Settings,
+    dry_run: bool,
+    reader: BlobReader,
+    cache: FxHashMap<String, BlobRewrite>,
+    seen: FxHashSet<(String, String)>,
+    savings: IndexMap<String, u64>,
+}
+
+impl<'a> HistoryRewriter<'a> {
+    fn new(repo_dir: &'a Path, settings: &'a Settings, dry_run: bool) -> Result<Self, Error> {
+        Ok(Self {
+            repo_dir,
+            settings,
+            dry_run,
+            reader: BlobReader::new(repo_dir)?,
+            cache: FxHashMap::default(),
+            seen: FxHashSet::default(),
+            savings: IndexMap::new(),
+        })
+    }
+
+    fn rewrite_blob(&mut self, id: &str) -> Result<BlobRewrite, Error> {
+        if let Some(cached) = self.cache.get(id) {
+            return Ok(cached.clone());
+        }
+        let contents = self.reader.read_blob(id)?;
+        let original_size = contents.len() as u64;
+        let rewrite = match strip_blob(&contents, self.settings) {
+            Some(stripped) => BlobRewrite {
+                new_id: if self.dry_run {
+                    None
+                } else {
+                    Some(write_blob(self.repo_dir, &stripped)?)
+                },
+                original_size,
+                stripped_size: stripped.len() as u64,
+            },
+            None => BlobRewrite {
+                new_id: None,
+                original_size,
+                stripped_size: original_size,
+            },
+        };
+        self.cache.insert(id.to_owned(), rewrite.clone());
+        Ok(rewrite)
+    }
+
+    /// Rewrite a `M <mode> <dataref> <path>` filemodify command, if it refers to a notebook.
+    fn rewrite_filemodify(&mut self, line: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+        let mut parts = line.splitn(4, |b| *b == b' ');
+        let (Some(b"M"), Some(mode), Some(dataref), Some(path)) =
+            (parts.next(), parts.next(), parts.next(), parts.next())
+        else {
+            return Ok(None);
+        };
+        let Ok(dataref) = std::str::from_utf8(dataref) else {
+            return Ok(None);
+        };
+        if !matches!(mode, b"100644" | b"100755") || !is_object_id(dataref) {
+            return Ok(None);
+        }
+        let path = String::from_utf8_lossy(&unquote_path(path)).into_owned();
+        if Path::new(&path).extension().and_then(|e| e.to_str()) != Some("ipynb")
+            || check_exclusions(&normalize_path_to(&path, self.repo_dir), self.settings)
+        {
+            return Ok(None);
+        }
+        let rewrite = self.rewrite_blob(dataref)?;
+        // count each version of a notebook once, however many commits contain it
+        if self.seen.insert((path.clone(), dataref.to_owned())) {
+            *self.savings.entry(path).or_default() += rewrite.saved();
+        }
+        Ok(rewrite.new_id.map(|new_id| {
+            let mut out = line.to_vec();
+            let start = 2 + mode.len() + 1;
+            out.splice(start..start + dataref.len(), new_id.bytes());
+            out
+        }))
+    }
+
+    /// Copy a `git fast-export` stream to `writer`, replacing notebook blobs with stripped ones.
+    fn process_stream<R: BufRead, W: Write>(
+        &mut self,
+        mut reader: R,
+        mut writer: W,
+    ) -> Result<(), Error> {
+        let mut line = Vec::new();
+        loop {
+            line.clear();
+            if reader.read_until(b'\n', &mut line)? == 0 {
+                break;
+            }
+            let content = line.strip_suffix(b"\n").unwrap_or(&line);
+            if let Some(len) = content.strip_prefix(b"data ") {
+                // copy commit and tag messages verbatim
+                let len: u64 = std::str::from_utf8(len)?.parse()?;
+                writer.write_all(&line)?;
+                std::io::copy(&mut (&mut reader).take(len), &mut writer)?;
+            } else if let Some(rewritten) = self.rewrite_filemodify(content)? {
+                writer.write_all(&rewritten)?;
+                writer.write_all(b"\n")?;
+            } else {
+                writer.write_all(&line)?;
+            }
+        }
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+fn current_branch(repo_dir: &Path) -> Result<String, Error> {
+    let output = Command::new("git")
+        .current_dir(repo_dir)
+        .args(["symbolic-ref", "-q", "HEAD"])
+        .output()?;
+    if !output.status.success() {
+        bail!("HEAD is detached. Please specify the branch to rewrite");
+    }
+    Ok(String::from_utf8(output.stdout)?.trim().to_owned())
+}
+
+fn get_work_tree() -> Result<PathBuf, Error> {
+    let cur_dir = std::env::current_dir()?;
+    let (git_dir, _) = gix::discover::upwards(&cur_dir)?;
+    git_dir
+        .into_repository_and_work_tree_directories()
+        .1
+        .context("Cannot rewrite history of a bare repository")
+}
+
+/// Feed the rewritten export stream to fast-import.
+///
+/// The stream ends with `done` only once the whole export has been processed and fast-export has
+/// succeeded, so a fast-import run with `--done` fails rather than importing truncated history.
+fn import_history<R: BufRead, W: Write>(
+    rewriter: &mut HistoryRewriter,
+    export_out: R,
+    mut import_in: W,
+    export: &mut Child,
+) -> Result<(), Error> {
+    import_in.write_all(b"feature done\n")?;
+    rewriter.process_stream(export_out, &mut import_in)?;
+    if !export.wait()?.success() {
+        bail!("Git fast-export failed");
+    }
+    import_in.write_all(b"done\n")?;
+    import_in.flush()?;
+    Ok(())
+}
+
+pub fn rewrite_history(cmd: RewriteHistoryCommand) -> Result<(), Error> {
+    let (args, overrides) = cmd.common.partition();
+    let settings = Settings::construct(args.config.as_deref(), args.isolated, &overrides)?;
+    let repo_dir = get_work_tree()?;
+    let refs = if cmd.refs.is_empty() {
+        vec![current_branch(&repo_dir)?]
+    } else {
+        cmd.refs
+    };
+
+    let mut export = Command::new("git")
+        .current_dir(&repo_dir)
+        .args(["fast-export", "--no-data", "--signed-tags=strip"])
+        .args(&refs)
+        .stdout(Stdio::piped())
+        .spawn()?;
+    let export_out = BufReader::new(export.stdout.take().context("Could not open stdout")?);
+
+    let mut rewriter = HistoryRewriter::new(&repo_dir, &settings, cmd.dry_run)?;
+    if cmd.dry_run {
+        rewriter.process_stream(export_out, std::io::sink())?;
+        if !export.wait()?.success() {
+            bail!("Git fast-export failed");
+        }
+    } else {
+        let mut import = Command::new("git")
+            .current_dir(&repo_dir)
+            .args(["fast-import", "--force", "--quiet", "--done"])
+            .stdin(Stdio::piped())
+            .spawn()?;
+        let import_in = BufWriter::new(import.stdin.take().context("Could not open stdin")?);
+        if let Err(e) = import_history(&mut rewriter, export_out, import_in, &mut export) {
+            // without `done`, fast-import must not be left to update the refs to partial history
+            let _ = import.kill();
+            let _ = import.wait();
+            let _ = export.kill();
+            return Err(e);
+        }
+        if !import.wait()?.success() {
+            bail!("Git fast-import failed");
+        }
+    }
+    let HistoryRewriter {
+        reader,
+        mut savings,
+        ..
+    } = rewriter;
+    reader.finish()?;
+
+    savings.retain(|_, saved| *saved > 0);
+    savings.sort_by(|_, a, _, b| b.cmp(a));
+    let total: u64 = savings.values().sum();
+    for (path, saved) in &savings {
+        println!("{}: {saved} bytes", path.bold());
+    }
+    if cmd.dry_run {
+        println!(
+            "Would save {total} bytes across {} notebooks",
+            savings.len()
+        );
+    } else {
+        println!("Saved {total} bytes across {} notebooks", savings.len());
+        println!(
+            "Rewrote {}. Run `git reset --hard` to update the working tree",
+            refs.join(", ")
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unquote_path() {
+        assert_eq!(unquote_path(b"plain.ipynb"), b"plain.ipynb");
+        assert_eq!(unquote_path(b"\"a b.ipynb\""), b"a b.ipynb");
+        assert_eq!(unquote_path(b"\"a\\\"b\\\\.ipynb\""), b"a\"b\\.ipynb");
+        assert_eq!(
+            unquote_path(b"\"\\303\\244.ipynb\""),
+            "\u{e4}.ipynb".as_bytes()
+        );
+    }
+
+    #[test]
+    fn test_is_object_id() {
+        assert!(is_object_id("0967ef424bce6791893e9a57bb952f80fd536e93"));
+        assert!(!is_object_id(":12"));
+        assert!(!is_object_id("inline"));
+    }
+}
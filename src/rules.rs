@@ -0,0 +1,215 @@
+use std::{fmt::Display, str::FromStr};
+
+use serde::{Deserialize, Serialize, de};
+use strum::{EnumIter, IntoEnumIterator};
+use thiserror::Error;
+
+/// The kinds of finding reported by `check`, each with a stable code.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, EnumIter)]
+pub enum Rule {
+    OutputPresent,
+    ExecutionCount,
+    CellId,
+    DroppedCell,
+    TaggedSource,
+    NotebookMetadata,
+    CellMetadata,
+    NbformatDowngrade,
+    DisallowedKernel,
+    WrongLanguage,
+    WrongLanguageVersion,
+    NonCanonicalKernelspec,
+    IOError,
+    InvalidNotebook,
+}
+
+impl Rule {
+    pub const fn code(self) -> &'static str {
+        match self {
+            Self::OutputPresent => "NBW001",
+            Self::ExecutionCount => "NBW002",
+            Self::CellId => "NBW003",
+            Self::DroppedCell => "NBW004",
+            Self::TaggedSource => "NBW005",
+            Self::NotebookMetadata => "NBW010",
+            Self::CellMetadata => "NBW011",
+            Self::NbformatDowngrade => "NBW020",
+            Self::DisallowedKernel => "NBW030",
+            Self::WrongLanguage => "NBW031",
+            Self::WrongLanguageVersion => "NBW032",
+            Self::NonCanonicalKernelspec => "NBW033",
+            Self::IOError => "NBW900",
+            Self::InvalidNotebook => "NBW901",
+        }
+    }
+
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::OutputPresent => "output-present",
+            Self::ExecutionCount => "execution-count",
+            Self::CellId => "cell-id",
+            Self::DroppedCell => "dropped-cell",
+            Self::TaggedSource => "tagged-source",
+            Self::NotebookMetadata => "notebook-metadata",
+            Self::CellMetadata => "cell-metadata",
+            Self::NbformatDowngrade => "nbformat-downgrade",
+            Self::DisallowedKernel => "disallowed-kernel",
+            Self::WrongLanguage => "wrong-language",
+            Self::WrongLanguageVersion => "wrong-language-version",
+            Self::NonCanonicalKernelspec => "non-canonical-kernelspec",
+            Self::IOError => "io-error",
+            Self::InvalidNotebook => "invalid-notebook",
+        }
+    }
+
+    pub const fn description(self) -> &'static str {
+        match self {
+            Self::OutputPresent => "Code cell has outputs that cleaning would clear",
+            Self::ExecutionCount => "Code cell has an execution count that cleaning would clear",
+            Self::CellId => "Cell has an id that cleaning would remove or renumber",
+            Self::DroppedCell => "Cell is empty or has a tag that makes cleaning drop it",
+            Self::TaggedSource => "Cell has a tag in `clear-source-tags` and source to clear",
+            Self::NotebookMetadata => "Notebook has a metadata key that cleaning would remove",
+            Self::CellMetadata => "Cell has a metadata key that cleaning would remove",
+            Self::NbformatDowngrade => {
+                "Notebook format would be downgraded to 4.4 because cell ids are dropped"
+            }
+            Self::DisallowedKernel => "Notebook kernel is not one of `allowed-kernels`",
+            Self::WrongLanguage => "Notebook language is not `required-language`",
+            Self::WrongLanguageVersion => {
+                "Notebook language version does not match `required-language-version`"
+            }
+            Self::NonCanonicalKernelspec => {
+                "Notebook kernelspec differs from `canonical-kernelspec`"
+            }
+            Self::IOError => "Notebook could not be read",
+            Self::InvalidNotebook => "File is not a valid notebook",
+        }
+    }
+
+    /// Whether the rule is about a notebook that could not be read. Such findings are always
+    /// errors, whichever rules are selected.
+    pub const fn is_read_error(self) -> bool {
+        matches!(self, Self::IOError | Self::InvalidNotebook)
+    }
+}
+
+/// How a finding affects `check`: errors make it fail, while warnings are only reported.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Warning,
+    #[default]
+    Error,
+}
+
+impl Display for Severity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Warning => write!(f, "warning"),
+            Self::Error => write!(f, "error"),
+        }
+    }
+}
+
+/// Selects the rules whose codes start with a prefix, such as `NBW0` or `NBW001`, or every rule
+/// with `ALL`.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct RuleSelector {
+    prefix: String,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Error)]
+#[error("`{0}` does not match any rule code")]
+pub struct RuleSelectorParseError(String);
+
+impl RuleSelector {
+    pub const fn all() -> Self {
+        Self {
+            prefix: String::new(),
+        }
+    }
+
+    pub fn matches(&self, rule: Rule) -> bool {
+        rule.code().starts_with(&self.prefix)
+    }
+}
+
+impl From<Rule> for RuleSelector {
+    fn from(rule: Rule) -> Self {
+        Self {
+            prefix: rule.code().to_owned(),
+        }
+    }
+}
+
+impl FromStr for RuleSelector {
+    type Err = RuleSelectorParseError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.eq_ignore_ascii_case("ALL") {
+            return Ok(Self::all());
+        }
+        let selector = Self {
+            prefix: s.to_ascii_uppercase(),
+        };
+        if s.is_empty() || !Rule::iter().any(|rule| selector.matches(rule)) {
+            return Err(RuleSelectorParseError(s.to_owned()));
+        }
+        Ok(selector)
+    }
+}
+
+impl Display for RuleSelector {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.prefix.is_empty() {
+            write!(f, "ALL")
+        } else {
+            write!(f, "{}", self.prefix)
+        }
+    }
+}
+
+impl Serialize for RuleSelector {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+impl<'de> Deserialize<'de> for RuleSelector {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let str_result = String::deserialize(deserializer)?;
+        Self::from_str(&str_result).map_err(|_| {
+            de::Error::invalid_value(
+                de::Unexpected::Str(&str_result),
+                &"`ALL` or the prefix of a rule code, such as `NBW0` or `NBW001`",
+            )
+        })
+    }
+}
+
+#[allow(clippy::unwrap_used)]
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rule_selector() {
+        let selector = RuleSelector::from_str("nbw00").unwrap();
+        assert!(selector.matches(Rule::OutputPresent));
+        assert!(!selector.matches(Rule::NotebookMetadata));
+        assert_eq!(selector.to_string(), "NBW00");
+        assert!(
+            RuleSelector::from_str("ALL")
+                .unwrap()
+                .matches(Rule::IOError)
+        );
+        assert!(RuleSelector::from_str("NBW5").is_err());
+        assert!(RuleSelector::from_str("").is_err());
+    }
+}
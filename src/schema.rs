@@ -167,7 +167,10 @@ mod tests {
 
         assert!(code.is_clear_outputs());
         assert!(!code.is_clear_exec_count());
-        assert!(code.should_clear_output(true, true));
+        let settings = crate::config::Configuration::default()
+            .into_settings()
+            .unwrap();
+        assert!(code.should_clear_output(&settings));
         code.clear_counts();
         code.clear_outputs();
         assert!(code.is_clear_outputs());
@@ -0,0 +1,608 @@
+use std::{collections::HashMap, path::PathBuf};
+
+use anyhow::Error;
+use lsp_server::{Connection, ErrorCode, Message, Notification, Request, Response};
+use lsp_types::{
+    CodeAction, CodeActionKind, CodeActionOptions, CodeActionOrCommand, CodeActionParams,
+    CodeActionProviderCapability, Diagnostic, DiagnosticSeverity, DidChangeNotebookDocumentParams,
+    DidChangeTextDocumentParams, DidCloseNotebookDocumentParams, DidCloseTextDocumentParams,
+    DidOpenNotebookDocumentParams, DidOpenTextDocumentParams, LSPObject, Notebook, NotebookCell,
+    NotebookCellKind, NotebookDocumentSyncOptions, NotebookSelector, NumberOrString, OneOf,
+    Position, PublishDiagnosticsParams, Range, ServerCapabilities, TextDocumentSyncCapability,
+    TextDocumentSyncKind, TextEdit, Uri, WorkspaceEdit,
+    notification::{self, Notification as _},
+    request::{self, Request as _},
+};
+use rustc_hash::FxHashMap;
+use serde_json::{Value, json};
+
+use crate::{
+    check::{CheckResult, LocatedCheckResult, check_located, check_nb},
+    cli::ServerCommand,
+    files::{check_exclusions, normalize_path},
+    rules::Severity,
+    schema::{Cell, CodeCell, MarkdownCell, RawNotebook, SourceValue},
+    settings::Settings,
+    span::{self, Span},
+    strip::{strip_nb, strip_result, write_nb},
+};
+
+/// The kind of the code action that cleans the whole notebook.
+const FIX_ALL_KIND: &str = "source.fixAll.nbwipers";
+
+/// The lines of a document, to convert positions in it to LSP positions, which count UTF-16
+/// code units.
+struct LineIndex<'a> {
+    lines: Vec<&'a str>,
+}
+
+impl<'a> LineIndex<'a> {
+    fn new(text: &'a str) -> Self {
+        Self {
+            lines: text.split('\n').collect(),
+        }
+    }
+
+    fn utf16_len(text: &str) -> u32 {
+        u32::try_from(text.encode_utf16().count()).unwrap_or(u32::MAX)
+    }
+
+    fn position(&self, position: span::Position) -> Position {
+        let line = position.line.saturating_sub(1);
+        let prefix: String = self
+            .lines
+            .get(line)
+            .map(|text| {
+                text.chars()
+                    .take(position.column.saturating_sub(1))
+                    .collect()
+            })
+            .unwrap_or_default();
+        Position::new(
+            u32::try_from(line).unwrap_or(u32::MAX),
+            Self::utf16_len(&prefix),
+        )
+    }
+
+    fn range(&self, span: Option<Span>) -> Range {
+        span.map_or_else(Range::default, |span| {
+            Range::new(self.position(span.start), self.position(span.end))
+        })
+    }
+
+    /// The range of the first line, where findings about a whole cell are shown.
+    fn first_line(&self) -> Range {
+        Range::new(
+            Position::new(0, 0),
+            Position::new(
+                0,
+                self.lines.first().map_or(0, |line| Self::utf16_len(line)),
+            ),
+        )
+    }
+
+    /// The range of the whole document.
+    fn all(&self) -> Range {
+        let last = self.lines.len().saturating_sub(1);
+        Range::new(
+            Position::new(0, 0),
+            Position::new(
+                u32::try_from(last).unwrap_or(u32::MAX),
+                self.lines.last().map_or(0, |line| Self::utf16_len(line)),
+            ),
+        )
+    }
+}
+
+/// A finding with its severity and the range of the text it is about.
+type Finding = (CheckResult, Severity, Range);
+
+/// Check the text of a notebook, giving each finding with the range of the text it is about.
+fn check_text(
+    text: &str,
+    lines: &LineIndex,
+    settings: &Settings,
+) -> (Option<RawNotebook>, Vec<Finding>) {
+    match serde_json::from_str::<RawNotebook>(text) {
+        Ok(nb) => {
+            let findings = check_located(&nb, text, settings)
+                .into_iter()
+                .map(
+                    |LocatedCheckResult {
+                         result,
+                         severity,
+                         span,
+                     }| (result, severity, lines.range(span)),
+                )
+                .collect();
+            (Some(nb), findings)
+        }
+        Err(e) => {
+            let start = span::Position {
+                line: e.line().max(1),
+                column: e.column().max(1),
+            };
+            let span = Span { start, end: start };
+            let result = CheckResult::InvalidNotebook {
+                error: e.to_string(),
+            };
+            (
+                None,
+                vec![(result, Severity::Error, lines.range(Some(span)))],
+            )
+        }
+    }
+}
+
+fn diagnostic(result: &CheckResult, severity: Severity, range: Range) -> Diagnostic {
+    Diagnostic {
+        range,
+        severity: Some(match severity {
+            Severity::Warning => DiagnosticSeverity::WARNING,
+            Severity::Error => DiagnosticSeverity::ERROR,
+        }),
+        code: Some(NumberOrString::String(result.rule().code().to_owned())),
+        source: Some("nbwipers".to_owned()),
+        message: result.to_string(),
+        ..Diagnostic::default()
+    }
+}
+
+/// The title of the code action that resolves `result`, if cleaning can resolve it.
+fn fix_title(result: &CheckResult) -> Option<String> {
+    let title = match result {
+        CheckResult::StripMeta { extra_key } => format!("Remove {extra_key}"),
+        CheckResult::CellStripMeta {
+            cell_number,
+            extra_key,
+        } => format!("Remove {extra_key} from cell {cell_number}"),
+        CheckResult::DropCells { cell_number } => format!("Drop cell {cell_number}"),
+        CheckResult::ClearOutput { cell_number } => format!("Clear output of cell {cell_number}"),
+        CheckResult::ClearCount { cell_number } => {
+            format!("Clear execution count of cell {cell_number}")
+        }
+        CheckResult::ClearId { cell_number } => format!("Clean id of cell {cell_number}"),
+        CheckResult::ClearSource { cell_number } => format!("Clear source of cell {cell_number}"),
+        CheckResult::DowngradeNBFormat { .. } => "Downgrade nbformat_minor".to_owned(),
+        CheckResult::ReplaceKernelspec => "Replace kernelspec".to_owned(),
+        CheckResult::IOError { .. }
+        | CheckResult::InvalidNotebook { .. }
+        | CheckResult::DisallowedKernel { .. }
+        | CheckResult::WrongLanguage { .. }
+        | CheckResult::WrongLanguageVersion { .. } => return None,
+    };
+    Some(title)
+}
+
+fn overlaps(a: Range, b: Range) -> bool {
+    (a.start.line, a.start.character) <= (b.end.line, b.end.character)
+        && (b.start.line, b.start.character) <= (a.end.line, a.end.character)
+}
+
+/// The path of a `file` uri.
+fn uri_path(uri: &Uri) -> Option<PathBuf> {
+    if !uri
+        .scheme()
+        .is_some_and(|scheme| scheme.as_str().eq_ignore_ascii_case("file"))
+    {
+        return None;
+    }
+    let path = uri
+        .path()
+        .as_estr()
+        .decode()
+        .into_string_lossy()
+        .into_owned();
+    // `file:///C:/notebook.ipynb` has the path `/C:/notebook.ipynb`
+    #[cfg(windows)]
+    let path = match path.strip_prefix('/') {
+        Some(stripped) if stripped.get(1..2) == Some(":") => stripped.to_owned(),
+        _ => path,
+    };
+    Some(PathBuf::from(path))
+}
+
+/// The Jupyter metadata in the metadata of a notebook document or cell.
+///
+/// Editors such as VS Code keep the Jupyter metadata under a `metadata` key, next to other
+/// information such as the notebook format. Otherwise the whole object is taken as the Jupyter
+/// metadata.
+fn jupyter_metadata(metadata: Option<&LSPObject>) -> Value {
+    match metadata.and_then(|metadata| metadata.get("metadata")) {
+        Some(inner @ Value::Object(_)) => inner.clone(),
+        _ => Value::Object(metadata.cloned().unwrap_or_default()),
+    }
+}
+
+/// A notebook document, with the text of each of its cells.
+struct NotebookDocument {
+    metadata: Option<LSPObject>,
+    cells: Vec<NotebookCell>,
+    cell_texts: FxHashMap<Uri, String>,
+}
+
+impl NotebookDocument {
+    fn cell_text(&self, cell: &NotebookCell) -> &str {
+        self.cell_texts
+            .get(&cell.document)
+            .map_or("", String::as_str)
+    }
+
+    /// The notebook as it would be saved, except that editors do not send the outputs of cells.
+    fn to_raw(&self) -> RawNotebook {
+        let format = |key: &str, default: i64| {
+            self.metadata
+                .as_ref()
+                .and_then(|metadata| metadata.get(key))
+                .and_then(Value::as_i64)
+                .unwrap_or(default)
+        };
+        let cells = self
+            .cells
+            .iter()
+            .map(|cell| {
+                let metadata = jupyter_metadata(cell.metadata.as_ref());
+                let id = cell
+                    .metadata
+                    .as_ref()
+                    .and_then(|metadata| metadata.get("id"))
+                    .and_then(Value::as_str)
+                    .map(str::to_owned);
+                let source = SourceValue::String(self.cell_text(cell).to_owned());
+                match cell.kind {
+                    NotebookCellKind::Code => Cell::Code(CodeCell {
+                        execution_count: cell
+                            .execution_summary
+                            .as_ref()
+                            .map(|summary| i64::from(summary.execution_order)),
+                        id,
+                        metadata,
+                        outputs: vec![],
+                        source,
+                    }),
+                    NotebookCellKind::Markup => Cell::Markdown(MarkdownCell {
+                        attachments: None,
+                        id,
+                        metadata,
+                        source,
+                    }),
+                }
+            })
+            .collect();
+        RawNotebook {
+            cells,
+            metadata: jupyter_metadata(self.metadata.as_ref()),
+            nbformat: format("nbformat", 4),
+            nbformat_minor: format("nbformat_minor", 5),
+        }
+    }
+}
+
+struct Server {
+    connection: Connection,
+    settings: Settings,
+    /// Notebooks opened as the text of an `.ipynb` file.
+    documents: FxHashMap<Uri, String>,
+    notebooks: FxHashMap<Uri, NotebookDocument>,
+}
+
+impl Server {
+    fn excluded(&self, uri: &Uri) -> bool {
+        uri_path(uri).is_some_and(|path| check_exclusions(&normalize_path(path), &self.settings))
+    }
+
+    fn publish(&self, uri: Uri, diagnostics: Vec<Diagnostic>) -> Result<(), Error> {
+        let params = PublishDiagnosticsParams {
+            uri,
+            diagnostics,
+            version: None,
+        };
+        let notification =
+            Notification::new(notification::PublishDiagnostics::METHOD.to_owned(), params);
+        self.connection.sender.send(notification.into())?;
+        Ok(())
+    }
+
+    fn publish_document(&self, uri: &Uri) -> Result<(), Error> {
+        let diagnostics = match self.documents.get(uri) {
+            Some(text) if !self.excluded(uri) => {
+                check_text(text, &LineIndex::new(text), &self.settings)
+                    .1
+                    .iter()
+                    .map(|(result, severity, range)| diagnostic(result, *severity, *range))
+                    .collect()
+            }
+            _ => vec![],
+        };
+        self.publish(uri.clone(), diagnostics)
+    }
+
+    /// Publish the findings in a notebook document on its cells. Findings about the notebook as
+    /// a whole are shown on the first cell.
+    fn publish_notebook(&self, uri: &Uri) -> Result<(), Error> {
+        let Some(notebook) = self.notebooks.get(uri) else {
+            return Ok(());
+        };
+        let mut diagnostics = vec![vec![]; notebook.cells.len()];
+        if !self.excluded(uri) {
+            for result in check_nb(&notebook.to_raw(), &self.settings) {
+                let Some(severity) = self.settings.severity(result.rule()) else {
+                    continue;
+                };
+                let cell_number = result.cell_number().unwrap_or(0);
+                if let (Some(cell), Some(cell_diagnostics)) = (
+                    notebook.cells.get(cell_number),
+                    diagnostics.get_mut(cell_number),
+                ) {
+                    let range = LineIndex::new(notebook.cell_text(cell)).first_line();
+                    cell_diagnostics.push(diagnostic(&result, severity, range));
+                }
+            }
+        }
+        for (cell, cell_diagnostics) in notebook.cells.iter().zip(diagnostics) {
+            self.publish(cell.document.clone(), cell_diagnostics)?;
+        }
+        Ok(())
+    }
+
+    /// A code action for each finding in `params.range` that cleaning can resolve, and one that
+    /// cleans the whole notebook.
+    fn code_actions(&self, params: &CodeActionParams) -> Vec<CodeActionOrCommand> {
+        let uri = &params.text_document.uri;
+        let Some(text) = self.documents.get(uri) else {
+            return vec![];
+        };
+        if self.excluded(uri) {
+            return vec![];
+        }
+        let lines = LineIndex::new(text);
+        let (Some(nb), findings) = check_text(text, &lines, &self.settings) else {
+            return vec![];
+        };
+        let edit = |nb: &RawNotebook| {
+            let mut new_text = vec![];
+            write_nb(&mut new_text, nb).ok()?;
+            let text_edit = TextEdit::new(lines.all(), String::from_utf8(new_text).ok()?);
+            Some(WorkspaceEdit {
+                changes: Some(HashMap::from([(uri.clone(), vec![text_edit])])),
+                ..WorkspaceEdit::default()
+            })
+        };
+
+        let mut actions = vec![];
+        for (result, severity, range) in &findings {
+            if !overlaps(*range, params.range) {
+                continue;
+            }
+            let Some(title) = fix_title(result) else {
+                continue;
+            };
+            let mut fixed = nb.clone();
+            if !strip_result(&mut fixed, result, &self.settings) {
+                continue;
+            }
+            actions.push(CodeActionOrCommand::CodeAction(CodeAction {
+                title,
+                kind: Some(CodeActionKind::QUICKFIX),
+                diagnostics: Some(vec![diagnostic(result, *severity, *range)]),
+                edit: edit(&fixed),
+                ..CodeAction::default()
+            }));
+        }
+        if !findings.is_empty()
+            && let (stripped, true) = strip_nb(nb, &self.settings)
+        {
+            actions.push(CodeActionOrCommand::CodeAction(CodeAction {
+                title: "Clean notebook".to_owned(),
+                kind: Some(CodeActionKind::new(FIX_ALL_KIND)),
+                edit: edit(&stripped),
+                ..CodeAction::default()
+            }));
+        }
+        actions
+    }
+
+    fn change_notebook(&mut self, params: DidChangeNotebookDocumentParams) -> Vec<Uri> {
+        let mut closed = vec![];
+        let Some(notebook) = self.notebooks.get_mut(&params.notebook_document.uri) else {
+            return closed;
+        };
+        if let Some(metadata) = params.change.metadata {
+            notebook.metadata = Some(metadata);
+        }
+        let Some(cells) = params.change.cells else {
+            return closed;
+        };
+        if let Some(structure) = cells.structure {
+            let start = (structure.array.start as usize).min(notebook.cells.len());
+            let end = (start + structure.array.delete_count as usize).min(notebook.cells.len());
+            notebook
+                .cells
+                .splice(start..end, structure.array.cells.unwrap_or_default());
+            for item in structure.did_open.unwrap_or_default() {
+                notebook.cell_texts.insert(item.uri, item.text);
+            }
+            for identifier in structure.did_close.unwrap_or_default() {
+                notebook.cell_texts.remove(&identifier.uri);
+                closed.push(identifier.uri);
+            }
+        }
+        for cell in cells.data.unwrap_or_default() {
+            if let Some(existing) = notebook
+                .cells
+                .iter_mut()
+                .find(|existing| existing.document == cell.document)
+            {
+                *existing = cell;
+            }
+        }
+        for content in cells.text_content.unwrap_or_default() {
+            // documents are synced in full, so the last change holds the whole text
+            if let Some(text) = notebook.cell_texts.get_mut(&content.document.uri)
+                && let Some(change) = content.changes.into_iter().last()
+            {
+                *text = change.text;
+            }
+        }
+        closed
+    }
+
+    fn handle_request(&self, request: Request) -> Result<(), Error> {
+        let response = match request.method.as_str() {
+            request::CodeActionRequest::METHOD => {
+                match serde_json::from_value::<CodeActionParams>(request.params) {
+                    Ok(params) => Response::new_ok(request.id, self.code_actions(&params)),
+                    Err(e) => Response::new_err(
+                        request.id,
+                        ErrorCode::InvalidParams as i32,
+                        e.to_string(),
+                    ),
+                }
+            }
+            method => Response::new_err(
+                request.id,
+                ErrorCode::MethodNotFound as i32,
+                format!("Unsupported request {method}"),
+            ),
+        };
+        self.connection.sender.send(response.into())?;
+        Ok(())
+    }
+
+    fn handle_notification(&mut self, notification: Notification) -> Result<(), Error> {
+        match notification.method.as_str() {
+            notification::DidOpenTextDocument::METHOD => {
+                let params: DidOpenTextDocumentParams =
+                    serde_json::from_value(notification.params)?;
+                let uri = params.text_document.uri;
+                self.documents
+                    .insert(uri.clone(), params.text_document.text);
+                self.publish_document(&uri)?;
+            }
+            notification::DidChangeTextDocument::METHOD => {
+                let params: DidChangeTextDocumentParams =
+                    serde_json::from_value(notification.params)?;
+                let uri = params.text_document.uri;
+                if let Some(text) = self.documents.get_mut(&uri)
+                    && let Some(change) = params.content_changes.into_iter().last()
+                {
+                    *text = change.text;
+                }
+                self.publish_document(&uri)?;
+            }
+            notification::DidCloseTextDocument::METHOD => {
+                let params: DidCloseTextDocumentParams =
+                    serde_json::from_value(notification.params)?;
+                self.documents.remove(&params.text_document.uri);
+                self.publish(params.text_document.uri, vec![])?;
+            }
+            notification::DidOpenNotebookDocument::METHOD => {
+                let params: DidOpenNotebookDocumentParams =
+                    serde_json::from_value(notification.params)?;
+                let uri = params.notebook_document.uri;
+                let notebook = NotebookDocument {
+                    metadata: params.notebook_document.metadata,
+                    cells: params.notebook_document.cells,
+                    cell_texts: params
+                        .cell_text_documents
+                        .into_iter()
+                        .map(|item| (item.uri, item.text))
+                        .collect(),
+                };
+                self.notebooks.insert(uri.clone(), notebook);
+                self.publish_notebook(&uri)?;
+            }
+            notification::DidChangeNotebookDocument::METHOD => {
+                let params: DidChangeNotebookDocumentParams =
+                    serde_json::from_value(notification.params)?;
+                let uri = params.notebook_document.uri.clone();
+                for closed in self.change_notebook(params) {
+                    self.publish(closed, vec![])?;
+                }
+                self.publish_notebook(&uri)?;
+            }
+            notification::DidCloseNotebookDocument::METHOD => {
+                let params: DidCloseNotebookDocumentParams =
+                    serde_json::from_value(notification.params)?;
+                self.notebooks.remove(&params.notebook_document.uri);
+                for cell in params.cell_text_documents {
+                    self.publish(cell.uri, vec![])?;
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn run(&mut self) -> Result<(), Error> {
+        while let Ok(message) = self.connection.receiver.recv() {
+            match message {
+                Message::Request(request) => {
+                    if self.connection.handle_shutdown(&request)? {
+                        return Ok(());
+                    }
+                    self.handle_request(request)?;
+                }
+                Message::Notification(notification) => {
+                    let method = notification.method.clone();
+                    if let Err(e) = self.handle_notification(notification) {
+                        eprintln!("Could not handle {method}: {e}");
+                    }
+                }
+                Message::Response(_) => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+fn capabilities() -> ServerCapabilities {
+    ServerCapabilities {
+        text_document_sync: Some(TextDocumentSyncCapability::Kind(TextDocumentSyncKind::FULL)),
+        notebook_document_sync: Some(OneOf::Left(NotebookDocumentSyncOptions {
+            notebook_selector: vec![NotebookSelector::ByNotebook {
+                notebook: Notebook::String("jupyter-notebook".to_owned()),
+                cells: None,
+            }],
+            save: None,
+        })),
+        code_action_provider: Some(CodeActionProviderCapability::Options(CodeActionOptions {
+            code_action_kinds: Some(vec![
+                CodeActionKind::QUICKFIX,
+                CodeActionKind::new(FIX_ALL_KIND),
+            ]),
+            ..CodeActionOptions::default()
+        })),
+        ..ServerCapabilities::default()
+    }
+}
+
+/// Run a language server over stdio that publishes the findings of `check` as diagnostics.
+///
+/// Notebooks opened as the text of `.ipynb` files get a diagnostic at the line of each finding,
+/// with code actions that clean it. Notebooks opened as notebook documents get diagnostics on
+/// their cells, but editors do not send the outputs of cells, so these never have findings about
+/// outputs.
+pub fn server(cmd: ServerCommand) -> Result<(), Error> {
+    let (args, overrides) = cmd.common.partition();
+    let settings = Settings::construct(args.config.as_deref(), args.isolated, &overrides)?;
+
+    let (connection, io_threads) = Connection::stdio();
+    let (id, _params) = connection.initialize_start()?;
+    connection.initialize_finish(
+        id,
+        json!({
+            "capabilities": capabilities(),
+            "serverInfo": {"name": "nbwipers", "version": env!("CARGO_PKG_VERSION")},
+        }),
+    )?;
+    Server {
+        connection,
+        settings,
+        documents: FxHashMap::default(),
+        notebooks: FxHashMap::default(),
+    }
+    .run()?;
+    io_threads.join()?;
+    Ok(())
+}
@@ -1,28 +1,52 @@
 use crate::cli::ConfigOverrides;
-use crate::config::{Configuration, IdAction, resolve};
+use crate::config::{Configuration, IdAction, SmudgeDefault};
 use crate::extra_keys::ExtraKey;
+use crate::files::normalize_path;
+use crate::rules::{Rule, RuleSelector, Severity};
 use globset::GlobSet;
 use rustc_hash::FxHashSet;
+use semver::VersionReq;
 use serde::Serialize;
+use serde_json::Value;
 use std::path::Path;
 
 #[allow(clippy::struct_excessive_bools)]
 #[derive(Debug, Clone, Serialize)]
 pub struct Settings {
+    pub profile: Option<String>,
     pub extra_keys: FxHashSet<ExtraKey>,
     pub drop_tagged_cells: FxHashSet<String>,
+    pub keep_output_tags: FxHashSet<String>,
+    pub drop_output_tags: FxHashSet<String>,
+    pub keep_count_tags: FxHashSet<String>,
+    pub clear_source_tags: FxHashSet<String>,
     pub drop_empty_cells: bool,
     pub drop_output: bool,
     pub drop_count: bool,
     pub id_action: IdAction,
     pub strip_init_cell: bool,
     pub strip_kernel_info: bool,
+    pub cache_outputs: bool,
+    pub record_on_clean: bool,
     pub exclude: Vec<String>,
     #[serde(skip_serializing)]
     pub exclude_: GlobSet,
     pub extend_exclude: Vec<String>,
     #[serde(skip_serializing)]
     pub extend_exclude_: GlobSet,
+    pub allowed_kernels: Option<Vec<String>>,
+    pub required_language: Option<String>,
+    pub required_language_version: Option<String>,
+    #[serde(skip_serializing)]
+    pub required_language_version_: Option<VersionReq>,
+    pub canonical_kernelspec: Option<Value>,
+    pub record_keys: Vec<ExtraKey>,
+    pub select: Vec<RuleSelector>,
+    pub ignore: Vec<RuleSelector>,
+    pub warn: Vec<RuleSelector>,
+    pub smudge_defaults: Vec<SmudgeDefault>,
+    #[serde(skip_serializing)]
+    pub smudge_defaults_: Vec<GlobSet>,
 }
 
 impl Settings {
@@ -31,14 +55,34 @@ impl Settings {
         isolated: bool,
         overrides: &ConfigOverrides,
     ) -> Result<Self, anyhow::Error> {
-        let mut config = if isolated {
-            Configuration::default()
+        Configuration::load(config_file, isolated, overrides)?.into_settings()
+    }
+
+    /// The severity of the findings of `rule`, or `None` if `check` should not report them.
+    ///
+    /// Rules in `warn` are reported as warnings, in addition to the rules in `select`, and
+    /// `ignore` takes precedence over both.
+    pub fn severity(&self, rule: Rule) -> Option<Severity> {
+        let matches = |selectors: &[RuleSelector]| selectors.iter().any(|s| s.matches(rule));
+        if rule.is_read_error() {
+            Some(Severity::Error)
+        } else if matches(&self.ignore) {
+            None
+        } else if matches(&self.warn) {
+            Some(Severity::Warning)
+        } else if matches(&self.select) {
+            Some(Severity::Error)
         } else {
-            let (config_sec, config_path) = resolve(config_file)?;
-            config_sec.make_configuration(config_path.as_deref())
-        };
-        config = overrides.override_config(config);
+            None
+        }
+    }
 
-        config.into_settings()
+    /// The first entry of `smudge.defaults` whose path matches `path`.
+    pub fn smudge_default_for<P: AsRef<Path>>(&self, path: P) -> Option<&SmudgeDefault> {
+        let path = normalize_path(path);
+        self.smudge_defaults
+            .iter()
+            .zip(&self.smudge_defaults_)
+            .find_map(|(default, globset)| globset.is_match(&path).then_some(default))
     }
 }
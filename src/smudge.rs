@@ -1,34 +1,115 @@
 use std::io::{Read, stdin};
 use std::io::{Write, stdout};
+use std::path::Path;
+use std::str::FromStr;
 
 use anyhow::bail;
+use indexmap::IndexMap;
 use serde_json::{Value, json};
 
+use crate::cli::ConfigOverrides;
+use crate::config::SmudgeDefault;
+use crate::extra_keys::ExtraKey;
 use crate::files::get_cwd;
-use crate::record::{KernelSpecInfo, get_kernelspec_file, read_kernelspec_file};
+use crate::output_cache::OutputCache;
+use crate::record::{
+    KernelSpecInfo, find_moved_record, get_kernelspec_file, get_work_tree, read_kernelspec_file,
+    update_kernelspec_file,
+};
 use crate::schema::RawNotebook;
+use crate::settings::Settings;
 use crate::strip::write_nb;
+use crate::utils::insert_value_child;
 
 pub fn smudge(path: String) -> Result<(), anyhow::Error> {
     let mut in_nb_bytes = Vec::new();
     stdin().lock().read_to_end(&mut in_nb_bytes)?;
     // let lock = std_in.lock();
     // serde_json::from_reader(lock)?
-    let kernelspec_info =
-        read_kernelspec_file(get_kernelspec_file(get_cwd())?)?.unwrap_or_default();
-    match kernelspec_info.get(&path) {
-        Some(kernel_spec) => {
-            let out_nb = maybe_replace_kernelspec(&in_nb_bytes, kernel_spec)?;
-            write_nb(stdout(), &out_nb)?;
-        }
-        None => {
-            stdout().write_all(&in_nb_bytes)?;
+    // a broken store should not stop checkouts, so treat it as empty
+    let kernelspec_file = get_kernelspec_file(get_cwd())?;
+    let kernelspec_info = match read_kernelspec_file(&kernelspec_file) {
+        Ok(kernelspec_info) => kernelspec_info.unwrap_or_default(),
+        Err(e) => {
+            eprintln!("Could not read kernelspec file: {e}");
+            IndexMap::default()
         }
+    };
+    let moved_info = if kernelspec_info.contains_key(&path) {
+        None
+    } else {
+        take_moved_record(&kernelspec_file, &kernelspec_info, &path, &in_nb_bytes)
+    };
+    let kernel_spec = kernelspec_info.get(&path).or(moved_info.as_ref());
+    // a broken configuration should not stop checkouts, so it is treated as empty
+    let settings = Settings::construct(None, false, &ConfigOverrides::default()).ok();
+    let mut out_nb = match kernel_spec {
+        Some(kernel_spec) => Some(maybe_replace_kernelspec(&in_nb_bytes, kernel_spec)?),
+        None => settings
+            .as_ref()
+            .and_then(|settings| settings.smudge_default_for(&path))
+            .and_then(|default| apply_smudge_default(&in_nb_bytes, default)),
+    };
+    if settings.is_some_and(|settings| settings.cache_outputs) {
+        let nb = out_nb.or_else(|| serde_json::from_slice::<RawNotebook>(&in_nb_bytes).ok());
+        out_nb = nb.map(|mut nb| {
+            if let Ok(cache) = OutputCache::for_repo(get_cwd()) {
+                cache.restore(&mut nb);
+            }
+            nb
+        });
+    }
+    match out_nb {
+        Some(out_nb) => write_nb(stdout(), &out_nb)?,
+        None => stdout().write_all(&in_nb_bytes)?,
     }
 
     Ok(())
 }
 
+/// Find the record of a notebook that was renamed or moved to `path` since it was recorded, and
+/// move the record to `path` so it is found directly next time.
+fn take_moved_record(
+    kernelspec_file: &Path,
+    kernelspec_info: &IndexMap<String, KernelSpecInfo>,
+    path: &str,
+    nb_bytes: &[u8],
+) -> Option<KernelSpecInfo> {
+    if kernelspec_info.is_empty() {
+        return None;
+    }
+    let nb = serde_json::from_slice::<RawNotebook>(nb_bytes).ok()?;
+    let old_path = find_moved_record(kernelspec_info, &nb, &get_work_tree(get_cwd()).ok()?)?;
+    let info = kernelspec_info.get(old_path)?.clone();
+    let moved = update_kernelspec_file(kernelspec_file, |records| {
+        if let Some(info) = records.shift_remove(old_path) {
+            records.insert(path.to_string(), info);
+        }
+    });
+    if let Err(e) = moved {
+        eprintln!("Could not move the record of {old_path} to {path}: {e}");
+    }
+    Some(info)
+}
+
+/// Add the kernel metadata of a committed `smudge.defaults` entry, leaving any value already in
+/// the notebook alone. Input that is not a notebook is left to pass through unchanged.
+fn apply_smudge_default(nb_in: &[u8], default: &SmudgeDefault) -> Option<RawNotebook> {
+    let mut nb = serde_json::from_slice::<RawNotebook>(nb_in).ok()?;
+    if !nb.metadata.is_null() && !nb.metadata.is_object() {
+        return None;
+    }
+    if let Some(kernelspec) = &default.kernelspec {
+        insert_value_child(&mut nb.metadata, &["kernelspec"], kernelspec.clone());
+    }
+    if let Some(Value::Object(language_info)) = &default.language_info {
+        for (key, value) in language_info {
+            insert_value_child(&mut nb.metadata, &["language_info", key], value.clone());
+        }
+    }
+    Some(nb)
+}
+
 fn maybe_replace_kernelspec(
     nb_in: &[u8],
     kernelspec_info: &KernelSpecInfo,
@@ -77,10 +158,46 @@ fn maybe_replace_kernelspec(
             _ => bail!("Unexpected metadata type"),
         }
     };
+    restore_recorded_keys(&mut nb, kernelspec_info)?;
 
     Ok(nb)
 }
 
+/// Restore values recorded for `record-keys`, leaving any value already in the notebook alone.
+fn restore_recorded_keys(
+    nb: &mut RawNotebook,
+    kernelspec_info: &KernelSpecInfo,
+) -> Result<(), anyhow::Error> {
+    for (key, value) in &kernelspec_info.metadata {
+        let Ok(ExtraKey::Metadata(meta_key)) = ExtraKey::from_str(key) else {
+            continue;
+        };
+        if !nb.metadata.is_null() && !nb.metadata.is_object() {
+            bail!("Unexpected metadata type");
+        }
+        insert_value_child(&mut nb.metadata, &meta_key.parts, value.clone());
+    }
+    if kernelspec_info.cells.is_empty() {
+        return Ok(());
+    }
+    for cell in &mut nb.cells {
+        let Some(values) = cell
+            .get_id()
+            .as_ref()
+            .and_then(|id| kernelspec_info.cells.get(id))
+        else {
+            continue;
+        };
+        for (key, value) in values {
+            let Ok(ExtraKey::CellMeta(cell_key)) = ExtraKey::from_str(key) else {
+                continue;
+            };
+            insert_value_child(cell.get_metadata_mut(), &cell_key.parts, value.clone());
+        }
+    }
+    Ok(())
+}
+
 #[cfg(test)]
 mod test {
     use serde_json::{Value, json};
@@ -95,6 +212,7 @@ mod test {
                 "display_name": "Python 3"
             }),
             python_version: Some("3.12.4".to_string()),
+            ..Default::default()
         }
     }
     fn notebook_with_metadata_bytes(meta: serde_json::Value) -> Vec<u8> {
@@ -135,6 +253,7 @@ mod test {
         let kernelspec_info = KernelSpecInfo {
             kernelspec: Value::Null,
             python_version: Some("3.12.4".to_string()),
+            ..Default::default()
         };
         let nb_bytes = notebook_with_metadata_bytes(Value::Null);
 
@@ -233,4 +352,23 @@ mod test {
             maybe_replace_kernelspec(&nb_bytes_malformed_language_info, &kernelspec_info).is_err()
         );
     }
+
+    #[test]
+    fn test_restore_recorded_keys() {
+        let mut kernelspec_info = KernelSpecInfo::default();
+        kernelspec_info
+            .metadata
+            .insert("metadata.widgets".to_string(), json!({"state": {}}));
+        kernelspec_info
+            .metadata
+            .insert("metadata.signature".to_string(), json!("recorded"));
+        let nb_bytes = notebook_with_metadata_bytes(json!({"signature": "original"}));
+
+        let out_nb = maybe_replace_kernelspec(&nb_bytes, &kernelspec_info).unwrap();
+
+        assert_eq!(
+            out_nb.metadata,
+            json!({"signature": "original", "widgets": {"state": {}}})
+        );
+    }
 }
@@ -0,0 +1,236 @@
+use std::ops::Range;
+
+use rustc_hash::FxHashMap;
+use serde::Serialize;
+
+/// A position in a text file. Lines and columns start at 1, and columns count characters.
+#[derive(Clone, Copy, Debug, Serialize, PartialEq, Eq)]
+pub struct Position {
+    pub line: usize,
+    pub column: usize,
+}
+
+/// The range of a JSON value in the text of a notebook, from `start` up to but not including
+/// `end`. For an object member, the range includes the key.
+#[derive(Clone, Copy, Debug, Serialize, PartialEq, Eq)]
+pub struct Span {
+    pub start: Position,
+    pub end: Position,
+}
+
+/// The byte ranges of the values in the text of a notebook, looked up by their path.
+///
+/// Every object member is indexed, but of array elements only the cells are, so that long
+/// sources and outputs do not fill the index.
+pub struct SpanIndex<'a> {
+    text: &'a str,
+    line_starts: Vec<usize>,
+    ranges: FxHashMap<Vec<String>, Range<usize>>,
+}
+
+struct Scanner<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+    path: Vec<String>,
+    ranges: FxHashMap<Vec<String>, Range<usize>>,
+}
+
+impl Scanner<'_> {
+    fn skip_whitespace(&mut self) {
+        while self
+            .bytes
+            .get(self.pos)
+            .is_some_and(u8::is_ascii_whitespace)
+        {
+            self.pos += 1;
+        }
+    }
+
+    fn expect(&mut self, byte: u8) -> Option<()> {
+        self.skip_whitespace();
+        (self.bytes.get(self.pos) == Some(&byte)).then(|| self.pos += 1)
+    }
+
+    /// Scan a string, returning its raw text including the quotes.
+    fn string(&mut self) -> Option<&[u8]> {
+        let start = self.pos;
+        self.pos += 1;
+        loop {
+            match self.bytes.get(self.pos)? {
+                b'\\' => self.pos += 2,
+                b'"' => {
+                    self.pos += 1;
+                    return Some(&self.bytes[start..self.pos]);
+                }
+                _ => self.pos += 1,
+            }
+        }
+    }
+
+    fn value(&mut self) -> Option<()> {
+        self.skip_whitespace();
+        match self.bytes.get(self.pos)? {
+            b'{' => self.object(),
+            b'[' => self.array(),
+            b'"' => self.string().map(|_| ()),
+            _ => {
+                while self
+                    .bytes
+                    .get(self.pos)
+                    .is_some_and(|b| !b.is_ascii_whitespace() && !b",]}".contains(b))
+                {
+                    self.pos += 1;
+                }
+                Some(())
+            }
+        }
+    }
+
+    fn object(&mut self) -> Option<()> {
+        self.pos += 1;
+        self.skip_whitespace();
+        if self.bytes.get(self.pos) == Some(&b'}') {
+            self.pos += 1;
+            return Some(());
+        }
+        loop {
+            self.skip_whitespace();
+            let start = self.pos;
+            let key: String = serde_json::from_slice(self.string()?).ok()?;
+            self.expect(b':')?;
+            self.path.push(key);
+            self.value()?;
+            self.ranges.insert(self.path.clone(), start..self.pos);
+            self.path.pop();
+            self.skip_whitespace();
+            match self.bytes.get(self.pos)? {
+                b',' => self.pos += 1,
+                b'}' => {
+                    self.pos += 1;
+                    return Some(());
+                }
+                _ => return None,
+            }
+        }
+    }
+
+    fn array(&mut self) -> Option<()> {
+        let index_elements = self.path == ["cells"];
+        self.pos += 1;
+        self.skip_whitespace();
+        if self.bytes.get(self.pos) == Some(&b']') {
+            self.pos += 1;
+            return Some(());
+        }
+        for index in 0.. {
+            self.skip_whitespace();
+            let start = self.pos;
+            self.path.push(index.to_string());
+            self.value()?;
+            if index_elements {
+                self.ranges.insert(self.path.clone(), start..self.pos);
+            }
+            self.path.pop();
+            self.skip_whitespace();
+            match self.bytes.get(self.pos)? {
+                b',' => self.pos += 1,
+                b']' => {
+                    self.pos += 1;
+                    return Some(());
+                }
+                _ => return None,
+            }
+        }
+        None
+    }
+}
+
+impl<'a> SpanIndex<'a> {
+    /// Index the JSON `text`. If the text is not valid JSON, the values before the problem are
+    /// still indexed.
+    pub fn new(text: &'a str) -> Self {
+        let mut scanner = Scanner {
+            bytes: text.as_bytes(),
+            pos: 0,
+            path: vec![],
+            ranges: FxHashMap::default(),
+        };
+        scanner.value();
+        let line_starts = std::iter::once(0)
+            .chain(text.match_indices('\n').map(|(i, _)| i + 1))
+            .collect();
+        Self {
+            text,
+            line_starts,
+            ranges: scanner.ranges,
+        }
+    }
+
+    fn position(&self, offset: usize) -> Position {
+        let line = self.line_starts.partition_point(|&start| start <= offset);
+        let line_start = self.line_starts[line - 1];
+        let column = self.text[line_start..offset].chars().count() + 1;
+        Position { line, column }
+    }
+
+    /// The span of the value at `path`.
+    pub fn span<S: AsRef<str>>(&self, path: &[S]) -> Option<Span> {
+        let path: Vec<String> = path.iter().map(|s| s.as_ref().to_owned()).collect();
+        let range = self.ranges.get(&path)?;
+        Some(Span {
+            start: self.position(range.start),
+            end: self.position(range.end),
+        })
+    }
+
+    /// The span of the value at `path`, or of its closest ancestor that is indexed.
+    pub fn nearest_span<S: AsRef<str>>(&self, path: &[S]) -> Option<Span> {
+        (1..=path.len())
+            .rev()
+            .find_map(|len| self.span(&path[..len]))
+    }
+}
+
+#[allow(clippy::unwrap_used)]
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_span_index() {
+        let text = "{\n \"cells\": [\n  {\"execution_count\": 1, \"outputs\": [\"a\", \"b\\\"]\"]}\n ],\n \"metadata\": {\"kernelspec\": {\"name\": \"python3\"}}\n}\n";
+        let index = SpanIndex::new(text);
+        assert_eq!(
+            index.span(&["cells", "0"]),
+            Some(Span {
+                start: Position { line: 3, column: 3 },
+                end: Position {
+                    line: 3,
+                    column: 51
+                },
+            })
+        );
+        assert_eq!(
+            index
+                .span(&["cells", "0", "execution_count"])
+                .unwrap()
+                .start,
+            Position { line: 3, column: 4 }
+        );
+        assert_eq!(index.span(&["cells", "0", "outputs", "0"]), None);
+        assert_eq!(
+            index
+                .span(&["metadata", "kernelspec", "name"])
+                .unwrap()
+                .start,
+            Position {
+                line: 5,
+                column: 30
+            }
+        );
+        assert_eq!(
+            index.nearest_span(&["metadata", "kernelspec", "missing"]),
+            index.span(&["metadata", "kernelspec"])
+        );
+    }
+}
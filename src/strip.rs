@@ -3,6 +3,7 @@ use std::{
     fs,
     io::{BufWriter, Write},
     path::Path,
+    str::FromStr,
 };
 
 use serde::Serialize;
@@ -10,31 +11,49 @@ use thiserror::Error;
 
 /// Maximum nbformat_minor version for which cell ids are optional.
 use crate::{
+    check::CheckResult,
     config::IdAction,
-    extra_keys::partition_extra_keys,
-    files::{NBReadError, NBWriteError, check_exclusions, normalize_path, read_nb, read_nb_stdin},
-    schema::{ID_OPTIONAL_MAX_VERSION, RawNotebook},
+    extra_keys::{ExtraKey, partition_extra_keys},
+    files::{
+        NBReadError, NBWriteError, check_exclusions, get_cwd, normalize_path, read_nb,
+        read_nb_stdin,
+    },
+    output_cache::OutputCache,
+    record::{extract_record, record_kernel_info},
+    rules::Rule,
+    schema::{Cell, ID_OPTIONAL_MAX_VERSION, RawNotebook},
     settings::Settings,
-    utils::{get_value_child, pop_cell_key, pop_meta_key},
+    suppress::Suppressions,
+    utils::{pop_cell_key, pop_meta_key},
 };
-use serde_json::Value;
 
 pub fn strip_nb(mut nb: RawNotebook, settings: &Settings) -> (RawNotebook, bool) {
     let (cell_keys, meta_keys) = partition_extra_keys(&settings.extra_keys);
-    let nb_keep_output = get_value_child(&nb.metadata, &["keep_output"])
-        .and_then(Value::as_bool)
-        .unwrap_or(false);
-    let drop_output = settings.drop_output && !nb_keep_output;
+    let suppressions = Suppressions::new(&nb);
 
     let mut stripped = false;
-    for meta_key in meta_keys {
-        stripped |= pop_meta_key(&mut nb, meta_key).is_some();
+    if !suppressions.notebook(Rule::NotebookMetadata) {
+        for meta_key in meta_keys {
+            stripped |= pop_meta_key(&mut nb, meta_key).is_some();
+        }
+    }
+    if let Some(canonical) = &settings.canonical_kernelspec
+        && !suppressions.notebook(Rule::NonCanonicalKernelspec)
+        && nb.metadata.get("kernelspec") != Some(canonical)
+        && let Some(metadata) = nb.metadata.as_object_mut()
+    {
+        metadata.insert("kernelspec".to_owned(), canonical.clone());
+        stripped = true;
     }
 
     let drop_cells: Vec<_> = nb
         .cells
         .iter()
-        .map(|c| c.should_drop(settings.drop_empty_cells, &settings.drop_tagged_cells))
+        .map(|c| {
+            let clears_source =
+                c.should_clear_source(settings) && !suppressions.cell(c, Rule::TaggedSource);
+            c.should_drop(settings, clears_source) && !suppressions.cell(c, Rule::DroppedCell)
+        })
         .collect();
     if drop_cells.iter().any(|b| *b) {
         stripped = true;
@@ -47,23 +66,41 @@ pub fn strip_nb(mut nb: RawNotebook, settings: &Settings) -> (RawNotebook, bool)
         nb.cells = retained_cells;
     }
     let mut downgrade_nbversion_minor = false;
+    let mut ids_kept = false;
 
     for (i, cell) in nb.cells.iter_mut().enumerate() {
+        let [keep_output, keep_count, keep_source, keep_id, keep_metadata] = [
+            Rule::OutputPresent,
+            Rule::ExecutionCount,
+            Rule::TaggedSource,
+            Rule::CellId,
+            Rule::CellMetadata,
+        ]
+        .map(|rule| suppressions.cell(cell, rule));
+        if !keep_source && cell.should_clear_source(settings) {
+            stripped = true;
+            cell.clear_source();
+        }
         if let Some(codecell) = cell.as_codecell_mut() {
-            if codecell.should_clear_output(drop_output, settings.strip_init_cell)
+            if !keep_output
+                && codecell.should_clear_output(settings)
                 && !codecell.is_clear_outputs()
             {
                 stripped = true;
 
                 codecell.clear_outputs();
             }
-            if settings.drop_count && !codecell.is_clear_exec_count() {
+            if !keep_count
+                && codecell.should_clear_count(settings)
+                && !codecell.is_clear_exec_count()
+            {
                 stripped = true;
 
                 codecell.clear_counts();
             }
         }
         match settings.id_action {
+            _ if keep_id => ids_kept |= cell.get_id().is_some(),
             IdAction::Sequential => {
                 if !cell.is_clear_id(i) {
                     stripped = true;
@@ -80,16 +117,111 @@ pub fn strip_nb(mut nb: RawNotebook, settings: &Settings) -> (RawNotebook, bool)
             IdAction::Keep => {}
         }
 
-        for cell_key in &cell_keys {
-            stripped |= pop_cell_key(cell, cell_key).is_some();
+        if !keep_metadata {
+            for cell_key in &cell_keys {
+                stripped |= pop_cell_key(cell, cell_key).is_some();
+            }
         }
     }
-    if downgrade_nbversion_minor && nb.nbformat_minor > ID_OPTIONAL_MAX_VERSION {
+    // cells whose ids are kept need a format that has cell ids
+    if downgrade_nbversion_minor
+        && !ids_kept
+        && !suppressions.notebook(Rule::NbformatDowngrade)
+        && nb.nbformat_minor > ID_OPTIONAL_MAX_VERSION
+    {
         nb.nbformat_minor = ID_OPTIONAL_MAX_VERSION;
         stripped = true;
     }
     (nb, stripped)
 }
+/// Make the single change to `nb` that would resolve `result`, as `strip_nb` would make it.
+///
+/// Returns whether the notebook was changed. Findings such as a disallowed kernel cannot be
+/// resolved by cleaning, so they leave the notebook unchanged.
+pub fn strip_result(nb: &mut RawNotebook, result: &CheckResult, settings: &Settings) -> bool {
+    match result {
+        CheckResult::StripMeta { extra_key } => ExtraKey::from_str(extra_key)
+            .is_ok_and(|extra_key| pop_meta_key(nb, &extra_key).is_some()),
+        CheckResult::CellStripMeta {
+            cell_number,
+            extra_key,
+        } => match (
+            nb.cells.get_mut(*cell_number),
+            ExtraKey::from_str(extra_key),
+        ) {
+            (Some(cell), Ok(extra_key)) => pop_cell_key(cell, &extra_key).is_some(),
+            _ => false,
+        },
+        CheckResult::DropCells { cell_number } => {
+            let in_range = *cell_number < nb.cells.len();
+            if in_range {
+                nb.cells.remove(*cell_number);
+            }
+            in_range
+        }
+        CheckResult::ClearOutput { cell_number } => nb
+            .cells
+            .get_mut(*cell_number)
+            .and_then(Cell::as_codecell_mut)
+            .is_some_and(|codecell| {
+                let stripped = !codecell.is_clear_outputs();
+                codecell.clear_outputs();
+                stripped
+            }),
+        CheckResult::ClearCount { cell_number } => nb
+            .cells
+            .get_mut(*cell_number)
+            .and_then(Cell::as_codecell_mut)
+            .is_some_and(|codecell| {
+                let stripped = !codecell.is_clear_exec_count();
+                codecell.clear_counts();
+                stripped
+            }),
+        CheckResult::ClearSource { cell_number } => {
+            nb.cells.get_mut(*cell_number).is_some_and(|cell| {
+                let stripped = cell.should_clear_source(settings);
+                cell.clear_source();
+                stripped
+            })
+        }
+        CheckResult::ClearId { cell_number } => {
+            let new_id = match settings.id_action {
+                IdAction::Sequential => Some(format!("{cell_number}")),
+                IdAction::Drop => None,
+                IdAction::Keep => return false,
+            };
+            nb.cells
+                .get_mut(*cell_number)
+                .is_some_and(|cell| cell.set_id(new_id.clone()) != new_id)
+        }
+        CheckResult::DowngradeNBFormat { .. } => {
+            let downgrade = nb.nbformat_minor > ID_OPTIONAL_MAX_VERSION;
+            if downgrade {
+                for cell in &mut nb.cells {
+                    cell.set_id(None);
+                }
+                nb.nbformat_minor = ID_OPTIONAL_MAX_VERSION;
+            }
+            downgrade
+        }
+        CheckResult::ReplaceKernelspec => {
+            if let Some(canonical) = &settings.canonical_kernelspec
+                && let Some(metadata) = nb.metadata.as_object_mut()
+            {
+                metadata.insert("kernelspec".to_owned(), canonical.clone())
+                    != Some(canonical.clone())
+            } else {
+                false
+            }
+        }
+        CheckResult::IOError { .. }
+        | CheckResult::InvalidNotebook { .. }
+        | CheckResult::DisallowedKernel { .. }
+        | CheckResult::WrongLanguage { .. }
+        | CheckResult::WrongLanguageVersion { .. } => false,
+    }
+}
+
 pub fn strip_single(
     nb_path: &Path,
     textconv: bool,
@@ -101,19 +233,36 @@ pub fn strip_single(
         Some("-") => (read_nb_stdin()?, true, stdin_file_name),
         _ => (read_nb(nb_path)?, textconv, Some(nb_path)),
     };
-    let (strip_nb, stripped) = match (resolved_file_name, respect_exclusions) {
-        (None, _) => strip_nb(nb, settings),
-        (Some(_), false) => strip_nb(nb, settings),
-        (Some(stdin_name), true) => {
-            // git passes filter paths relative to the repo root; absolutize so
-            // they can match the absolutized exclude globs
-            if check_exclusions(&normalize_path(stdin_name), settings) {
-                (nb, false)
-            } else {
-                strip_nb(nb, settings)
-            }
+    let excluded = match (resolved_file_name, respect_exclusions) {
+        (None, _) | (Some(_), false) => false,
+        // git passes filter paths relative to the repo root; absolutize so
+        // they can match the absolutized exclude globs
+        (Some(stdin_name), true) => check_exclusions(&normalize_path(stdin_name), settings),
+    };
+    if settings.cache_outputs && to_stdout && !excluded && stdin_file_name.is_some() {
+        // running as the clean filter: keep the outputs so that smudge can restore them
+        if let Err(e) = OutputCache::for_repo(get_cwd())
+            .map_err(anyhow::Error::from)
+            .and_then(|cache| cache.store(&nb))
+        {
+            eprintln!("Could not cache outputs: {e}");
         }
+    }
+    let kernel_info =
+        (settings.record_on_clean && settings.strip_kernel_info && to_stdout && !excluded)
+            .then(|| stdin_file_name.zip(extract_record(&nb, &settings.record_keys)))
+            .flatten();
+    let (strip_nb, stripped) = if excluded {
+        (nb, false)
+    } else {
+        strip_nb(nb, settings)
     };
+    if let Some((stdin_name, info)) = kernel_info
+        && strip_nb.metadata.get("kernelspec").is_none()
+        && let Err(e) = record_kernel_info(stdin_name, info)
+    {
+        eprintln!("Could not record kernel info: {e}");
+    }
     // let (strip_nb, stripped) = strip_nb(nb, settings);
     match (to_stdout, stripped) {
         (true, _) => {
@@ -134,6 +283,13 @@ pub fn strip_single(
         }
     }
 }
+/// Size in bytes of the notebook once it has been cleaned and serialized.
+pub fn stripped_size(nb: RawNotebook, settings: &Settings) -> Result<u64, NBWriteError> {
+    let (stripped_nb, _) = strip_nb(nb, settings);
+    let mut out: Vec<u8> = Vec::new();
+    write_nb(&mut out, &stripped_nb)?;
+    Ok(out.len() as u64)
+}
 pub fn write_nb<W, T>(mut writer: W, value: &T) -> Result<(), NBWriteError>
 where
     W: Write,
@@ -218,9 +374,24 @@ impl StripResult {
         matches!(self, Self::ReadError(_) | Self::WriteError(_))
     }
 }
+#[allow(clippy::unwrap_used)]
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::{check::check_nb, cli::ConfigOverrides, files::read_nb};
+
+    #[test]
+    fn test_strip_result() {
+        let settings = Settings::construct(None, true, &ConfigOverrides::default()).unwrap();
+        let mut nb = read_nb("tests/e2e_notebooks/test_metadata.ipynb").unwrap();
+        let results = check_nb(&nb, &settings);
+        assert!(!results.is_empty());
+        for result in &results {
+            assert!(strip_result(&mut nb, result, &settings));
+        }
+        assert!(check_nb(&nb, &settings).is_empty());
+        assert!(!strip_result(&mut nb, &results[0], &settings));
+    }
 
     #[test]
     fn test_strip_error_to_strip_result() {
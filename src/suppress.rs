@@ -0,0 +1,114 @@
+use serde_json::Value;
+
+use crate::{
+    check::CheckResult,
+    rules::{Rule, RuleSelector},
+    schema::{Cell, RawNotebook},
+    utils::get_value_child,
+};
+
+/// The prefix of the cell tags that opt a cell out of rules.
+const TAG_PREFIX: &str = "nbwipers:";
+
+/// The rules a notebook opts out of itself, so that neither cleaning nor `check` applies them.
+///
+/// A notebook opts out of rules with `metadata.nbwipers.ignore`, a list of rule codes or
+/// prefixes, or `metadata.keep_output = true`, which ignores `NBW001` as in nbstripout. A cell
+/// opts out with a tag such as `nbwipers:ignore=NBW001,NBW002`, or `nbwipers:keep-metadata` to
+/// ignore `NBW011`.
+#[derive(Clone, Debug, Default)]
+pub struct Suppressions {
+    notebook: Vec<RuleSelector>,
+}
+
+impl Suppressions {
+    pub fn new(nb: &RawNotebook) -> Self {
+        let mut notebook: Vec<RuleSelector> =
+            get_value_child(&nb.metadata, &["nbwipers", "ignore"])
+                .and_then(Value::as_array)
+                .into_iter()
+                .flatten()
+                .filter_map(Value::as_str)
+                .filter_map(|selector| selector.parse().ok())
+                .collect();
+        if get_value_child(&nb.metadata, &["keep_output"])
+            .and_then(Value::as_bool)
+            .unwrap_or(false)
+        {
+            notebook.push(Rule::OutputPresent.into());
+        }
+        Self { notebook }
+    }
+
+    /// Whether the notebook opts out of `rule` as a whole.
+    pub fn notebook(&self, rule: Rule) -> bool {
+        self.notebook.iter().any(|selector| selector.matches(rule))
+    }
+
+    /// Whether `rule` does not apply to `cell`, because of the notebook or the tags of the cell.
+    pub fn cell(&self, cell: &Cell, rule: Rule) -> bool {
+        self.notebook(rule) || cell.get_tags().any(|tag| tag_suppresses(tag, rule))
+    }
+
+    /// Whether `result`, a finding of `check` in `nb`, is suppressed.
+    pub fn result(&self, nb: &RawNotebook, result: &CheckResult) -> bool {
+        let rule = result.rule();
+        if rule.is_read_error() {
+            return false;
+        }
+        match result.cell_number().and_then(|n| nb.cells.get(n)) {
+            Some(cell) => self.cell(cell, rule),
+            None => self.notebook(rule),
+        }
+    }
+}
+
+fn tag_suppresses(tag: &str, rule: Rule) -> bool {
+    let Some(directive) = tag.strip_prefix(TAG_PREFIX) else {
+        return false;
+    };
+    match directive.split_once('=') {
+        Some(("ignore", selectors)) => selectors
+            .split(',')
+            .filter_map(|selector| selector.trim().parse::<RuleSelector>().ok())
+            .any(|selector| selector.matches(rule)),
+        Some(_) => false,
+        None => directive == "keep-metadata" && rule == Rule::CellMetadata,
+    }
+}
+
+#[allow(clippy::unwrap_used)]
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_suppressions() {
+        let nb: RawNotebook = serde_json::from_value(json!({
+            "metadata": {"nbwipers": {"ignore": ["NBW01", "not-a-rule"]}},
+            "nbformat": 4,
+            "nbformat_minor": 5,
+            "cells": [{
+                "cell_type": "code",
+                "id": "0",
+                "execution_count": 1,
+                "metadata": {"tags": ["nbwipers:ignore=NBW002, NBW003", "nbwipers:keep-metadata"]},
+                "outputs": [],
+                "source": ""
+            }]
+        }))
+        .unwrap();
+        let suppressions = Suppressions::new(&nb);
+        let cell = &nb.cells[0];
+        assert!(suppressions.notebook(Rule::NotebookMetadata));
+        assert!(!suppressions.notebook(Rule::OutputPresent));
+        assert!(suppressions.cell(cell, Rule::ExecutionCount));
+        assert!(suppressions.cell(cell, Rule::CellId));
+        assert!(suppressions.cell(cell, Rule::CellMetadata));
+        assert!(!suppressions.cell(cell, Rule::DroppedCell));
+        assert!(suppressions.result(&nb, &CheckResult::ClearCount { cell_number: 0 }));
+        assert!(!suppressions.result(&nb, &CheckResult::ClearOutput { cell_number: 0 }));
+    }
+}
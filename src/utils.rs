@@ -85,6 +85,36 @@ pub fn get_value_child<'a, T: AsRef<str>>(value: &'a Value, path: &[T]) -> Optio
     None
 }
 
+/// Insert `new` at `path` unless a value is already present there, creating intermediate objects
+/// as needed. Returns whether the value was inserted.
+pub fn insert_value_child<T: AsRef<str>>(value: &mut Value, path: &[T], new: Value) -> bool {
+    if get_value_child(value, path).is_some() {
+        return false;
+    }
+    let Some((last, parents)) = path.split_last() else {
+        return false;
+    };
+    let mut cur = value;
+    for segment in parents {
+        if cur.is_null() {
+            *cur = Value::Object(serde_json::Map::new());
+        }
+        let Some(obj) = cur.as_object_mut() else {
+            return false;
+        };
+        cur = obj
+            .entry(segment.as_ref())
+            .or_insert_with(|| Value::Object(serde_json::Map::new()));
+    }
+    if cur.is_null() {
+        *cur = Value::Object(serde_json::Map::new());
+    }
+    cur.as_object_mut().is_some_and(|obj| {
+        obj.insert(last.as_ref().to_owned(), new);
+        true
+    })
+}
+
 // fn get_value_child_mut<'a, T: AsRef<str>>(
 //     value: &'a mut Value,
 //     path: &[T],
@@ -149,6 +179,30 @@ mod test {
         assert!(metadata.as_object().is_some_and(serde_json::Map::is_empty));
     }
     #[test]
+    fn test_insert_value_child() {
+        let mut metadata = json!({"jupyter": {"outputs_hidden": true}});
+        assert!(insert_value_child(
+            &mut metadata,
+            &["jupyter", "source_hidden"],
+            json!(true)
+        ));
+        assert!(!insert_value_child(
+            &mut metadata,
+            &["jupyter", "outputs_hidden"],
+            json!(false)
+        ));
+        assert!(insert_value_child(&mut metadata, &["a", "b"], json!(1)));
+        assert_eq!(
+            metadata,
+            json!({"jupyter": {"outputs_hidden": true, "source_hidden": true}, "a": {"b": 1}})
+        );
+        let mut null = Value::Null;
+        assert!(insert_value_child(&mut null, &["widgets"], json!({})));
+        assert_eq!(null, json!({"widgets": {}}));
+        let mut number = json!(10);
+        assert!(!insert_value_child(&mut number, &["widgets"], json!({})));
+    }
+    #[test]
     fn test_pop_nothing() {
         let mut cell = Cell::Code(CodeCell {
             execution_count: None,
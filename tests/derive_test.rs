@@ -0,0 +1,120 @@
+use std::{fs, path::PathBuf, process::Command};
+
+#[test]
+fn test_derive_student() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let cur_exe = PathBuf::from(env!("CARGO_BIN_EXE_nbwipers"));
+    fs::create_dir(temp_dir.path().join("course")).unwrap();
+    fs::copy(
+        "tests/e2e_notebooks/test_derive_solution.ipynb",
+        temp_dir.path().join("course/exercise.ipynb"),
+    )
+    .unwrap();
+
+    let output = Command::new(&cur_exe)
+        .current_dir(&temp_dir)
+        .args([
+            "derive",
+            "--profile",
+            "student",
+            "--output-dir",
+            "student",
+            "--isolated",
+            ".",
+        ])
+        .output()
+        .expect("command failed");
+    assert!(output.status.success());
+    let expected =
+        fs::read_to_string("tests/e2e_notebooks/test_derive_solution.ipynb.expected").unwrap();
+    let derived =
+        fs::read_to_string(temp_dir.path().join("student/course/exercise.ipynb")).unwrap();
+    assert_eq!(derived, expected);
+
+    // derived notebooks are not derived again
+    let output = Command::new(&cur_exe)
+        .current_dir(&temp_dir)
+        .args([
+            "derive",
+            "--profile",
+            "student",
+            "--output-dir",
+            "student",
+            "--isolated",
+            ".",
+        ])
+        .output()
+        .expect("command failed");
+    assert!(output.status.success());
+    assert!(!temp_dir.path().join("student/student").exists());
+}
+
+#[test]
+fn test_derive_unterminated_block() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let cur_exe = PathBuf::from(env!("CARGO_BIN_EXE_nbwipers"));
+    let solution = fs::read_to_string("tests/e2e_notebooks/test_derive_solution.ipynb")
+        .unwrap()
+        .replace("    ### END SOLUTION", "");
+    fs::write(temp_dir.path().join("exercise.ipynb"), solution).unwrap();
+
+    let output = Command::new(&cur_exe)
+        .current_dir(&temp_dir)
+        .args([
+            "derive",
+            "--profile",
+            "student",
+            "--output-dir",
+            "student",
+            "--isolated",
+            "exercise.ipynb",
+        ])
+        .output()
+        .expect("command failed");
+    assert!(!output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("cell 1: `### BEGIN SOLUTION` is not followed by an end marker"));
+    assert!(!temp_dir.path().join("student/exercise.ipynb").exists());
+}
+
+#[test]
+fn test_derive_student_configured_tags() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let cur_exe = PathBuf::from(env!("CARGO_BIN_EXE_nbwipers"));
+    let mut nb: serde_json::Value = serde_json::from_str(
+        &fs::read_to_string("tests/e2e_notebooks/test_derive_solution.ipynb").unwrap(),
+    )
+    .unwrap();
+    nb["cells"].as_array_mut().unwrap().push(serde_json::json!({
+        "cell_type": "code",
+        "execution_count": null,
+        "metadata": {"tags": ["scratch"]},
+        "outputs": [],
+        "source": ["print(double(3))"]
+    }));
+    fs::write(temp_dir.path().join("exercise.ipynb"), nb.to_string()).unwrap();
+    fs::write(
+        temp_dir.path().join(".nbwipers.toml"),
+        "drop-tagged-cells = [\"scratch\"]\n",
+    )
+    .unwrap();
+
+    let output = Command::new(&cur_exe)
+        .current_dir(&temp_dir)
+        .args([
+            "derive",
+            "--profile",
+            "student",
+            "--output-dir",
+            "student",
+            "exercise.ipynb",
+        ])
+        .output()
+        .expect("command failed");
+    assert!(output.status.success());
+    // the student tags are added to the configured tags
+    let derived = fs::read_to_string(temp_dir.path().join("student/exercise.ipynb")).unwrap();
+    let expected =
+        fs::read_to_string("tests/e2e_notebooks/test_derive_solution.ipynb.expected").unwrap();
+    assert_eq!(derived, expected);
+}
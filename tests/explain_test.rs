@@ -0,0 +1,104 @@
+use std::{path::PathBuf, process::Command};
+
+use bstr::ByteSlice;
+
+#[test]
+fn test_show_config_explain() {
+    let cur_exe = PathBuf::from(env!("CARGO_BIN_EXE_nbwipers"));
+    let output = Command::new(&cur_exe)
+        .args([
+            "show-config",
+            "--explain",
+            "-c",
+            "tests/e2e_notebooks/test_profiles.toml",
+            "--profile",
+            "docs",
+            "--keep-init-cell",
+            "--strip-kernel-info",
+        ])
+        .output()
+        .expect("command failed");
+    assert!(output.status.success());
+    insta::assert_snapshot!(output.stdout.to_str().unwrap());
+}
+
+#[test]
+fn test_explain() {
+    let cur_exe = PathBuf::from(env!("CARGO_BIN_EXE_nbwipers"));
+    let output = Command::new(&cur_exe)
+        .args([
+            "explain",
+            "tests/e2e_notebooks/test_metadata.ipynb",
+            "-c",
+            "tests/e2e_notebooks/test_profiles.toml",
+            "--warn",
+            "NBW002",
+            "--ignore",
+            "NBW011",
+        ])
+        .output()
+        .expect("command failed");
+    // explaining findings does not fail on them
+    assert!(output.status.success());
+    insta::assert_snapshot!(output.stdout.to_str().unwrap());
+
+    let output = Command::new(&cur_exe)
+        .args([
+            "explain",
+            "tests/e2e_notebooks/test_metadata.ipynb",
+            "--isolated",
+            "--cell",
+            "2",
+        ])
+        .output()
+        .expect("command failed");
+    assert!(output.status.success());
+    let output = output.stdout.to_str().unwrap();
+    assert!(output.lines().any(|line| line.contains("NBW002 cell 2")));
+    assert!(!output.contains("cell 1:"));
+
+    let output = Command::new(&cur_exe)
+        .args([
+            "explain",
+            "tests/e2e_notebooks/test_metadata.ipynb",
+            "--isolated",
+            "--cell",
+            "9",
+        ])
+        .output()
+        .expect("command failed");
+    assert!(!output.status.success());
+    assert!(
+        output
+            .stderr
+            .to_str()
+            .unwrap()
+            .contains("There is no cell 9, as the notebook has 4 cells")
+    );
+}
+
+#[test]
+fn test_show_config_explain_id_flag() {
+    let cur_exe = PathBuf::from(env!("CARGO_BIN_EXE_nbwipers"));
+    for (flag, expected) in [
+        (
+            "--drop-id",
+            r#"id-action = "drop" # command line `--drop-id`"#,
+        ),
+        (
+            "--sequential-id",
+            r#"id-action = "sequential" # command line `--sequential-id`"#,
+        ),
+        (
+            "--id-action=keep",
+            r#"id-action = "keep" # command line `--id-action`"#,
+        ),
+    ] {
+        let output = Command::new(&cur_exe)
+            .args(["show-config", "--explain", "--isolated", flag])
+            .output()
+            .expect("command failed");
+        assert!(output.status.success());
+        assert!(output.stdout.to_str().unwrap().contains(expected));
+    }
+}
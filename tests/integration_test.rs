@@ -2,7 +2,7 @@ use std::{env, fs, io::BufWriter, io::Write, path::PathBuf, process::Command, pr
 
 use bstr::ByteSlice;
 use nbwipers::{
-    schema::{Cell, CodeCell, RawNotebook, SourceValue},
+    schema::{Cell, CodeCell, MarkdownCell, RawNotebook, SourceValue},
     strip::write_nb,
 };
 use serde_json::{Value, json};
@@ -345,6 +345,35 @@ fn test_uninstall_invalid_attribute_line() {
     assert_eq!(fs::read_to_string(&attr_file).unwrap(), attr_contents);
 }
 
+#[test]
+fn test_install_keeps_non_utf8_attribute_file() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let cur_exe = PathBuf::from(env!("CARGO_BIN_EXE_nbwipers"));
+
+    let git_init_out = Command::new("git")
+        .current_dir(&temp_dir)
+        .args(["init"])
+        .output()
+        .expect("git init failed");
+    assert!(git_init_out.status.success());
+    let attr_file = temp_dir.path().join(".gitattributes");
+    let attr_contents = b"caf\xe9.txt text\n";
+    fs::write(&attr_file, attr_contents).unwrap();
+
+    let output = Command::new(&cur_exe)
+        .current_dir(&temp_dir)
+        .args(["install", "local", "-a", ".gitattributes"])
+        .output()
+        .expect("command failed");
+    assert!(output.status.success());
+    let new_contents = fs::read(&attr_file).unwrap();
+    assert!(new_contents.starts_with(attr_contents));
+    let added = new_contents[attr_contents.len()..].to_vec();
+    let added = String::from_utf8(added).unwrap();
+    assert!(added.lines().any(|line| line == "*.ipynb filter=nbwipers"));
+    assert!(added.lines().any(|line| line == "*.ipynb diff=nbwipers"));
+}
+
 #[test]
 fn test_check_install() {
     let temp_dir = tempfile::tempdir().unwrap();
@@ -495,6 +524,307 @@ fn test_check_install_bare_repo() {
     assert!(!output.status.success());
 }
 
+#[test]
+fn test_project_install() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let cur_exe = PathBuf::from(env!("CARGO_BIN_EXE_nbwipers"));
+    let path = env::join_paths(
+        std::iter::once(cur_exe.parent().unwrap().to_path_buf())
+            .chain(env::split_paths(&env::var_os("PATH").unwrap_or_default())),
+    )
+    .unwrap();
+    let git = |args: &[&str]| {
+        let output = Command::new("git")
+            .current_dir(&temp_dir)
+            .env("PATH", &path)
+            .args([
+                "-c",
+                "user.name=nbwipers",
+                "-c",
+                "user.email=nbwipers@example.com",
+            ])
+            .args(args)
+            .output()
+            .expect("git failed");
+        assert!(output.status.success(), "{output:?}");
+        output.stdout
+    };
+    git(&["init"]);
+    let output = Command::new(&cur_exe)
+        .current_dir(&temp_dir)
+        .env("PATH", &path)
+        .args(["install", "local", "--project"])
+        .output()
+        .expect("command failed");
+    assert!(output.status.success(), "{output:?}");
+    let attr_contents = fs::read_to_string(temp_dir.path().join(".gitattributes")).unwrap();
+    assert!(attr_contents.contains("*.ipynb filter=nbwipers"));
+    let config_contents = fs::read_to_string(temp_dir.path().join(".git/config")).unwrap();
+    assert!(config_contents.contains("clean = nbwipers clean - --stdin-file-name %f"));
+    assert!(config_contents.contains("smudge = nbwipers smudge %f"));
+
+    fs::copy(
+        "tests/e2e_notebooks/test_metadata.ipynb",
+        temp_dir.path().join("notebook.ipynb"),
+    )
+    .unwrap();
+    git(&["add", "."]);
+    git(&["commit", "-m", "add notebook"]);
+    let committed = git(&["show", "HEAD:notebook.ipynb"]);
+    let committed_nb: RawNotebook = serde_json::from_slice(&committed).unwrap();
+    assert!(
+        committed_nb
+            .cells
+            .iter()
+            .filter_map(Cell::as_codecell)
+            .all(|cell| cell.execution_count.is_none())
+    );
+
+    let output = Command::new(&cur_exe)
+        .current_dir(&temp_dir)
+        .args(["install", "local", "--command", "python"])
+        .output()
+        .expect("command failed");
+    assert!(output.status.success());
+    let config_contents = fs::read_to_string(temp_dir.path().join(".git/config")).unwrap();
+    assert!(config_contents.contains("smudge = python -m nbwipers smudge %f"));
+}
+
+#[test]
+fn test_check_install_stale_executable() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let cur_exe = PathBuf::from(env!("CARGO_BIN_EXE_nbwipers"));
+    let git_init_out = Command::new("git")
+        .current_dir(&temp_dir)
+        .args(["init"])
+        .output()
+        .expect("git init failed");
+    assert!(git_init_out.status.success());
+    let output = Command::new(&cur_exe)
+        .current_dir(&temp_dir)
+        .args(["install", "local", "-a", ".gitattributes"])
+        .output()
+        .expect("command failed");
+    assert!(output.status.success());
+    let check_install = || {
+        Command::new(&cur_exe)
+            .current_dir(&temp_dir)
+            .env_remove("NBWIPERS_CHECK_INSTALL_EXIT_ZERO")
+            .args(["check-install", "local"])
+            .output()
+            .expect("command failed")
+    };
+    assert!(check_install().status.success());
+
+    // simulate the executable having moved since it was installed
+    let config_file = temp_dir.path().join(".git/config");
+    let missing_exe = temp_dir.path().join("old-venv/bin/nbwipers");
+    let config_contents = fs::read_to_string(&config_file)
+        .unwrap()
+        .replace(cur_exe.to_str().unwrap(), missing_exe.to_str().unwrap());
+    fs::write(&config_file, config_contents).unwrap();
+    let output = check_install();
+    assert!(!output.status.success());
+    assert!(
+        String::from_utf8(output.stderr)
+            .unwrap()
+            .contains("which does not exist")
+    );
+    // the report has the missing executable in its JSON instead
+    let output = Command::new(&cur_exe)
+        .current_dir(&temp_dir)
+        .env_remove("NBWIPERS_CHECK_INSTALL_EXIT_ZERO")
+        .args(["check-install", "local", "-o", "json"])
+        .output()
+        .expect("command failed");
+    assert!(
+        !String::from_utf8(output.stderr)
+            .unwrap()
+            .contains("which does not exist")
+    );
+}
+
+#[test]
+fn test_check_install_report() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let cur_exe = PathBuf::from(env!("CARGO_BIN_EXE_nbwipers"));
+    let git_init_out = Command::new("git")
+        .current_dir(&temp_dir)
+        .args(["init"])
+        .output()
+        .expect("git init failed");
+    assert!(git_init_out.status.success());
+    let output = Command::new(&cur_exe)
+        .current_dir(&temp_dir)
+        .args(["install", "local", "-a", ".gitattributes"])
+        .output()
+        .expect("command failed");
+    assert!(output.status.success());
+    let get_report = || {
+        let output = Command::new(&cur_exe)
+            .current_dir(&temp_dir)
+            .env_remove("NBWIPERS_CHECK_INSTALL_EXIT_ZERO")
+            .args(["check-install", "local", "-o", "json"])
+            .output()
+            .expect("command failed");
+        let report: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+        (output.status.success(), report)
+    };
+    let (success, report) = get_report();
+    assert!(success);
+    assert_eq!(report["installed"], serde_json::json!(["nbwipers"]));
+    let clean = report["config_keys"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|key| key["key"] == "filter.nbwipers.clean")
+        .unwrap();
+    assert_eq!(clean["scope"], "local");
+    assert_eq!(clean["effective"], true);
+    assert_eq!(clean["executable"]["exists"], true);
+    assert_eq!(clean["executable"]["version_matches"], true);
+    let repo_attrs = report["attribute_files"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|file| file["scope"] == "repository")
+        .unwrap();
+    assert_eq!(repo_attrs["assignments"][0]["value"], "nbwipers");
+    assert_eq!(report["conflicts"], serde_json::json!([]));
+
+    // info/attributes takes precedence over .gitattributes
+    fs::write(
+        temp_dir.path().join(".git/info/attributes"),
+        "*.ipynb filter=lfs\n",
+    )
+    .unwrap();
+    let (_, report) = get_report();
+    let conflicts = report["conflicts"].as_array().unwrap();
+    assert!(
+        conflicts
+            .iter()
+            .any(|c| c.as_str().unwrap().contains("filter=nbwipers"))
+    );
+    assert!(
+        conflicts
+            .iter()
+            .any(|c| c.as_str().unwrap().contains("filter=lfs"))
+    );
+
+    // patterns other than `*.ipynb` that match notebooks count too, unlike those that do not
+    fs::write(
+        temp_dir.path().join(".git/info/attributes"),
+        "**/*.ipynb filter=lfs\nnotebooks/*.ipynb diff=custom\n*.py filter=black\n",
+    )
+    .unwrap();
+    let (_, report) = get_report();
+    let info_attrs = report["attribute_files"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|file| file["scope"] == "info")
+        .unwrap();
+    let values: Vec<_> = info_attrs["assignments"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|a| a["value"].as_str().unwrap())
+        .collect();
+    assert_eq!(values, ["lfs", "custom"]);
+    let conflicts = report["conflicts"].as_array().unwrap();
+    assert!(
+        conflicts
+            .iter()
+            .any(|c| c.as_str().unwrap().contains("filter=lfs"))
+    );
+    assert!(
+        !conflicts
+            .iter()
+            .any(|c| c.as_str().unwrap().contains("black"))
+    );
+}
+
+#[test]
+fn test_migrate_from_nbstripout() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let cur_exe = PathBuf::from(env!("CARGO_BIN_EXE_nbwipers"));
+    let git = |args: &[&str]| {
+        let output = Command::new("git")
+            .current_dir(&temp_dir)
+            .args(args)
+            .output()
+            .expect("git failed");
+        assert!(output.status.success(), "{output:?}");
+    };
+    git(&["init"]);
+    git(&["config", "filter.nbstripout.clean", "python -m nbstripout"]);
+    git(&["config", "filter.nbstripout.smudge", "cat"]);
+    git(&["config", "diff.ipynb.textconv", "python -m nbstripout -t"]);
+    git(&["config", "filter.nbstripout.keepcount", "true"]);
+    git(&[
+        "config",
+        "filter.nbstripout.extrakeys",
+        "metadata.foo cell.metadata.bar",
+    ]);
+    let attr_file = temp_dir.path().join(".git/info/attributes");
+    let attr_contents = "*.ipynb filter=nbstripout\n*.zpln filter=nbstripout\n*.ipynb diff=ipynb\n";
+    fs::write(&attr_file, attr_contents).unwrap();
+    let config_file = temp_dir.path().join(".git/config");
+    let config_contents = fs::read_to_string(&config_file).unwrap();
+
+    let migrate = |dry_run: bool| {
+        let mut command = Command::new(&cur_exe);
+        command
+            .current_dir(&temp_dir)
+            .args(["migrate-from-nbstripout", "local"]);
+        if dry_run {
+            command.arg("--dry-run");
+        }
+        let output = command.output().expect("command failed");
+        assert!(output.status.success(), "{output:?}");
+        String::from_utf8(output.stdout).unwrap()
+    };
+    let diff = migrate(true);
+    assert!(diff.contains("+drop-count = false"));
+    assert!(diff.contains("-[filter \"nbstripout\"]"));
+    assert!(diff.contains("-*.ipynb filter=nbstripout"));
+    assert!(diff.contains("+*.ipynb filter=nbwipers"));
+    assert!(!temp_dir.path().join(".nbwipers.toml").exists());
+    assert_eq!(fs::read_to_string(&config_file).unwrap(), config_contents);
+    assert_eq!(fs::read_to_string(&attr_file).unwrap(), attr_contents);
+
+    migrate(false);
+    let nbwipers_toml = fs::read_to_string(temp_dir.path().join(".nbwipers.toml")).unwrap();
+    assert_eq!(
+        nbwipers_toml,
+        "extra-keys = [\"metadata.foo\", \"cell.metadata.bar\"]\ndrop-count = false\n"
+    );
+    let config_contents = fs::read_to_string(&config_file).unwrap();
+    assert!(!config_contents.contains("nbstripout"));
+    assert!(config_contents.contains("[filter \"nbwipers\"]"));
+    assert_eq!(
+        fs::read_to_string(&attr_file).unwrap(),
+        "*.ipynb diff=nbwipers\n*.ipynb filter=nbwipers\n"
+    );
+
+    let output = Command::new(&cur_exe)
+        .current_dir(&temp_dir)
+        .env_remove("NBWIPERS_CHECK_INSTALL_EXIT_ZERO")
+        .args(["check-install", "local"])
+        .output()
+        .expect("command failed");
+    assert!(output.status.success());
+    assert_eq!(output.stdout.as_bstr(), "nbwipers is installed\n");
+
+    // nothing is left to migrate
+    let output = Command::new(&cur_exe)
+        .current_dir(&temp_dir)
+        .args(["migrate-from-nbstripout", "local"])
+        .output()
+        .expect("command failed");
+    assert!(!output.status.success());
+}
+
 #[test]
 fn test_uninstall_fresh_repo_defaults() {
     // uninstall using the default (non-explicit) git config file and
@@ -778,6 +1108,54 @@ fn test_large_files() {
         .expect("command failed");
 
     assert!(!output.status.success());
+
+    let git = |args: &[&str]| {
+        let git_out = Command::new("git")
+            .current_dir(&temp_dir)
+            .args([
+                "-c",
+                "user.name=nbwipers",
+                "-c",
+                "user.email=nbwipers@example.com",
+            ])
+            .args(args)
+            .output()
+            .expect("git failed");
+        assert!(git_out.status.success());
+    };
+    let check = |path: &str| {
+        Command::new(&cur_exe)
+            .current_dir(temp_dir.path())
+            .args(["hook", "check-large-files", path])
+            .output()
+            .expect("command failed")
+            .status
+            .success()
+    };
+    git(&["commit", "-m", "add large notebook"]);
+    // once committed, the file is no longer added
+    assert!(check("large_nb.ipynb"));
+
+    // a copy of a committed file is added
+    fs::copy(
+        temp_dir.path().join("large_nb.ipynb"),
+        temp_dir.path().join("large_copy.ipynb"),
+    )
+    .unwrap();
+    git(&["add", "large_copy.ipynb"]);
+    assert!(!check("large_copy.ipynb"));
+
+    // so is a renamed file with edits
+    git(&["mv", "large_nb.ipynb", "large_moved.ipynb"]);
+    *nb.cells[0].get_source_mut() =
+        SourceValue::StringArray(vec!["# This is an edited test".to_string()]);
+    write_nb(
+        BufWriter::new(fs::File::create(temp_dir.path().join("large_moved.ipynb")).unwrap()),
+        &nb,
+    )
+    .unwrap();
+    git(&["add", "large_moved.ipynb"]);
+    assert!(!check("large_moved.ipynb"));
 }
 
 #[test]
@@ -823,6 +1201,68 @@ fn test_large_files_lfs_skip() {
     assert!(output.status.success());
 }
 
+#[test]
+fn test_large_files_custom_lfs_filters() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let cur_exe = PathBuf::from(env!("CARGO_BIN_EXE_nbwipers"));
+
+    let git_init_out = Command::new("git")
+        .current_dir(&temp_dir)
+        .args(["init"])
+        .output()
+        .expect("git init failed");
+    assert!(git_init_out.status.success());
+    for name in ["store.bin", "annex.bin", "unset.bin"] {
+        fs::write(temp_dir.path().join(name), "a".repeat(1000 * 1024)).unwrap();
+    }
+    fs::write(
+        temp_dir.path().join(".gitattributes"),
+        "*.bin filter=lfs\nstore.bin filter=store\nannex.bin filter=annex\nunset.bin -filter\n",
+    )
+    .unwrap();
+    // a filter is recognised as lfs-like if its command runs git-lfs
+    let git_config_out = Command::new("git")
+        .current_dir(&temp_dir)
+        .args(["config", "filter.store.process", "git-lfs filter-process"])
+        .output()
+        .expect("git config failed");
+    assert!(git_config_out.status.success());
+
+    let output = Command::new(&cur_exe)
+        .current_dir(temp_dir.path())
+        .args([
+            "hook",
+            "check-large-files",
+            "--enforce-all",
+            "store.bin",
+            "annex.bin",
+            "unset.bin",
+        ])
+        .output()
+        .expect("command failed");
+    assert!(!output.status.success());
+    let stdout = output.stdout.to_str().unwrap();
+    assert!(!stdout.contains("store.bin"));
+    assert!(stdout.contains("annex.bin"));
+    // `-filter` overrides the lfs filter set for all `.bin` files
+    assert!(stdout.contains("unset.bin"));
+
+    let output = Command::new(&cur_exe)
+        .current_dir(temp_dir.path())
+        .args([
+            "hook",
+            "check-large-files",
+            "--enforce-all",
+            "--lfs-filters",
+            "annex",
+            "store.bin",
+            "annex.bin",
+        ])
+        .output()
+        .expect("command failed");
+    assert!(output.status.success());
+}
+
 #[test]
 fn test_large_files_non_ipynb() {
     // non-notebook files are measured by their on-disk size directly
@@ -864,7 +1304,7 @@ fn test_large_files_non_ipynb() {
 
 #[test]
 fn test_large_files_outside_git_repo() {
-    // the hook shells out to `git check-attr`, which fails outside a repo
+    // the hook reads the git index and attributes, which don't exist outside a repo
     let temp_dir = tempfile::tempdir().unwrap();
     let cur_exe = PathBuf::from(env!("CARGO_BIN_EXE_nbwipers"));
 
@@ -883,7 +1323,7 @@ fn test_large_files_outside_git_repo() {
             .stderr
             .to_str()
             .unwrap()
-            .contains("Git check-attr failed")
+            .contains("Not inside a git repository")
     );
 }
 
@@ -1019,3 +1459,413 @@ fn test_clean_all_non_interactive_confirmation() {
     assert!(!output.status.success());
     assert_eq!(fs::read_to_string(&nb_path).unwrap(), before);
 }
+
+#[test]
+fn test_audit() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let cur_exe = PathBuf::from(env!("CARGO_BIN_EXE_nbwipers"));
+    fs::copy(
+        "tests/e2e_notebooks/test_max_size.ipynb",
+        temp_dir.path().join("big.ipynb"),
+    )
+    .unwrap();
+    fs::copy(
+        "tests/e2e_notebooks/test_unicode.ipynb",
+        temp_dir.path().join("small.ipynb"),
+    )
+    .unwrap();
+    let git_init_out = Command::new("git")
+        .current_dir(&temp_dir)
+        .args(["init"])
+        .output()
+        .expect("git init failed");
+    assert!(git_init_out.status.success());
+    for args in [
+        &["add", "big.ipynb"][..],
+        &[
+            "-c",
+            "user.name=nbwipers",
+            "-c",
+            "user.email=nbwipers@example.com",
+            "commit",
+            "-m",
+            "add big notebook",
+        ],
+    ] {
+        let git_out = Command::new("git")
+            .current_dir(&temp_dir)
+            .args(args)
+            .output()
+            .expect("git failed");
+        assert!(git_out.status.success());
+    }
+
+    let output = Command::new(&cur_exe)
+        .current_dir(&temp_dir)
+        .args(["audit", "-o", "json", "--git-history"])
+        .output()
+        .expect("command failed");
+    assert!(output.status.success());
+    let report: Value = serde_json::from_slice(&output.stdout).unwrap();
+    let report = report.as_array().unwrap();
+    assert_eq!(report.len(), 2);
+    assert_eq!(report[0]["path"], "big.ipynb");
+    assert!(report[0]["savings"].as_u64().unwrap() >= report[1]["savings"].as_u64().unwrap());
+    assert!(report[0]["stripped_size"].as_u64().unwrap() < report[0]["size"].as_u64().unwrap());
+    assert!(!report[0]["largest_outputs"].as_array().unwrap().is_empty());
+    assert_eq!(report[0]["history"]["versions"], 1);
+    assert_eq!(report[1]["history"]["versions"], 0);
+
+    let output = Command::new(&cur_exe)
+        .current_dir(&temp_dir)
+        .args(["audit"])
+        .output()
+        .expect("command failed");
+    assert!(output.status.success());
+    assert!(output.stdout.to_str().unwrap().contains("bytes saved"));
+}
+
+#[test]
+fn test_hook_check_merge_conflict() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let cur_exe = PathBuf::from(env!("CARGO_BIN_EXE_nbwipers"));
+
+    let clean_nb = RawNotebook {
+        cells: vec![
+            Cell::Code(CodeCell {
+                execution_count: None,
+                id: Some("a".into()),
+                metadata: json!({}),
+                outputs: vec![],
+                source: SourceValue::StringArray(vec!["x = 1\n".into()]),
+            }),
+            // a setext heading is underlined like the separator of a conflict
+            Cell::Markdown(MarkdownCell {
+                attachments: None,
+                id: Some("b".into()),
+                metadata: json!({}),
+                source: SourceValue::StringArray(vec!["Results\n".into(), "=======".into()]),
+            }),
+        ],
+        ..Default::default()
+    };
+    let mut conflicted_nb = clean_nb.clone();
+    if let Cell::Code(cell) = &mut conflicted_nb.cells[0] {
+        cell.source = SourceValue::StringArray(vec![
+            "<<<<<<< HEAD\n".into(),
+            "x = 1\n".into(),
+            "=======\n".into(),
+            "x = 2\n".into(),
+            ">>>>>>> branch\n".into(),
+        ]);
+    }
+    write_nb(
+        fs::File::create(temp_dir.path().join("clean.ipynb")).unwrap(),
+        &clean_nb,
+    )
+    .unwrap();
+    write_nb(
+        fs::File::create(temp_dir.path().join("cells.ipynb")).unwrap(),
+        &conflicted_nb,
+    )
+    .unwrap();
+    fs::write(
+        temp_dir.path().join("json.ipynb"),
+        "{\n<<<<<<< HEAD\n  \"cells\": [],\n=======\n  \"cells\": [{}],\n>>>>>>> branch\n}\n",
+    )
+    .unwrap();
+
+    let output = Command::new(&cur_exe)
+        .current_dir(temp_dir.path())
+        .args(["hook", "check-merge-conflict", "clean.ipynb"])
+        .output()
+        .expect("command failed");
+    assert!(output.status.success());
+
+    let output = Command::new(&cur_exe)
+        .current_dir(temp_dir.path())
+        .args([
+            "hook",
+            "check-merge-conflict",
+            "clean.ipynb",
+            "cells.ipynb",
+            "json.ipynb",
+        ])
+        .output()
+        .expect("command failed");
+    assert!(!output.status.success());
+    let stdout = output.stdout.to_str().unwrap();
+    assert!(!stdout.contains("clean.ipynb"));
+    assert!(stdout.contains("cells.ipynb: merge conflict marker at cell 0: <<<<<<< HEAD"));
+    assert!(stdout.contains("cells.ipynb: merge conflict marker at cell 0: ======="));
+    assert!(!stdout.contains("cell 1:"));
+    assert!(stdout.contains("json.ipynb: merge conflict marker at line 2: <<<<<<< HEAD"));
+    assert!(stdout.contains("json.ipynb: merge conflict marker at line 4: ======="));
+}
+
+#[test]
+fn test_hook_check_json() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let cur_exe = PathBuf::from(env!("CARGO_BIN_EXE_nbwipers"));
+
+    fs::write(
+        temp_dir.path().join("syntax.ipynb"),
+        "{\n  \"cells\": [\n}\n",
+    )
+    .unwrap();
+    fs::write(
+        temp_dir.path().join("schema.ipynb"),
+        "{\n  \"cells\": [],\n  \"metadata\": {},\n  \"nbformat\": \"four\",\n  \"nbformat_minor\": 5\n}\n",
+    )
+    .unwrap();
+    write_nb(
+        fs::File::create(temp_dir.path().join("valid.ipynb")).unwrap(),
+        &RawNotebook::default(),
+    )
+    .unwrap();
+
+    let output = Command::new(&cur_exe)
+        .current_dir(temp_dir.path())
+        .args(["hook", "check-json", "valid.ipynb"])
+        .output()
+        .expect("command failed");
+    assert!(output.status.success());
+
+    let output = Command::new(&cur_exe)
+        .current_dir(temp_dir.path())
+        .args([
+            "hook",
+            "check-json",
+            "syntax.ipynb",
+            "schema.ipynb",
+            "valid.ipynb",
+        ])
+        .output()
+        .expect("command failed");
+    assert!(!output.status.success());
+    let stdout = output.stdout.to_str().unwrap();
+    assert!(stdout.contains("syntax.ipynb:3:1: is not valid JSON"));
+    assert!(stdout.contains("schema.ipynb:4:"));
+    assert!(stdout.contains("does not follow the notebook schema"));
+    assert!(!stdout.contains("valid.ipynb:"));
+}
+
+#[test]
+fn test_hook_check_kernel() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let cur_exe = PathBuf::from(env!("CARGO_BIN_EXE_nbwipers"));
+
+    for (name, kernel) in [("py.ipynb", "python3"), ("r.ipynb", "ir")] {
+        let nb = RawNotebook {
+            metadata: json!({"kernelspec": {"name": kernel, "language": "", "display_name": ""}}),
+            ..Default::default()
+        };
+        write_nb(fs::File::create(temp_dir.path().join(name)).unwrap(), &nb).unwrap();
+    }
+    write_nb(
+        fs::File::create(temp_dir.path().join("none.ipynb")).unwrap(),
+        &RawNotebook::default(),
+    )
+    .unwrap();
+
+    let output = Command::new(&cur_exe)
+        .current_dir(temp_dir.path())
+        .args([
+            "hook",
+            "check-kernel",
+            "--allowed-kernels",
+            "python3,ir",
+            "py.ipynb",
+            "r.ipynb",
+            "none.ipynb",
+        ])
+        .output()
+        .expect("command failed");
+    assert!(output.status.success());
+
+    let output = Command::new(&cur_exe)
+        .current_dir(temp_dir.path())
+        .args([
+            "hook",
+            "check-kernel",
+            "--allowed-kernels",
+            "python3",
+            "py.ipynb",
+            "r.ipynb",
+        ])
+        .output()
+        .expect("command failed");
+    assert!(!output.status.success());
+    let stdout = output.stdout.to_str().unwrap();
+    assert!(stdout.contains("r.ipynb uses kernel \"ir\""));
+    assert!(!stdout.contains("py.ipynb"));
+}
+
+#[test]
+fn test_hook_check_filter_installed() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let cur_exe = PathBuf::from(env!("CARGO_BIN_EXE_nbwipers"));
+
+    let git_init_out = Command::new("git")
+        .current_dir(&temp_dir)
+        .args(["init"])
+        .output()
+        .expect("git init failed");
+    assert!(git_init_out.status.success());
+
+    // only non-notebook files are being committed
+    let output = Command::new(&cur_exe)
+        .current_dir(temp_dir.path())
+        .env_remove("NBWIPERS_CHECK_INSTALL_EXIT_ZERO")
+        .env("HOME", temp_dir.path())
+        .env("GIT_CONFIG_NOSYSTEM", "1")
+        .args(["hook", "check-filter-installed", "script.py"])
+        .output()
+        .expect("command failed");
+    assert!(output.status.success());
+
+    let output = Command::new(&cur_exe)
+        .current_dir(temp_dir.path())
+        .env_remove("NBWIPERS_CHECK_INSTALL_EXIT_ZERO")
+        .env("HOME", temp_dir.path())
+        .env("GIT_CONFIG_NOSYSTEM", "1")
+        .args(["hook", "check-filter-installed", "notebook.ipynb"])
+        .output()
+        .expect("command failed");
+    assert!(!output.status.success());
+
+    let install_out = Command::new(&cur_exe)
+        .current_dir(temp_dir.path())
+        .args(["install", "local"])
+        .output()
+        .expect("command failed");
+    assert!(install_out.status.success());
+
+    let output = Command::new(&cur_exe)
+        .current_dir(temp_dir.path())
+        .env_remove("NBWIPERS_CHECK_INSTALL_EXIT_ZERO")
+        .env("HOME", temp_dir.path())
+        .env("GIT_CONFIG_NOSYSTEM", "1")
+        .args(["hook", "check-filter-installed", "notebook.ipynb"])
+        .output()
+        .expect("command failed");
+    assert!(output.status.success());
+}
+
+#[test]
+fn test_check_sarif() {
+    let cur_exe = PathBuf::from(env!("CARGO_BIN_EXE_nbwipers"));
+    let output = Command::new(&cur_exe)
+        .args([
+            "check",
+            "tests/e2e_notebooks/test_metadata.ipynb",
+            "-o",
+            "sarif",
+        ])
+        .output()
+        .expect("command failed");
+    assert!(!output.status.success());
+    let sarif: Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(sarif["version"], "2.1.0");
+    let run = &sarif["runs"][0];
+    assert_eq!(run["tool"]["driver"]["name"], "nbwipers");
+    let rules = run["tool"]["driver"]["rules"].as_array().unwrap();
+    let results = run["results"].as_array().unwrap();
+    assert!(!results.is_empty());
+    for result in results {
+        let rule_index = usize::try_from(result["ruleIndex"].as_u64().unwrap()).unwrap();
+        assert_eq!(rules[rule_index]["id"], result["ruleId"]);
+        assert_eq!(
+            result["locations"][0]["physicalLocation"]["artifactLocation"]["uri"],
+            "tests/e2e_notebooks/test_metadata.ipynb"
+        );
+    }
+    assert!(results.iter().any(|result| result["ruleId"] == "NBW001"));
+
+    // the rules are described with the severity that the settings give them
+    let output = Command::new(&cur_exe)
+        .args([
+            "check",
+            "tests/e2e_notebooks/test_metadata.ipynb",
+            "-o",
+            "sarif",
+            "--warn",
+            "NBW002",
+            "--ignore",
+            "NBW011",
+        ])
+        .output()
+        .expect("command failed");
+    let sarif: Value = serde_json::from_slice(&output.stdout).unwrap();
+    let rules = sarif["runs"][0]["tool"]["driver"]["rules"]
+        .as_array()
+        .unwrap();
+    let level = |id: &str| {
+        rules.iter().find(|rule| rule["id"] == id).unwrap()["defaultConfiguration"]["level"].clone()
+    };
+    assert_eq!(level("NBW001"), "error");
+    assert_eq!(level("NBW002"), "warning");
+    assert_eq!(level("NBW011"), "none");
+
+    let output = Command::new(&cur_exe)
+        .args([
+            "audit",
+            "tests/e2e_notebooks/test_metadata.ipynb",
+            "-o",
+            "sarif",
+        ])
+        .output()
+        .expect("command failed");
+    assert!(!output.status.success());
+}
+
+#[test]
+fn test_check_span_stdin() {
+    let cur_exe = PathBuf::from(env!("CARGO_BIN_EXE_nbwipers"));
+    let contents = fs::read("tests/e2e_notebooks/test_metadata.ipynb").unwrap();
+
+    let mut check_output_cmd = Command::new(&cur_exe)
+        .args(["check", "-", "-o", "json"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("command failed");
+    {
+        let mut check_in = check_output_cmd.stdin.take().expect("Failed to open stdin");
+        check_in
+            .write_all(&contents)
+            .expect("Failed to write to stdin");
+    }
+    let check_output = check_output_cmd.wait_with_output().expect("Command failed");
+    assert!(!check_output.status.success());
+    let results: Value = serde_json::from_slice(&check_output.stdout).unwrap();
+    let count = results
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|result| result["type"] == "ClearCount" && result["cell_number"] == 1)
+        .unwrap();
+    assert_eq!(count["path"], "-");
+    assert_eq!(
+        count["span"],
+        json!({"start": {"line": 12, "column": 4}, "end": {"line": 12, "column": 24}})
+    );
+
+    let mut check_output_cmd = Command::new(&cur_exe)
+        .args(["check", "-"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("command failed");
+    {
+        let mut check_in = check_output_cmd.stdin.take().expect("Failed to open stdin");
+        check_in
+            .write_all(b"{\"cells\": [}")
+            .expect("Failed to write to stdin");
+    }
+    let check_output = check_output_cmd.wait_with_output().expect("Command failed");
+    assert!(!check_output.status.success());
+    assert!(check_output.stdout.to_str().unwrap().starts_with("-:"));
+}
@@ -330,6 +330,16 @@ fn test_metadata_notebook() {
     );
 }
 
+#[test]
+fn test_suppress() {
+    test_expected(
+        "tests/e2e_notebooks/test_suppress.ipynb",
+        "tests/e2e_notebooks/test_suppress.ipynb.expected",
+        &[],
+        "test_suppress",
+    );
+}
+
 #[test]
 fn test_keep_metadata_keys() {
     test_expected(
@@ -749,3 +759,262 @@ fn test_strip_stdin() {
     let clean_output_exclude = output_exclude.wait_with_output().expect("Command failed");
     assert_eq!(&original, clean_output_exclude.stdout.to_str().unwrap());
 }
+
+#[test]
+fn test_canonical_kernelspec() {
+    test_expected(
+        "tests/e2e_notebooks/test_metadata.ipynb",
+        "tests/e2e_notebooks/test_metadata_canonical_kernelspec.ipynb.expected",
+        &[
+            "-c",
+            "tests/e2e_notebooks/test_metadata_canonical_kernelspec.toml",
+        ],
+        "test_metadata_canonical_kernelspec",
+    );
+}
+
+#[test]
+fn test_kernel_policy() {
+    test_config_match(
+        "tests/e2e_notebooks/test_metadata_kernel_policy.toml",
+        &[
+            "--allowed-kernels",
+            "python3",
+            "--required-language",
+            "python",
+            "--required-language-version",
+            ">=3.10",
+        ],
+    );
+    let cur_exe = PathBuf::from(env!("CARGO_BIN_EXE_nbwipers"));
+    let output = Command::new(&cur_exe)
+        .args([
+            "check",
+            "tests/e2e_notebooks/test_metadata.ipynb",
+            "-c",
+            "tests/e2e_notebooks/test_metadata_kernel_policy.toml",
+            "-o",
+            "json",
+        ])
+        .output()
+        .expect("command failed");
+    assert!(!output.status.success());
+    insta::assert_snapshot!(
+        "test_metadata_kernel_policy_json",
+        output.stdout.to_str().unwrap()
+    );
+
+    // notebooks without kernel info satisfy the policy
+    let output = Command::new(&cur_exe)
+        .args([
+            "check",
+            "tests/e2e_notebooks/test_metadata_strip_kernelinfo.ipynb.expected",
+            "-c",
+            "tests/e2e_notebooks/test_metadata_kernel_policy.toml",
+        ])
+        .output()
+        .expect("command failed");
+    assert!(output.status.success());
+}
+
+#[test]
+fn test_check_reporters() {
+    let cur_exe = PathBuf::from(env!("CARGO_BIN_EXE_nbwipers"));
+    for format in ["github", "gitlab", "junit"] {
+        let output = Command::new(&cur_exe)
+            .args([
+                "check",
+                "tests/e2e_notebooks/test_metadata.ipynb",
+                "tests/e2e_notebooks/test_metadata.ipynb.expected",
+                "-o",
+                format,
+            ])
+            .output()
+            .expect("command failed");
+        assert!(!output.status.success());
+        insta::assert_snapshot!(
+            format!("test_check_reporters_{format}"),
+            output.stdout.to_str().unwrap()
+        );
+    }
+}
+
+#[test]
+fn test_rule_severity() {
+    test_config_match(
+        "tests/e2e_notebooks/test_metadata_rule_severity.toml",
+        &["--warn", "NBW002", "--ignore", "NBW011"],
+    );
+    let cur_exe = PathBuf::from(env!("CARGO_BIN_EXE_nbwipers"));
+    let output = Command::new(&cur_exe)
+        .args([
+            "check",
+            "tests/e2e_notebooks/test_metadata.ipynb",
+            "-c",
+            "tests/e2e_notebooks/test_metadata_rule_severity.toml",
+            "-o",
+            "json",
+        ])
+        .output()
+        .expect("command failed");
+    assert!(!output.status.success());
+    insta::assert_snapshot!(
+        "test_metadata_rule_severity_json",
+        output.stdout.to_str().unwrap()
+    );
+
+    // warnings alone do not fail the check
+    let output = Command::new(&cur_exe)
+        .args([
+            "check",
+            "tests/e2e_notebooks/test_metadata.ipynb",
+            "--isolated",
+            "--warn",
+            "NBW00",
+            "--ignore",
+            "NBW011",
+        ])
+        .output()
+        .expect("command failed");
+    assert!(output.status.success());
+    assert!(output.stdout.to_str().unwrap().contains("warning"));
+
+    let output = Command::new(&cur_exe)
+        .args([
+            "check",
+            "tests/e2e_notebooks/test_metadata.ipynb",
+            "--isolated",
+            "--select",
+            "NBW5",
+        ])
+        .output()
+        .expect("command failed");
+    assert!(!output.status.success());
+}
+
+#[test]
+fn test_tag_vocabulary() {
+    test_expected(
+        "tests/e2e_notebooks/test_tag_vocabulary.ipynb",
+        "tests/e2e_notebooks/test_tag_vocabulary.ipynb.expected",
+        &["-c", "tests/e2e_notebooks/test_tag_vocabulary.toml"],
+        "test_tag_vocabulary",
+    );
+    // a cell whose source is cleared is empty, so it is dropped in the same pass
+    test_expected(
+        "tests/e2e_notebooks/test_tag_vocabulary.ipynb",
+        "tests/e2e_notebooks/test_tag_vocabulary_drop_empty.ipynb.expected",
+        &[
+            "-c",
+            "tests/e2e_notebooks/test_tag_vocabulary.toml",
+            "--drop-empty-cells",
+        ],
+        "test_tag_vocabulary_drop_empty",
+    );
+    test_config_match(
+        "tests/e2e_notebooks/test_tag_vocabulary.toml",
+        &[
+            "--keep-output",
+            "--drop-output-tags",
+            "solution",
+            "--keep-count-tags",
+            "example",
+            "--clear-source-tags",
+            "solution",
+            "--drop-tagged-cells",
+            "scratch",
+        ],
+    );
+}
+
+#[test]
+fn test_tag_vocabulary_invalid() {
+    let cur_exe = PathBuf::from(env!("CARGO_BIN_EXE_nbwipers"));
+    let output = Command::new(&cur_exe)
+        .args([
+            "check",
+            "tests/e2e_notebooks/test_tag_vocabulary.ipynb",
+            "--isolated",
+            "--keep-output-tags",
+            "solution",
+            "--drop-output-tags",
+            "solution",
+        ])
+        .output()
+        .expect("command failed");
+    assert!(!output.status.success());
+    assert!(
+        output
+            .stderr
+            .to_str()
+            .unwrap()
+            .contains("The tag `solution` is in both `keep-output-tags` and `drop-output-tags`")
+    );
+
+    let output = Command::new(&cur_exe)
+        .args([
+            "check",
+            "tests/e2e_notebooks/test_tag_vocabulary.ipynb",
+            "--isolated",
+            "--clear-source-tags",
+            "my solution",
+        ])
+        .output()
+        .expect("command failed");
+    assert!(!output.status.success());
+}
+
+#[test]
+fn test_profiles() {
+    let cur_exe = PathBuf::from(env!("CARGO_BIN_EXE_nbwipers"));
+    let output = Command::new(&cur_exe)
+        .args([
+            "show-config",
+            "-c",
+            "tests/e2e_notebooks/test_profiles.toml",
+            "--profile",
+            "docs",
+        ])
+        .output()
+        .expect("command failed");
+    assert!(output.status.success());
+    assert_eq!(
+        output.stdout.to_str().unwrap(),
+        "profile = \"docs\"\nextra_keys = [\"metadata.celltoolbar\"]\ndrop_output = false\nkeep_keys = [\"cell.metadata.collapsed\"]\nextend_exclude = []\n\n"
+    );
+
+    let output = Command::new(&cur_exe)
+        .args([
+            "check",
+            "tests/e2e_notebooks/test_metadata.ipynb",
+            "-c",
+            "tests/e2e_notebooks/test_profiles.toml",
+            "--profile",
+            "docs",
+            "-o",
+            "json",
+        ])
+        .output()
+        .expect("command failed");
+    assert!(!output.status.success());
+    insta::assert_snapshot!(
+        "test_metadata_profile_docs_json",
+        output.stdout.to_str().unwrap()
+    );
+
+    let output = Command::new(&cur_exe)
+        .args([
+            "check",
+            "tests/e2e_notebooks/test_metadata.ipynb",
+            "-c",
+            "tests/e2e_notebooks/test_profiles.toml",
+            "--profile",
+            "publish",
+        ])
+        .output()
+        .expect("command failed");
+    assert!(!output.status.success());
+    assert!(output.stderr.to_str().unwrap().contains(
+        "Unknown profile `publish`. The available profiles are `commit`, `docs`, `student`"
+    ));
+}
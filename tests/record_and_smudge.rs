@@ -81,7 +81,8 @@ fn test_metadata_recorded() {
         kernelspec_info.get(&nb_rel_path),
         Some(&KernelSpecInfo {
             kernelspec,
-            python_version: Some(python_version)
+            python_version: Some(python_version),
+            ..Default::default()
         })
     );
 
@@ -372,3 +373,680 @@ fn test_record_nothing() {
         .expect("record failed");
     assert!(!out.status.success());
 }
+
+#[test]
+fn test_record_keys_restored() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let cur_exe = PathBuf::from(env!("CARGO_BIN_EXE_nbwipers"));
+    let git_init_out = Command::new("git")
+        .current_dir(&temp_dir)
+        .args(["init"])
+        .output()
+        .expect("git init failed");
+    assert!(git_init_out.status.success());
+    fs::write(
+        temp_dir.path().join("pyproject.toml"),
+        "[tool.nbwipers]\nrecord-keys = [\"metadata.widgets\", \"cell.metadata.collapsed\", \"cell.metadata.scrolled\"]\n",
+    )
+    .unwrap();
+    let dirty_nb: RawNotebook = serde_json::from_value(json!({
+        "cells": [
+            {
+                "cell_type": "code",
+                "execution_count": null,
+                "id": "first",
+                "metadata": {"collapsed": true, "scrolled": false},
+                "outputs": [],
+                "source": ["print('hello')"]
+            },
+            {
+                "cell_type": "markdown",
+                "id": "second",
+                "metadata": {},
+                "source": ["# Title"]
+            }
+        ],
+        "metadata": {"widgets": {"application/vnd.jupyter.widget-state+json": {"state": {}}}},
+        "nbformat": 4,
+        "nbformat_minor": 5
+    }))
+    .unwrap();
+    let nb_rel_path = String::from("notebook.ipynb");
+    let nb_path = temp_dir.path().join(&nb_rel_path);
+    write_nb(File::create(&nb_path).unwrap(), &dirty_nb).unwrap();
+
+    let record_out = Command::new(&cur_exe)
+        .current_dir(&temp_dir)
+        .args(["record"])
+        .output()
+        .expect("record failed");
+    assert!(record_out.status.success());
+    let records = read_kernelspec_file(get_kernelspec_file(&temp_dir).unwrap())
+        .unwrap()
+        .unwrap();
+    let record = records.get(&nb_rel_path).unwrap();
+    assert!(record.kernelspec.is_null());
+    assert!(record.metadata.contains_key("metadata.widgets"));
+    assert_eq!(record.cells.len(), 1);
+
+    let strip_out = Command::new(&cur_exe)
+        .current_dir(&temp_dir)
+        .args(["clean", &nb_rel_path])
+        .output()
+        .expect("clean failed");
+    assert!(strip_out.status.success());
+    let nb_bytes = fs::read(&nb_path).unwrap();
+    let clean_nb = serde_json::from_slice::<RawNotebook>(&nb_bytes).unwrap();
+    assert_ne!(dirty_nb, clean_nb);
+
+    let mut smudge_cmd = Command::new(&cur_exe)
+        .current_dir(&temp_dir)
+        .args(["smudge", &nb_rel_path])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("smudge failed");
+    smudge_cmd
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(&nb_bytes)
+        .unwrap();
+    let smudge_out = smudge_cmd.wait_with_output().unwrap();
+    assert!(smudge_out.status.success());
+    let smudged_nb = serde_json::from_slice::<RawNotebook>(&smudge_out.stdout).unwrap();
+    assert_eq!(dirty_nb, smudged_nb);
+}
+
+#[test]
+fn test_outputs_cached_across_checkout() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let cur_exe = PathBuf::from(env!("CARGO_BIN_EXE_nbwipers"));
+    let git = |args: &[&str]| {
+        let output = Command::new("git")
+            .current_dir(&temp_dir)
+            .args([
+                "-c",
+                "user.name=nbwipers",
+                "-c",
+                "user.email=nbwipers@example.com",
+            ])
+            .args(args)
+            .output()
+            .expect("git failed");
+        assert!(output.status.success(), "{output:?}");
+        output.stdout
+    };
+    git(&["init"]);
+    fs::write(
+        temp_dir.path().join("pyproject.toml"),
+        "[tool.nbwipers]\ncache-outputs = true\n",
+    )
+    .unwrap();
+    let install_out = Command::new(&cur_exe)
+        .current_dir(&temp_dir)
+        .args(["install", "local"])
+        .output()
+        .expect("install failed");
+    assert!(install_out.status.success());
+
+    let dirty_nb: RawNotebook = serde_json::from_value(json!({
+        "cells": [
+            {
+                "cell_type": "code",
+                "execution_count": 1,
+                "id": "plot",
+                "metadata": {},
+                "outputs": [{"name": "stdout", "output_type": "stream", "text": ["hello\n"]}],
+                "source": ["print('hello')"]
+            }
+        ],
+        "metadata": {},
+        "nbformat": 4,
+        "nbformat_minor": 5
+    }))
+    .unwrap();
+    let nb_path = temp_dir.path().join("notebook.ipynb");
+    write_nb(File::create(&nb_path).unwrap(), &dirty_nb).unwrap();
+    git(&["add", "."]);
+    git(&["commit", "-m", "add notebook"]);
+
+    let committed = git(&["show", "HEAD:notebook.ipynb"]);
+    let committed_nb = serde_json::from_slice::<RawNotebook>(&committed).unwrap();
+    assert!(
+        committed_nb.cells[0]
+            .as_codecell()
+            .unwrap()
+            .is_clear_outputs()
+    );
+
+    fs::remove_file(&nb_path).unwrap();
+    git(&["checkout", "--", "notebook.ipynb"]);
+    let restored_nb = serde_json::from_slice::<RawNotebook>(&fs::read(&nb_path).unwrap()).unwrap();
+    assert_eq!(restored_nb, dirty_nb);
+
+    let gc_out = Command::new(&cur_exe)
+        .current_dir(&temp_dir)
+        .args(["cache", "gc", "--max-age-days", "0"])
+        .output()
+        .expect("gc failed");
+    assert!(gc_out.status.success());
+    assert!(
+        String::from_utf8(gc_out.stdout)
+            .unwrap()
+            .starts_with("Removed 0 cached outputs")
+    );
+    let clear_out = Command::new(&cur_exe)
+        .current_dir(&temp_dir)
+        .args(["cache", "clear"])
+        .output()
+        .expect("clear failed");
+    assert!(clear_out.status.success());
+    assert!(!temp_dir.path().join(".git/x-nbwipers/outputs").exists());
+}
+
+#[test]
+fn test_record_on_clean() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let cur_exe = PathBuf::from(env!("CARGO_BIN_EXE_nbwipers"));
+    let git = |args: &[&str]| {
+        let output = Command::new("git")
+            .current_dir(&temp_dir)
+            .args([
+                "-c",
+                "user.name=nbwipers",
+                "-c",
+                "user.email=nbwipers@example.com",
+            ])
+            .args(args)
+            .output()
+            .expect("git failed");
+        assert!(output.status.success(), "{output:?}");
+        output.stdout
+    };
+    git(&["init"]);
+    fs::write(
+        temp_dir.path().join("pyproject.toml"),
+        "[tool.nbwipers]\nstrip-kernel-info = true\nrecord-on-clean = true\n",
+    )
+    .unwrap();
+    let install_out = Command::new(&cur_exe)
+        .current_dir(&temp_dir)
+        .args(["install", "local"])
+        .output()
+        .expect("install failed");
+    assert!(install_out.status.success());
+
+    let kernelspec = json!({"display_name": "Python 3", "language": "python", "name": "python3"});
+    let names: Vec<String> = (0..8).map(|i| format!("notebook{i}.ipynb")).collect();
+    let dirty_nb = RawNotebook {
+        metadata: json!({
+            "kernelspec": kernelspec,
+            "language_info": {"name": "python", "version": "3.12.4"}
+        }),
+        ..Default::default()
+    };
+    for name in &names {
+        write_nb(File::create(temp_dir.path().join(name)).unwrap(), &dirty_nb).unwrap();
+    }
+
+    // run the clean filters concurrently, as git may do
+    let children: Vec<_> = names
+        .iter()
+        .map(|name| {
+            let mut child = Command::new(&cur_exe)
+                .current_dir(&temp_dir)
+                .args([
+                    "clean",
+                    "-",
+                    "--stdin-file-name",
+                    name,
+                    "--respect-exclusions",
+                ])
+                .stdin(Stdio::piped())
+                .stdout(Stdio::piped())
+                .spawn()
+                .expect("clean failed");
+            child
+                .stdin
+                .take()
+                .unwrap()
+                .write_all(&fs::read(temp_dir.path().join(name)).unwrap())
+                .unwrap();
+            child
+        })
+        .collect();
+    for child in children {
+        let output = child.wait_with_output().unwrap();
+        assert!(output.status.success());
+        let clean_nb = serde_json::from_slice::<RawNotebook>(&output.stdout).unwrap();
+        assert!(clean_nb.metadata.get("kernelspec").is_none());
+    }
+    let records = read_kernelspec_file(get_kernelspec_file(&temp_dir).unwrap())
+        .unwrap()
+        .unwrap();
+    for name in &names {
+        assert_eq!(
+            records.get(name),
+            Some(&KernelSpecInfo {
+                kernelspec: kernelspec.clone(),
+                python_version: Some("3.12.4".to_string()),
+                ..Default::default()
+            })
+        );
+    }
+
+    // the store survives a round trip through git
+    git(&["add", "."]);
+    git(&["commit", "-m", "add notebooks"]);
+    fs::remove_file(temp_dir.path().join(&names[0])).unwrap();
+    git(&["checkout", "--", &names[0]]);
+    let restored_nb =
+        serde_json::from_slice::<RawNotebook>(&fs::read(temp_dir.path().join(&names[0])).unwrap())
+            .unwrap();
+    assert_eq!(restored_nb.metadata.get("kernelspec"), Some(&kernelspec));
+}
+
+#[test]
+fn test_smudge_corrupt_store() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let cur_exe = PathBuf::from(env!("CARGO_BIN_EXE_nbwipers"));
+    let git_init_out = Command::new("git")
+        .current_dir(&temp_dir)
+        .args(["init"])
+        .output()
+        .expect("git init failed");
+    assert!(git_init_out.status.success());
+    fs::write(
+        get_kernelspec_file(&temp_dir).unwrap(),
+        "{\"notebook.ipynb\": ",
+    )
+    .unwrap();
+    let verbatim_bytes = Vec::from(b"Hello world!");
+
+    let mut check_smudge_output = Command::new(&cur_exe)
+        .current_dir(&temp_dir)
+        .args(["smudge", "notebook.ipynb"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("smudge failed");
+    {
+        let mut check_in = check_smudge_output.stdin.take().unwrap();
+        check_in.write_all(&verbatim_bytes).unwrap()
+    }
+    let smudge_out = check_smudge_output.wait_with_output().unwrap();
+    assert!(smudge_out.status.success());
+    assert_eq!(smudge_out.stdout, verbatim_bytes);
+    assert!(
+        String::from_utf8(smudge_out.stderr)
+            .unwrap()
+            .contains("Could not read kernelspec file")
+    );
+}
+
+#[test]
+fn test_record_follows_moves() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let cur_exe = PathBuf::from(env!("CARGO_BIN_EXE_nbwipers"));
+    let git_init_out = Command::new("git")
+        .current_dir(&temp_dir)
+        .args(["init"])
+        .output()
+        .expect("git init failed");
+    assert!(git_init_out.status.success());
+    let kernelspec = json!({
+        "name": "python3",
+        "display_name": "Python 3"
+    });
+    let dirty_nb: RawNotebook = serde_json::from_value(json!({
+        "cells": [
+            {
+                "cell_type": "code",
+                "execution_count": null,
+                "id": "a",
+                "metadata": {},
+                "outputs": [],
+                "source": ["print(1)"]
+            }
+        ],
+        "metadata": {"kernelspec": kernelspec},
+        "nbformat": 4,
+        "nbformat_minor": 5
+    }))
+    .unwrap();
+    write_nb(
+        File::create(temp_dir.path().join("nb.ipynb")).unwrap(),
+        &dirty_nb,
+    )
+    .unwrap();
+    write_nb(
+        File::create(temp_dir.path().join("other.ipynb")).unwrap(),
+        &RawNotebook {
+            metadata: json!({"kernelspec": kernelspec}),
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    let record_out = Command::new(&cur_exe)
+        .current_dir(&temp_dir)
+        .args(["record"])
+        .output()
+        .expect("record failed");
+    assert!(record_out.status.success());
+
+    fs::rename(
+        temp_dir.path().join("nb.ipynb"),
+        temp_dir.path().join("renamed.ipynb"),
+    )
+    .unwrap();
+    fs::remove_file(temp_dir.path().join("other.ipynb")).unwrap();
+    let prune_out = Command::new(&cur_exe)
+        .current_dir(&temp_dir)
+        .args(["record", "--prune"])
+        .output()
+        .expect("record failed");
+    assert!(prune_out.status.success());
+    let prune_stdout = String::from_utf8(prune_out.stdout).unwrap();
+    assert!(prune_stdout.contains("Moved nb.ipynb to renamed.ipynb"));
+    assert!(prune_stdout.contains("Removed other.ipynb"));
+
+    let list_out = Command::new(&cur_exe)
+        .current_dir(&temp_dir)
+        .args(["record", "--list", "-o", "json"])
+        .output()
+        .expect("record failed");
+    assert!(list_out.status.success());
+    let listed: serde_json::Value = serde_json::from_slice(&list_out.stdout).unwrap();
+    let listed = listed.as_object().unwrap();
+    assert_eq!(listed.len(), 1);
+    assert_eq!(listed["renamed.ipynb"]["kernelspec"], kernelspec);
+
+    let show_out = Command::new(&cur_exe)
+        .current_dir(&temp_dir)
+        .args(["record", "--show", "renamed.ipynb"])
+        .output()
+        .expect("record failed");
+    assert!(show_out.status.success());
+    assert!(
+        String::from_utf8(show_out.stdout)
+            .unwrap()
+            .contains("kernelspec: ")
+    );
+    let show_missing_out = Command::new(&cur_exe)
+        .current_dir(&temp_dir)
+        .args(["record", "--show", "nb.ipynb"])
+        .output()
+        .expect("record failed");
+    assert!(!show_missing_out.status.success());
+
+    // without pruning, the smudge filter finds the record of a moved notebook by its sources
+    fs::rename(
+        temp_dir.path().join("renamed.ipynb"),
+        temp_dir.path().join("moved.ipynb"),
+    )
+    .unwrap();
+    let mut clean_nb = dirty_nb.clone();
+    clean_nb.metadata = json!({});
+    let mut nb_bytes = Vec::new();
+    write_nb(&mut nb_bytes, &clean_nb).unwrap();
+    let mut check_smudge_output = Command::new(&cur_exe)
+        .current_dir(&temp_dir)
+        .args(["smudge", "moved.ipynb"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("smudge failed");
+    {
+        let mut check_in = check_smudge_output.stdin.take().unwrap();
+        check_in.write_all(&nb_bytes).unwrap()
+    }
+    let smudge_out = check_smudge_output.wait_with_output().unwrap();
+    assert!(smudge_out.status.success());
+    let smudged_nb = serde_json::from_slice::<RawNotebook>(&smudge_out.stdout).unwrap();
+    assert_eq!(smudged_nb, dirty_nb);
+    let kernelspec_info = read_kernelspec_file(get_kernelspec_file(&temp_dir).unwrap())
+        .unwrap()
+        .unwrap();
+    assert!(kernelspec_info.contains_key("moved.ipynb"));
+    assert!(!kernelspec_info.contains_key("renamed.ipynb"));
+}
+
+#[test]
+fn test_smudge_in_linked_worktree() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let worktree_parent = tempfile::tempdir().unwrap();
+    let worktree_dir = worktree_parent.path().join("worktree");
+    let cur_exe = PathBuf::from(env!("CARGO_BIN_EXE_nbwipers"));
+    let git = |args: &[&str]| {
+        let output = Command::new("git")
+            .current_dir(&temp_dir)
+            .args([
+                "-c",
+                "user.name=nbwipers",
+                "-c",
+                "user.email=nbwipers@example.com",
+            ])
+            .args(args)
+            .output()
+            .expect("git failed");
+        assert!(output.status.success(), "{output:?}");
+    };
+    git(&["init"]);
+    let kernelspec = json!({
+        "name": "python3",
+        "display_name": "Python 3"
+    });
+    let dirty_nb = RawNotebook {
+        metadata: json!({"kernelspec": kernelspec}),
+        ..Default::default()
+    };
+    write_nb(
+        File::create(temp_dir.path().join("notebook.ipynb")).unwrap(),
+        &dirty_nb,
+    )
+    .unwrap();
+    git(&["add", "."]);
+    git(&["commit", "-m", "add notebook"]);
+    git(&["worktree", "add", worktree_dir.to_str().unwrap()]);
+    assert!(worktree_dir.join(".git").is_file());
+
+    let record_out = Command::new(&cur_exe)
+        .current_dir(&worktree_dir)
+        .args(["record"])
+        .output()
+        .expect("record failed");
+    assert!(record_out.status.success(), "{record_out:?}");
+    // the store is shared by all worktrees of the repository
+    let kernelspec_info = read_kernelspec_file(get_kernelspec_file(&temp_dir).unwrap())
+        .unwrap()
+        .unwrap();
+    assert!(kernelspec_info.contains_key("notebook.ipynb"));
+
+    let mut nb_bytes = Vec::new();
+    write_nb(&mut nb_bytes, &RawNotebook::default()).unwrap();
+    let smudge = |current_dir: &std::path::Path, git_dir: Option<&std::path::Path>| {
+        let mut command = Command::new(&cur_exe);
+        command
+            .current_dir(current_dir)
+            .args(["smudge", "notebook.ipynb"])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
+        if let Some(git_dir) = git_dir {
+            command.env("GIT_DIR", git_dir);
+        }
+        let mut child = command.spawn().expect("smudge failed");
+        {
+            let mut check_in = child.stdin.take().unwrap();
+            check_in.write_all(&nb_bytes).unwrap()
+        }
+        let smudge_out = child.wait_with_output().unwrap();
+        assert!(smudge_out.status.success(), "{smudge_out:?}");
+        serde_json::from_slice::<RawNotebook>(&smudge_out.stdout).unwrap()
+    };
+    assert_eq!(smudge(&worktree_dir, None), dirty_nb);
+
+    let outside_dir = tempfile::tempdir().unwrap();
+    assert_eq!(
+        smudge(outside_dir.path(), Some(&temp_dir.path().join(".git"))),
+        dirty_nb
+    );
+}
+
+#[test]
+fn test_smudge_defaults() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let cur_exe = PathBuf::from(env!("CARGO_BIN_EXE_nbwipers"));
+    let git_init_out = Command::new("git")
+        .current_dir(&temp_dir)
+        .args(["init"])
+        .output()
+        .expect("git init failed");
+    assert!(git_init_out.status.success());
+    fs::write(
+        temp_dir.path().join("pyproject.toml"),
+        r#"
+[tool.nbwipers]
+strip-kernel-info = true
+
+[[tool.nbwipers.smudge.defaults]]
+path = "r/**"
+kernelspec = { name = "ir", display_name = "R", language = "R" }
+language-info = { name = "R", version = "4.4.1" }
+
+[[tool.nbwipers.smudge.defaults]]
+path = "*.ipynb"
+kernelspec = { name = "python3", display_name = "Python 3", language = "python" }
+"#,
+    )
+    .unwrap();
+    let show_config_out = Command::new(&cur_exe)
+        .current_dir(&temp_dir)
+        .args(["show-config", "--show-all"])
+        .output()
+        .expect("show-config failed");
+    assert!(show_config_out.status.success(), "{show_config_out:?}");
+
+    let smudge = |path: &str, nb: &RawNotebook| {
+        let mut nb_bytes = Vec::new();
+        write_nb(&mut nb_bytes, nb).unwrap();
+        let mut child = Command::new(&cur_exe)
+            .current_dir(&temp_dir)
+            .args(["smudge", path])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .expect("smudge failed");
+        {
+            let mut check_in = child.stdin.take().unwrap();
+            check_in.write_all(&nb_bytes).unwrap()
+        }
+        let smudge_out = child.wait_with_output().unwrap();
+        assert!(smudge_out.status.success(), "{smudge_out:?}");
+        serde_json::from_slice::<RawNotebook>(&smudge_out.stdout).unwrap()
+    };
+    let clean_nb = RawNotebook {
+        metadata: json!({"language_info": {"name": "R"}}),
+        ..Default::default()
+    };
+
+    let r_nb = smudge("r/analysis.ipynb", &clean_nb);
+    assert_eq!(r_nb.metadata["kernelspec"]["name"], "ir");
+    assert_eq!(
+        r_nb.metadata["language_info"],
+        json!({"name": "R", "version": "4.4.1"})
+    );
+    let python_nb = smudge("notebooks/analysis.ipynb", &clean_nb);
+    assert_eq!(python_nb.metadata["kernelspec"]["name"], "python3");
+
+    // a local record takes precedence over the committed defaults
+    let recorded_nb = RawNotebook {
+        metadata: json!({"kernelspec": {"name": "ir-4.3", "display_name": "R 4.3"}}),
+        ..Default::default()
+    };
+    fs::create_dir_all(temp_dir.path().join("r")).unwrap();
+    write_nb(
+        File::create(temp_dir.path().join("r/analysis.ipynb")).unwrap(),
+        &recorded_nb,
+    )
+    .unwrap();
+    let record_out = Command::new(&cur_exe)
+        .current_dir(&temp_dir)
+        .args(["record"])
+        .output()
+        .expect("record failed");
+    assert!(record_out.status.success());
+    let r_nb = smudge("r/analysis.ipynb", &clean_nb);
+    assert_eq!(r_nb.metadata["kernelspec"]["name"], "ir-4.3");
+}
+
+#[test]
+fn test_record_from_subdirectory() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let sub_dir = temp_dir.path().join("sub");
+    let cur_exe = PathBuf::from(env!("CARGO_BIN_EXE_nbwipers"));
+    let git = |args: &[&str]| {
+        let output = Command::new("git")
+            .current_dir(&temp_dir)
+            .args([
+                "-c",
+                "user.name=nbwipers",
+                "-c",
+                "user.email=nbwipers@example.com",
+            ])
+            .args(args)
+            .output()
+            .expect("git failed");
+        assert!(output.status.success(), "{output:?}");
+    };
+    let nbwipers = |args: &[&str]| {
+        let output = Command::new(&cur_exe)
+            .current_dir(&sub_dir)
+            .args(args)
+            .output()
+            .expect("nbwipers failed");
+        assert!(output.status.success(), "{output:?}");
+        String::from_utf8(output.stdout).unwrap()
+    };
+    git(&["init"]);
+    fs::write(
+        temp_dir.path().join("pyproject.toml"),
+        "[tool.nbwipers]\nstrip-kernel-info = true\n",
+    )
+    .unwrap();
+    fs::create_dir(&sub_dir).unwrap();
+    nbwipers(&["install", "local"]);
+    let kernelspec = json!({"display_name": "Python 3", "language": "python", "name": "python3"});
+    let dirty_nb = RawNotebook {
+        metadata: json!({"kernelspec": kernelspec}),
+        ..Default::default()
+    };
+    write_nb(File::create(sub_dir.join("a.ipynb")).unwrap(), &dirty_nb).unwrap();
+
+    // keys are relative to the root of the work tree, wherever record is run
+    nbwipers(&["record"]);
+    let records = read_kernelspec_file(get_kernelspec_file(&temp_dir).unwrap())
+        .unwrap()
+        .unwrap();
+    assert_eq!(records.keys().collect::<Vec<_>>(), ["sub/a.ipynb"]);
+    assert!(nbwipers(&["record", "--show", "a.ipynb"]).starts_with("sub/a.ipynb\n"));
+
+    git(&["add", "."]);
+    git(&["commit", "-m", "add notebook"]);
+    fs::remove_file(sub_dir.join("a.ipynb")).unwrap();
+    git(&["checkout", "--", "sub/a.ipynb"]);
+    let restored_nb =
+        serde_json::from_slice::<RawNotebook>(&fs::read(sub_dir.join("a.ipynb")).unwrap()).unwrap();
+    assert_eq!(restored_nb.metadata.get("kernelspec"), Some(&kernelspec));
+
+    nbwipers(&["record", "--remove", "a.ipynb"]);
+    let records = read_kernelspec_file(get_kernelspec_file(&temp_dir).unwrap())
+        .unwrap()
+        .unwrap();
+    assert!(records.is_empty());
+}
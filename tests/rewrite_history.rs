@@ -0,0 +1,122 @@
+use std::{fs::File, path::Path, path::PathBuf, process::Command};
+
+use nbwipers::{
+    schema::{Cell, CodeCell, RawNotebook, SourceValue},
+    strip::write_nb,
+};
+use serde_json::json;
+
+fn git<P: AsRef<Path>>(dir: P, args: &[&str]) -> Vec<u8> {
+    let output = Command::new("git")
+        .current_dir(dir)
+        .args([
+            "-c",
+            "user.name=nbwipers",
+            "-c",
+            "user.email=nbwipers@example.com",
+        ])
+        .args(args)
+        .output()
+        .expect("git failed");
+    assert!(output.status.success());
+    output.stdout
+}
+
+fn dirty_nb() -> RawNotebook {
+    RawNotebook {
+        cells: vec![Cell::Code(CodeCell {
+            execution_count: Some(1),
+            id: Some("a".into()),
+            metadata: json!({}),
+            outputs: vec![json!({
+                "name": "stdout",
+                "output_type": "stream",
+                "text": ["hello world\n"]
+            })],
+            source: SourceValue::String("print('hello world')".into()),
+        })],
+        metadata: json!({}),
+        ..Default::default()
+    }
+}
+
+fn setup_repo() -> tempfile::TempDir {
+    let temp_dir = tempfile::tempdir().unwrap();
+    git(&temp_dir, &["init", "-b", "main"]);
+    write_nb(
+        File::create(temp_dir.path().join("notebook.ipynb")).unwrap(),
+        &dirty_nb(),
+    )
+    .unwrap();
+    std::fs::write(temp_dir.path().join("script.py"), "print('hello world')\n").unwrap();
+    git(&temp_dir, &["add", "."]);
+    git(&temp_dir, &["commit", "-m", "add notebook"]);
+    temp_dir
+}
+
+#[test]
+fn test_rewrite_history_dry_run() {
+    let temp_dir = setup_repo();
+    let cur_exe = PathBuf::from(env!("CARGO_BIN_EXE_nbwipers"));
+    let head_before = git(&temp_dir, &["rev-parse", "HEAD"]);
+
+    let output = Command::new(&cur_exe)
+        .current_dir(&temp_dir)
+        .args(["rewrite-history", "--dry-run"])
+        .output()
+        .expect("command failed");
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("notebook.ipynb"));
+    assert!(stdout.contains("Would save"));
+    assert_eq!(git(&temp_dir, &["rev-parse", "HEAD"]), head_before);
+}
+
+#[test]
+fn test_rewrite_history() {
+    let temp_dir = setup_repo();
+    let cur_exe = PathBuf::from(env!("CARGO_BIN_EXE_nbwipers"));
+    let script_before = git(&temp_dir, &["rev-parse", "HEAD:script.py"]);
+
+    let output = Command::new(&cur_exe)
+        .current_dir(&temp_dir)
+        .args(["rewrite-history"])
+        .output()
+        .expect("command failed");
+    assert!(output.status.success());
+
+    let committed = git(&temp_dir, &["show", "HEAD:notebook.ipynb"]);
+    let committed_nb: RawNotebook = serde_json::from_slice(&committed).unwrap();
+    let committed_cell = committed_nb.cells[0].as_codecell().unwrap();
+    assert!(committed_cell.is_clear_outputs());
+    assert!(committed_cell.is_clear_exec_count());
+    assert_eq!(
+        git(&temp_dir, &["rev-parse", "HEAD:script.py"]),
+        script_before
+    );
+    assert_eq!(git(&temp_dir, &["rev-list", "--count", "HEAD"]), b"1\n");
+}
+
+#[test]
+fn test_rewrite_history_failure_keeps_refs() {
+    let temp_dir = setup_repo();
+    let cur_exe = PathBuf::from(env!("CARGO_BIN_EXE_nbwipers"));
+    // a blob of its own, as blobs are only read once
+    std::fs::write(temp_dir.path().join("later.ipynb"), b"{}").unwrap();
+    git(&temp_dir, &["add", "."]);
+    git(&temp_dir, &["commit", "-m", "add later notebook"]);
+    let head_before = git(&temp_dir, &["rev-parse", "HEAD"]);
+
+    // fast-export does not read blobs, so processing only fails once it reaches the second commit
+    let blob = String::from_utf8(git(&temp_dir, &["rev-parse", "HEAD:later.ipynb"])).unwrap();
+    let (dir, file) = blob.trim().split_at(2);
+    std::fs::remove_file(temp_dir.path().join(".git/objects").join(dir).join(file)).unwrap();
+
+    let output = Command::new(&cur_exe)
+        .current_dir(&temp_dir)
+        .args(["rewrite-history"])
+        .output()
+        .expect("command failed");
+    assert!(!output.status.success());
+    assert_eq!(git(&temp_dir, &["rev-parse", "HEAD"]), head_before);
+}
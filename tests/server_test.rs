@@ -0,0 +1,176 @@
+use std::{
+    fs,
+    io::{BufRead, BufReader, Read, Write},
+    path::PathBuf,
+    process::{ChildStdin, ChildStdout, Command, Stdio},
+};
+
+use serde_json::{Value, json};
+
+fn send(stdin: &mut ChildStdin, message: &Value) {
+    let body = message.to_string();
+    write!(stdin, "Content-Length: {}\r\n\r\n{body}", body.len()).unwrap();
+    stdin.flush().unwrap();
+}
+
+fn receive(stdout: &mut BufReader<ChildStdout>) -> Value {
+    let mut length = 0;
+    loop {
+        let mut header = String::new();
+        stdout.read_line(&mut header).unwrap();
+        let header = header.trim();
+        if header.is_empty() {
+            break;
+        }
+        if let Some(value) = header.strip_prefix("Content-Length: ") {
+            length = value.parse().unwrap();
+        }
+    }
+    let mut body = vec![0; length];
+    stdout.read_exact(&mut body).unwrap();
+    serde_json::from_slice(&body).unwrap()
+}
+
+/// Receive messages until one matches `pred`, skipping the others.
+fn receive_until(stdout: &mut BufReader<ChildStdout>, pred: impl Fn(&Value) -> bool) -> Value {
+    loop {
+        let message = receive(stdout);
+        if pred(&message) {
+            return message;
+        }
+    }
+}
+
+fn diagnostics_for(stdout: &mut BufReader<ChildStdout>, uri: &str) -> Vec<Value> {
+    let message = receive_until(stdout, |message| {
+        message["method"] == "textDocument/publishDiagnostics" && message["params"]["uri"] == uri
+    });
+    message["params"]["diagnostics"].as_array().unwrap().clone()
+}
+
+#[test]
+fn test_server() {
+    let cur_exe = PathBuf::from(env!("CARGO_BIN_EXE_nbwipers"));
+    let mut child = Command::new(&cur_exe)
+        .args(["server", "--isolated"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("command failed");
+    let mut stdin = child.stdin.take().unwrap();
+    let mut stdout = BufReader::new(child.stdout.take().unwrap());
+
+    send(
+        &mut stdin,
+        &json!({"jsonrpc": "2.0", "id": 1, "method": "initialize", "params": {"capabilities": {}}}),
+    );
+    let response = receive_until(&mut stdout, |message| message["id"] == 1);
+    assert_eq!(response["result"]["serverInfo"]["name"], "nbwipers");
+    assert!(response["result"]["capabilities"]["codeActionProvider"].is_object());
+    send(
+        &mut stdin,
+        &json!({"jsonrpc": "2.0", "method": "initialized", "params": {}}),
+    );
+
+    // a notebook opened as the text of an `.ipynb` file
+    let nb_path = fs::canonicalize("tests/e2e_notebooks/test_metadata.ipynb").unwrap();
+    let uri = format!("file://{}", nb_path.to_str().unwrap());
+    let text = fs::read_to_string(&nb_path).unwrap();
+    send(
+        &mut stdin,
+        &json!({"jsonrpc": "2.0", "method": "textDocument/didOpen", "params": {
+            "textDocument": {"uri": uri, "languageId": "json", "version": 1, "text": text}
+        }}),
+    );
+    let diagnostics = diagnostics_for(&mut stdout, &uri);
+    let output = diagnostics
+        .iter()
+        .find(|diagnostic| diagnostic["code"] == "NBW001")
+        .unwrap();
+    assert_eq!(output["source"], "nbwipers");
+    assert_eq!(
+        output["range"]["start"],
+        json!({"line": 61, "character": 3})
+    );
+
+    send(
+        &mut stdin,
+        &json!({"jsonrpc": "2.0", "id": 2, "method": "textDocument/codeAction", "params": {
+            "textDocument": {"uri": uri},
+            "range": output["range"],
+            "context": {"diagnostics": [output]}
+        }}),
+    );
+    let response = receive_until(&mut stdout, |message| message["id"] == 2);
+    let actions = response["result"].as_array().unwrap();
+    let clear_output = actions
+        .iter()
+        .find(|action| action["title"] == "Clear output of cell 3")
+        .unwrap();
+    assert_eq!(clear_output["kind"], "quickfix");
+    let new_text = clear_output["edit"]["changes"][&uri][0]["newText"]
+        .as_str()
+        .unwrap();
+    let fixed: Value = serde_json::from_str(new_text).unwrap();
+    assert_eq!(fixed["cells"][3]["outputs"], json!([]));
+    // only the output is cleared
+    assert_eq!(fixed["cells"][3]["execution_count"], 3);
+    assert!(
+        actions
+            .iter()
+            .any(|action| action["kind"] == "source.fixAll.nbwipers")
+    );
+
+    send(
+        &mut stdin,
+        &json!({"jsonrpc": "2.0", "method": "textDocument/didChange", "params": {
+            "textDocument": {"uri": uri, "version": 2},
+            "contentChanges": [{"text": new_text}]
+        }}),
+    );
+    let diagnostics = diagnostics_for(&mut stdout, &uri);
+    assert!(!diagnostics.is_empty());
+    assert!(
+        diagnostics
+            .iter()
+            .all(|diagnostic| diagnostic["code"] != "NBW001")
+    );
+
+    // a notebook document, whose cells are sent separately
+    let notebook_uri = "file:///notebooks/analysis.ipynb";
+    let cell_uri = "vscode-notebook-cell:/notebooks/analysis.ipynb#cell0";
+    send(
+        &mut stdin,
+        &json!({"jsonrpc": "2.0", "method": "notebookDocument/didOpen", "params": {
+            "notebookDocument": {
+                "uri": notebook_uri,
+                "notebookType": "jupyter-notebook",
+                "version": 1,
+                "metadata": {"metadata": {"widgets": {}}, "nbformat": 4, "nbformat_minor": 5},
+                "cells": [{"kind": 2, "document": cell_uri, "executionSummary": {"executionOrder": 1}}]
+            },
+            "cellTextDocuments": [
+                {"uri": cell_uri, "languageId": "python", "version": 1, "text": "print('hello')\n"}
+            ]
+        }}),
+    );
+    let diagnostics = diagnostics_for(&mut stdout, cell_uri);
+    let codes: Vec<&str> = diagnostics
+        .iter()
+        .map(|diagnostic| diagnostic["code"].as_str().unwrap())
+        .collect();
+    assert!(codes.contains(&"NBW002"));
+    assert!(codes.contains(&"NBW010"));
+    assert_eq!(
+        diagnostics[0]["range"]["end"],
+        json!({"line": 0, "character": 14})
+    );
+
+    send(
+        &mut stdin,
+        &json!({"jsonrpc": "2.0", "id": 3, "method": "shutdown"}),
+    );
+    receive_until(&mut stdout, |message| message["id"] == 3);
+    send(&mut stdin, &json!({"jsonrpc": "2.0", "method": "exit"}));
+    assert!(child.wait().unwrap().success());
+}